@@ -0,0 +1,430 @@
+use crate::db::{self, DB_FILE, LAST_BACKUP_AT, VAULT_ENTRIES};
+use crate::AppState;
+use chrono::{DateTime, Duration, Local, Utc};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use tauri::{Emitter, Manager};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+/// Sent with the error message when a scheduled backup fails; the scheduler
+/// tries again at its next check.
+pub const AUTO_BACKUP_FAILED_EVENT: &str = "auto-backup-failed";
+/// Sent with the error message when emptying expired trash fails.
+pub const TRASH_PURGE_FAILED_EVENT: &str = "trash-purge-failed";
+const FORMAT: &str = "promptvault-backup";
+const FORMAT_VERSION: u32 = 1;
+/// Prefix of the archives written by the scheduler; only these are pruned.
+const AUTO_PREFIX: &str = "auto-";
+/// Backups started over because images changed under them, at most.
+const BACKUP_ATTEMPTS: usize = 3;
+/// How often the scheduler checks whether a backup is due.
+const SCHEDULE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+/// `manifest.json` at the root of every backup archive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub format: String,
+    pub format_version: u32,
+    pub app_version: String,
+    /// `user_version` of the database in the archive.
+    pub schema_version: i32,
+    pub created_at: String,
+    pub prompt_count: i64,
+    pub files: Vec<BackupFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupFile {
+    /// Path inside the archive, `/`-separated.
+    pub path: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BackupInfo {
+    pub path: String,
+    pub size: u64,
+    pub manifest: BackupManifest,
+}
+
+pub fn backups_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("backups")
+}
+
+/// Why an archive couldn't be written.
+enum ArchiveError {
+    /// An image the snapshot refers to was deleted before it was zipped.
+    Changed(String),
+    Failed(String),
+}
+
+impl From<String> for ArchiveError {
+    fn from(e: String) -> Self {
+        ArchiveError::Failed(e)
+    }
+}
+
+/// Backs up the whole vault to `dest`. The database lock is only held while
+/// SQLite copies the database and the image folders are listed; images are
+/// zipped afterwards. If one the snapshot refers to is deleted in between,
+/// the backup starts over, so the archive never points at missing images.
+pub fn backup_vault(state: &AppState, dest: &Path) -> Result<BackupInfo, String> {
+    let data_dir = state.db.lock().map_err(|e| e.to_string())?.get_data_dir().clone();
+    let snapshot = backups_dir(&data_dir).join(format!(".snapshot-{}.db", uuid::Uuid::new_v4()));
+    fs::create_dir_all(backups_dir(&data_dir)).map_err(|e| e.to_string())?;
+
+    let mut attempt = 1;
+    let manifest = loop {
+        let result = {
+            let db = state.db.lock().map_err(|e| e.to_string())?;
+            db.snapshot_database(&snapshot)
+                .map(|_| vault_files(&data_dir))
+                .map_err(|e| ArchiveError::from(e.to_string()))
+        }
+        .and_then(|files| write_archive(&data_dir, &snapshot, &files, dest));
+        let _ = fs::remove_file(&snapshot);
+
+        match result {
+            Ok(manifest) => break manifest,
+            Err(ArchiveError::Changed(_)) if attempt < BACKUP_ATTEMPTS => attempt += 1,
+            Err(ArchiveError::Changed(path)) => {
+                return Err(format!("{} was removed while backing up; try again", path))
+            }
+            Err(ArchiveError::Failed(e)) => return Err(e),
+        }
+    };
+    let size = fs::metadata(dest).map(|m| m.len()).unwrap_or(0);
+    Ok(BackupInfo {
+        path: dest.to_string_lossy().to_string(),
+        size,
+        manifest,
+    })
+}
+
+/// Relative paths of the files in the image folders of `data_dir`.
+fn vault_files(data_dir: &Path) -> Vec<String> {
+    let mut paths = Vec::new();
+    for folder in VAULT_ENTRIES.iter().filter(|entry| **entry != DB_FILE) {
+        collect_files(&data_dir.join(folder), folder, &mut paths);
+    }
+    paths
+}
+
+/// Image and thumbnail paths a database refers to.
+fn referenced_files(conn: &Connection) -> rusqlite::Result<HashSet<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT image_path FROM prompt_images
+         UNION SELECT thumbnail_path FROM prompt_images
+         UNION SELECT image_path FROM prompts
+         UNION SELECT thumbnail_path FROM prompts",
+    )?;
+    let rows = stmt.query_map([], |row| row.get::<_, Option<String>>(0))?;
+    rows.filter_map(|row| row.transpose()).collect()
+}
+
+/// Writes `snapshot` plus `files` from `data_dir` to a zip at `dest`. The
+/// archive is built next to `dest` and renamed into place, so a failed
+/// backup never leaves a truncated file behind.
+fn write_archive(
+    data_dir: &Path,
+    snapshot: &Path,
+    files: &[String],
+    dest: &Path,
+) -> Result<BackupManifest, ArchiveError> {
+    let conn = Connection::open(snapshot).map_err(|e| e.to_string())?;
+    let referenced = referenced_files(&conn).map_err(|e| e.to_string())?;
+    let schema_version: i32 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    let prompt_count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM prompts", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    drop(conn);
+
+    let partial = dest.with_extension("zip.partial");
+    let result = (|| -> Result<BackupManifest, ArchiveError> {
+        let mut zip = ZipWriter::new(File::create(&partial).map_err(|e| e.to_string())?);
+        let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        // Images are already compressed.
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+
+        let mut entries = Vec::new();
+        zip.start_file(DB_FILE, deflated).map_err(|e| e.to_string())?;
+        let size = io::copy(&mut File::open(snapshot).map_err(|e| e.to_string())?, &mut zip)
+            .map_err(|e| e.to_string())?;
+        entries.push(BackupFile {
+            path: DB_FILE.to_string(),
+            size,
+        });
+
+        for path in files {
+            let Ok(mut file) = File::open(data_dir.join(path)) else {
+                // Files nothing refers to, such as leftovers of a replaced
+                // image, may go; the database needs the others.
+                if referenced.contains(path) {
+                    return Err(ArchiveError::Changed(path.clone()));
+                }
+                continue;
+            };
+            zip.start_file(path.as_str(), stored).map_err(|e| e.to_string())?;
+            let size = io::copy(&mut file, &mut zip).map_err(|e| e.to_string())?;
+            entries.push(BackupFile {
+                path: path.clone(),
+                size,
+            });
+        }
+
+        let manifest = BackupManifest {
+            format: FORMAT.to_string(),
+            format_version: FORMAT_VERSION,
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            schema_version,
+            created_at: Utc::now().to_rfc3339(),
+            prompt_count,
+            files: entries,
+        };
+        zip.start_file(MANIFEST_FILE, deflated).map_err(|e| e.to_string())?;
+        serde_json::to_writer_pretty(&mut zip, &manifest).map_err(|e| e.to_string())?;
+        zip.finish().map_err(|e| e.to_string())?;
+        Ok(manifest)
+    })();
+
+    match result {
+        Ok(manifest) => {
+            fs::rename(&partial, dest).map_err(|e| e.to_string())?;
+            Ok(manifest)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+/// Relative, `/`-separated paths of every file below `dir`.
+fn collect_files(dir: &Path, prefix: &str, paths: &mut Vec<String>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = format!("{}/{}", prefix, name);
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => collect_files(&entry.path(), &path, paths),
+            Ok(kind) if kind.is_file() => paths.push(path),
+            _ => {}
+        }
+    }
+}
+
+/// Reads the manifest of a backup archive.
+pub fn read_manifest(archive: &Path) -> Result<BackupManifest, String> {
+    let mut zip = ZipArchive::new(File::open(archive).map_err(|e| e.to_string())?)
+        .map_err(|_| "Not a PromptVault backup (not a zip archive)".to_string())?;
+    let mut text = String::new();
+    zip.by_name(MANIFEST_FILE)
+        .map_err(|_| "Not a PromptVault backup (manifest.json is missing)".to_string())?
+        .read_to_string(&mut text)
+        .map_err(|e| e.to_string())?;
+    let manifest: BackupManifest =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid backup manifest: {}", e))?;
+    if manifest.format != FORMAT {
+        return Err("Not a PromptVault backup".to_string());
+    }
+    Ok(manifest)
+}
+
+/// Checks an archive and extracts it into `staging`. Nothing outside
+/// `staging` is touched, so a bad archive leaves the vault as it was.
+pub fn extract_archive(archive: &Path, staging: &Path) -> Result<BackupManifest, String> {
+    let manifest = read_manifest(archive)?;
+    if manifest.format_version > FORMAT_VERSION {
+        return Err("This backup was made by a newer version of PromptVault".to_string());
+    }
+    if manifest.schema_version > db::latest_version() {
+        return Err(format!(
+            "This backup uses database schema v{}, this build supports up to v{}. Update the app to restore it.",
+            manifest.schema_version,
+            db::latest_version()
+        ));
+    }
+
+    let mut zip = ZipArchive::new(File::open(archive).map_err(|e| e.to_string())?)
+        .map_err(|e| e.to_string())?;
+    let _ = fs::remove_dir_all(staging);
+    fs::create_dir_all(staging).map_err(|e| e.to_string())?;
+
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(|e| e.to_string())?;
+        if entry.name() == MANIFEST_FILE || entry.is_dir() {
+            continue;
+        }
+        let relative = entry
+            .enclosed_name()
+            .map(Path::to_path_buf)
+            .filter(|path| {
+                path.components().next().is_some_and(|first| {
+                    VAULT_ENTRIES.iter().any(|entry| first.as_os_str() == *entry)
+                })
+            })
+            .ok_or_else(|| format!("Unexpected file in backup: {}", entry.name()))?;
+        let target = staging.join(relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let mut out = File::create(&target).map_err(|e| e.to_string())?;
+        io::copy(&mut entry, &mut out).map_err(|e| e.to_string())?;
+    }
+
+    for file in &manifest.files {
+        let size = fs::metadata(staging.join(&file.path)).map(|m| m.len()).ok();
+        if size != Some(file.size) {
+            return Err(format!("Backup is incomplete or damaged: {}", file.path));
+        }
+    }
+    check_database(&staging.join(DB_FILE), &manifest)?;
+    Ok(manifest)
+}
+
+fn check_database(path: &Path, manifest: &BackupManifest) -> Result<(), String> {
+    let damaged = |e: rusqlite::Error| format!("The database in the backup is damaged: {}", e);
+    let conn = Connection::open(path).map_err(damaged)?;
+    let integrity: String = conn
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(damaged)?;
+    if integrity != "ok" {
+        return Err(format!("The database in the backup is damaged: {}", integrity));
+    }
+    let schema_version: i32 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(damaged)?;
+    let prompt_count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM prompts", [], |row| row.get(0))
+        .map_err(damaged)?;
+    if schema_version != manifest.schema_version || prompt_count != manifest.prompt_count {
+        return Err("The database in the backup does not match its manifest".to_string());
+    }
+    Ok(())
+}
+
+/// Replaces the vault with the contents of `archive`. The current vault is
+/// backed up to `backups/` first.
+pub fn restore_vault(state: &AppState, archive: &Path) -> Result<BackupManifest, String> {
+    let data_dir = state.db.lock().map_err(|e| e.to_string())?.get_data_dir().clone();
+    let staging = data_dir.join(".restore-staging");
+
+    let result = extract_archive(archive, &staging).and_then(|manifest| {
+        let safety = backups_dir(&data_dir).join(archive_name("pre-restore-"));
+        backup_vault(state, &safety)?;
+        let mut db = state.db.lock().map_err(|e| e.to_string())?;
+        db.swap_vault(&staging).map_err(|e| e.to_string())?;
+        Ok(manifest)
+    });
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+/// Backup archives in `backups/`, newest first.
+pub fn list_backups(data_dir: &Path) -> Vec<BackupInfo> {
+    let mut backups: Vec<BackupInfo> = fs::read_dir(backups_dir(data_dir))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "zip"))
+        .filter_map(|path| {
+            let manifest = read_manifest(&path).ok()?;
+            Some(BackupInfo {
+                size: fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+                path: path.to_string_lossy().to_string(),
+                manifest,
+            })
+        })
+        .collect();
+    backups.sort_by(|a, b| b.manifest.created_at.cmp(&a.manifest.created_at));
+    backups
+}
+
+/// File name for a new archive, e.g. `auto-20240301-103000.zip`.
+pub fn archive_name(prefix: &str) -> String {
+    format!("{}{}.zip", prefix, Local::now().format("%Y%m%d-%H%M%S"))
+}
+
+/// Deletes the oldest automatic backups beyond `retention`.
+fn prune_backups(data_dir: &Path, retention: u32) {
+    let mut automatic: Vec<PathBuf> = fs::read_dir(backups_dir(data_dir))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_name().map(|n| n.to_string_lossy().to_string());
+            name.is_some_and(|n| n.starts_with(AUTO_PREFIX) && n.ends_with(".zip"))
+        })
+        .collect();
+    // The timestamp in the name sorts chronologically.
+    automatic.sort();
+    let excess = automatic.len().saturating_sub(retention.max(1) as usize);
+    for path in automatic.into_iter().take(excess) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Makes an automatic backup if the schedule says one is due.
+pub fn run_scheduled_backup(state: &AppState) -> Result<Option<BackupInfo>, String> {
+    let (data_dir, schedule) = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        (db.get_data_dir().clone(), db.backup_schedule().map_err(|e| e.to_string())?)
+    };
+    if schedule.interval_hours == 0 {
+        return Ok(None);
+    }
+    let last = schedule
+        .last_backup_at
+        .as_deref()
+        .and_then(|at| DateTime::parse_from_rfc3339(at).ok());
+    if let Some(last) = last {
+        if Utc::now() < last + Duration::hours(schedule.interval_hours as i64) {
+            return Ok(None);
+        }
+    }
+
+    let dest = backups_dir(&data_dir).join(archive_name(AUTO_PREFIX));
+    let info = backup_vault(state, &dest)?;
+    state
+        .db
+        .lock()
+        .map_err(|e| e.to_string())?
+        .set_setting(LAST_BACKUP_AT, &Utc::now().to_rfc3339())
+        .map_err(|e| e.to_string())?;
+    prune_backups(&data_dir, schedule.retention);
+    Ok(Some(info))
+}
+
+/// Checks for due backups, and purges expired trash, in the background for
+/// as long as the app runs. Failures are reported through
+/// `auto-backup-failed` and `trash-purge-failed` events.
+pub fn spawn_scheduler(app_handle: tauri::AppHandle) {
+    std::thread::spawn(move || loop {
+        let state = app_handle.state::<AppState>();
+        if let Err(e) = run_scheduled_backup(&state) {
+            let _ = app_handle.emit(AUTO_BACKUP_FAILED_EVENT, e);
+        }
+        let purged = state
+            .db
+            .lock()
+            .map_err(|e| e.to_string())
+            .and_then(|db| db.purge_trash_past_retention().map_err(|e| e.to_string()));
+        if let Err(e) = purged {
+            let _ = app_handle.emit(TRASH_PURGE_FAILED_EVENT, e);
+        }
+        std::thread::sleep(SCHEDULE_CHECK_INTERVAL);
+    });
+}
+
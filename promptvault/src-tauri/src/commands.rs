@@ -1,25 +1,83 @@
-use crate::db::{NewPrompt, Prompt, UpdatePrompt};
+use crate::backup::{self, BackupInfo, BackupManifest};
+use crate::db::{
+    BackupSchedule, Database, NewPrompt, NewPromptImage, PageRequest, Prompt, PromptPage,
+    PromptQuery, PromptRevision, QueryError, RevisionDiff, SearchQuery, SearchResult, Tag,
+    TagMatch, UpdatePrompt, WatchedFolder, WatchedFolderSettings,
+};
+use crate::dedupe::{self, DedupeReport};
+use crate::export;
+use crate::image_store::{self, ImageUpload, StoredImage};
+use crate::import::{self, ImportOptions};
+use crate::metadata;
+use crate::similar::{self, SimilarPrompt};
+use crate::watcher;
 use crate::AppState;
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
-use image::imageops::FilterType;
 use std::fs;
-use std::path::{Component, Path};
+use std::path::{Component, Path, PathBuf};
 use tauri::State;
 use tauri_plugin_clipboard_manager::ClipboardExt;
 use tauri_plugin_shell::ShellExt;
-use uuid::Uuid;
 
+/// Prompts outside the trash, newest first. `query`, when given, replaces
+/// the older filter arguments.
 #[tauri::command]
 pub fn get_all_prompts(
     state: State<AppState>,
     filter: Option<String>,
     collection_id: Option<i32>,
+    include_descendants: Option<bool>,
+    tags: Option<Vec<String>>,
+    tag_match: Option<TagMatch>,
+    query: Option<PromptQuery>,
 ) -> Result<Vec<Prompt>, String> {
+    let query = query.unwrap_or_else(|| {
+        PromptQuery::from_filters(
+            filter,
+            collection_id,
+            include_descendants.unwrap_or(false),
+            tags.unwrap_or_default(),
+            tag_match.unwrap_or_default(),
+        )
+    });
+    query.validate()?;
     let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.get_all_prompts(filter.as_deref(), collection_id)
+    db.get_all_prompts(&query).map_err(|e| e.to_string())
+}
+
+/// One page of the gallery as slim summaries, with the total for the
+/// scrollbar.
+#[tauri::command]
+pub fn list_prompts(
+    state: State<AppState>,
+    query: Option<PromptQuery>,
+    page: Option<PageRequest>,
+) -> Result<PromptPage, String> {
+    let query = query.unwrap_or_default();
+    query.validate()?;
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.list_prompts(&query, &page.unwrap_or_default())
         .map_err(|e| e.to_string())
 }
 
+/// Writes the prompts matching `query` to `path` as JSON and returns how
+/// many were exported.
+#[tauri::command]
+pub fn export_prompts(
+    state: State<AppState>,
+    path: String,
+    query: Option<PromptQuery>,
+) -> Result<usize, String> {
+    let query = query.unwrap_or_default();
+    query.validate()?;
+    let prompts = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.get_all_prompts(&query).map_err(|e| e.to_string())?
+    };
+    fs::write(&path, export::prompts_json(&prompts)?).map_err(|e| e.to_string())?;
+    Ok(prompts.len())
+}
+
 #[tauri::command]
 pub fn get_prompt_by_id(state: State<AppState>, id: i32) -> Result<Prompt, String> {
     let db = state.db.lock().map_err(|e| e.to_string())?;
@@ -27,223 +85,451 @@ pub fn get_prompt_by_id(state: State<AppState>, id: i32) -> Result<Prompt, Strin
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub fn create_prompt(
     state: State<AppState>,
-    prompt: NewPrompt,
+    mut prompt: NewPrompt,
     image_data: Option<Vec<u8>>,
     filename: Option<String>,
     image_path: Option<String>,
     image_base64: Option<String>,
     has_image: Option<bool>,
+    images: Option<Vec<ImageUpload>>,
 ) -> Result<Prompt, String> {
     let db = state.db.lock().map_err(|e| e.to_string())?;
     let data_dir = db.get_data_dir().clone();
+    check_collection_exists(&db, prompt.collection_id)?;
+    check_manual_collections(&db, prompt.collection_ids.as_deref())?;
 
-    let expects_image = has_image.unwrap_or(false)
-        || image_data.is_some()
-        || image_path.as_deref().is_some_and(|p| !p.is_empty())
-        || image_base64.as_deref().is_some_and(|p| !p.is_empty());
-
-    let (image_path, thumbnail_path) = if let Some(data) = resolve_image_data(
+    // The single-image arguments are kept for older callers; they become the
+    // first image of the batch.
+    let single = ImageUpload {
         image_data,
-        image_path.as_deref(),
-        image_base64.as_deref(),
-    )? {
-        let ext = resolve_image_extension(
-            filename.as_deref(),
-            image_path.as_deref(),
-            image_base64.as_deref(),
-        );
-        let uuid = Uuid::new_v4().to_string();
-        let now = chrono::Local::now();
-        let month_dir = now.format("%Y-%m").to_string();
-
-        // Create directories
-        let images_dir = data_dir.join("images").join(&month_dir);
-        let thumbnails_dir = data_dir.join("thumbnails");
-        fs::create_dir_all(&images_dir).map_err(|e| e.to_string())?;
-        fs::create_dir_all(&thumbnails_dir).map_err(|e| e.to_string())?;
-
-        // Save original image
-        let image_filename = format!("{}.{}" , uuid, ext);
-        let image_path_full = images_dir.join(&image_filename);
-        fs::write(&image_path_full, &data).map_err(|e| e.to_string())?;
-
-        // Create thumbnail
-        let image_rel_path = format!("images/{}/{}", month_dir, image_filename);
-
-        let thumbnail_rel_path = if ext.eq_ignore_ascii_case("svg") {
-            // The `image` crate doesn't decode SVG. Reuse the original for preview.
-            image_rel_path.clone()
-        } else {
-            let thumbnail_filename = format!("{}_thumb.{}", uuid, ext);
-            let thumbnail_path_full = thumbnails_dir.join(&thumbnail_filename);
-            match image::load_from_memory(&data) {
-                Ok(img) => {
-                    let thumbnail = img.resize(300, 300, FilterType::Lanczos3);
-                    thumbnail
-                        .save(&thumbnail_path_full)
-                        .map_err(|e| e.to_string())?;
-                    format!("thumbnails/{}", thumbnail_filename)
-                }
-                Err(_) => {
-                    // Fallback: if we can't decode the image for thumbnail generation,
-                    // still show something in the UI.
-                    image_rel_path.clone()
-                }
-            }
-        };
+        filename,
+        image_path,
+        image_base64,
+        seed: None,
+    };
+    let expects_image = has_image.unwrap_or(false) || !single.is_empty();
+    let mut uploads = vec![single];
+    uploads.extend(images.unwrap_or_default());
 
-        (Some(image_rel_path), Some(thumbnail_rel_path))
-    } else {
-        if expects_image {
-            return Err("No se recibieron datos de imagen. Vuelve a seleccionar el archivo e intenta de nuevo.".to_string());
-        }
-        (None, None)
+    let stored = store_uploads(&db, uploads)?;
+    if stored.is_empty() && expects_image {
+        return Err(image_store::NO_IMAGE_DATA.to_string());
+    }
+
+    // Parameters embedded in the first image fill whatever the user left empty.
+    if let Some(metadata) = stored.iter().find_map(|image| image.metadata.as_ref()) {
+        metadata.fill_prompt(&mut prompt);
+    }
+
+    db.create_prompt(&prompt, &new_prompt_images(&stored)).map_err(|e| {
+        discard_stored(&data_dir, &stored);
+        e.to_string()
+    })
+}
+
+#[tauri::command]
+pub fn add_prompt_images(
+    state: State<AppState>,
+    prompt_id: i32,
+    images: Vec<ImageUpload>,
+) -> Result<Prompt, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let data_dir = db.get_data_dir().clone();
+    db.get_prompt_by_id(prompt_id).map_err(|e| e.to_string())?;
+
+    let stored = store_uploads(&db, images)?;
+    db.add_prompt_images(prompt_id, &new_prompt_images(&stored)).map_err(|e| {
+        discard_stored(&data_dir, &stored);
+        e.to_string()
+    })
+}
+
+#[tauri::command]
+pub fn attach_prompt_image(
+    state: State<AppState>,
+    prompt_id: i32,
+    image: ImageUpload,
+) -> Result<Prompt, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let data_dir = db.get_data_dir().clone();
+    db.get_prompt_by_id(prompt_id).map_err(|e| e.to_string())?;
+
+    let stored = store_single_upload(&db, image)?;
+    db.attach_prompt_image(prompt_id, &new_prompt_image(&stored)).map_err(|e| {
+        discard_stored(&data_dir, std::slice::from_ref(&stored));
+        e.to_string()
+    })
+}
+
+#[tauri::command]
+pub fn replace_prompt_image(
+    state: State<AppState>,
+    image_id: i32,
+    image: ImageUpload,
+) -> Result<Prompt, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let data_dir = db.get_data_dir().clone();
+    db.get_prompt_image(image_id).map_err(|e| e.to_string())?;
+
+    let stored = store_single_upload(&db, image)?;
+    db.replace_prompt_image(image_id, &new_prompt_image(&stored)).map_err(|e| {
+        discard_stored(&data_dir, std::slice::from_ref(&stored));
+        e.to_string()
+    })
+}
+
+#[tauri::command]
+pub fn detach_prompt_image(state: State<AppState>, prompt_id: i32) -> Result<Prompt, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.detach_prompt_image(prompt_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn remove_prompt_image(state: State<AppState>, image_id: i32) -> Result<Prompt, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.remove_prompt_image(image_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_prompt_cover_image(state: State<AppState>, image_id: i32) -> Result<Prompt, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_prompt_cover_image(image_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn reorder_prompt_images(
+    state: State<AppState>,
+    prompt_id: i32,
+    image_ids: Vec<i32>,
+) -> Result<Prompt, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.reorder_prompt_images(prompt_id, &image_ids)
+        .map_err(|e| e.to_string())
+}
+
+/// Reads the generation parameters of an image without storing it, as a
+/// pre-filled prompt for the new prompt form. `None` if the image has none.
+#[tauri::command]
+pub fn extract_image_metadata(image: ImageUpload) -> Result<Option<NewPrompt>, String> {
+    let Some((data, _)) = image_store::read_upload(image)? else {
+        return Err(image_store::NO_IMAGE_DATA.to_string());
     };
+    Ok(metadata::read_metadata(&data).map(|metadata| {
+        let mut prompt = NewPrompt::default();
+        metadata.fill_prompt(&mut prompt);
+        prompt
+    }))
+}
 
-    db.create_prompt(&prompt, image_path.as_deref(), thumbnail_path.as_deref())
+/// Prompts that already contain this exact image, so the new prompt form
+/// can point at them before a duplicate is saved.
+#[tauri::command]
+pub fn find_duplicate_prompts(
+    state: State<AppState>,
+    image: ImageUpload,
+) -> Result<Vec<Prompt>, String> {
+    let Some((data, _)) = image_store::read_upload(image)? else {
+        return Err(image_store::NO_IMAGE_DATA.to_string());
+    };
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.find_prompts_with_image(&image_store::content_hash(&data))
         .map_err(|e| e.to_string())
 }
 
-fn resolve_image_data(
-    image_data: Option<Vec<u8>>,
-    image_path: Option<&str>,
-    image_base64: Option<&str>,
-) -> Result<Option<Vec<u8>>, String> {
-    // Prefer bytes sent over IPC (most reliable) over trying to read a client-provided path.
-    if image_data.is_some() {
-        return Ok(image_data);
-    }
-    if let Some(data_url) = image_base64 {
-        // Supports both:
-        // - data:<mime>;base64,<...>
-        // - data:<mime>,<percent-encoded bytes> (common for SVG)
-        if let Some((meta, data)) = data_url.split_once(',') {
-            if meta.contains(";base64") {
-                match BASE64.decode(data) {
-                    Ok(bytes) => return Ok(Some(bytes)),
-                    Err(_) => {
-                        if image_data.is_some() {
-                            return Ok(image_data);
-                        }
-                    }
-                }
-            } else {
-                let decoded = percent_decode(data.as_bytes());
-                if !decoded.is_empty() {
-                    return Ok(Some(decoded));
-                }
-            }
-        } else {
-            // If the string is raw base64 without a data URL prefix.
-            if let Ok(bytes) = BASE64.decode(data_url) {
-                return Ok(Some(bytes));
-            }
+/// Hashes the images stored before hashing existed, makes identical images
+/// share one file and reports which prompts have the same image.
+#[tauri::command]
+pub fn dedupe_images(state: State<AppState>) -> Result<DedupeReport, String> {
+    dedupe::dedupe_vault(&state)
+}
+
+/// Reverse image lookup: the prompts whose images look most like `image`
+/// (bytes or a path) or like the images of prompt `prompt_id`, nearest
+/// first.
+#[tauri::command]
+pub fn find_similar_prompts(
+    state: State<AppState>,
+    image: Option<ImageUpload>,
+    prompt_id: Option<i32>,
+    max_distance: Option<u32>,
+    limit: Option<usize>,
+) -> Result<Vec<SimilarPrompt>, String> {
+    let query = match (image, prompt_id) {
+        (Some(image), _) => {
+            let Some((data, _)) = image_store::read_upload(image)? else {
+                return Err(image_store::NO_IMAGE_DATA.to_string());
+            };
+            let img = image::load_from_memory(&data).map_err(|_| "Not a readable image".to_string())?;
+            vec![similar::dhash(&img)]
         }
-    }
-    if let Some(path) = image_path {
-        let normalized = normalize_fs_path(path);
-        if let Ok(bytes) = fs::read(path) {
-            return Ok(Some(bytes));
+        (None, Some(prompt_id)) => {
+            let db = state.db.lock().map_err(|e| e.to_string())?;
+            let images = db.get_prompt_images(prompt_id).map_err(|e| e.to_string())?;
+            let hashes: Vec<i64> = images.iter().filter_map(|i| i.perceptual_hash).collect();
+            if hashes.is_empty() {
+                return Err("This prompt has no hashed images".to_string());
+            }
+            hashes
         }
-        if let Some(p) = normalized.as_deref() {
-            if p != path {
-                if let Ok(bytes) = fs::read(p) {
-                    return Ok(Some(bytes));
-                }
+        (None, None) => return Err("Give an image or a prompt to compare with".to_string()),
+    };
+
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let hashes = db.get_perceptual_hashes().map_err(|e| e.to_string())?;
+    similar::nearest(&hashes, &query, prompt_id, max_distance, limit.unwrap_or(20))
+        .into_iter()
+        .map(|(prompt_id, image_id, distance)| {
+            Ok(SimilarPrompt {
+                prompt: db.get_prompt_by_id(prompt_id).map_err(|e| e.to_string())?,
+                image_id,
+                distance,
+            })
+        })
+        .collect()
+}
+
+/// Starts computing perceptual hashes for images stored before they were
+/// recorded. Returns the job id; see `similar::spawn_backfill`.
+#[tauri::command]
+pub fn backfill_perceptual_hashes(app: tauri::AppHandle) -> Result<String, String> {
+    Ok(similar::spawn_backfill(app))
+}
+
+/// Stores every non-empty upload. If one fails, the files already written
+/// for the others are removed again.
+fn store_uploads(db: &Database, uploads: Vec<ImageUpload>) -> Result<Vec<StoredImage>, String> {
+    let mut stored = Vec::new();
+    for upload in uploads {
+        match image_store::store_upload(db, upload) {
+            Ok(Some(image)) => stored.push(image),
+            Ok(None) => {}
+            Err(e) => {
+                discard_stored(db.get_data_dir(), &stored);
+                return Err(e);
             }
         }
     }
-    Ok(None)
+    Ok(stored)
 }
 
-fn normalize_fs_path(path: &str) -> Option<String> {
-    let p = path.trim();
-    if let Some(rest) = p.strip_prefix("file://") {
-        // Common when a frontend passes a file URL instead of a plain path.
-        // file:///C:/... or file://C:/...
-        let rest = rest.strip_prefix('/').unwrap_or(rest);
-        return Some(rest.replace('/', "\\"));
+fn store_single_upload(db: &Database, upload: ImageUpload) -> Result<StoredImage, String> {
+    store_uploads(db, vec![upload])?
+        .pop()
+        .ok_or_else(|| image_store::NO_IMAGE_DATA.to_string())
+}
+
+fn new_prompt_image(image: &StoredImage) -> NewPromptImage {
+    NewPromptImage {
+        image_path: image.image_path.clone(),
+        thumbnail_path: Some(image.thumbnail_path.clone()),
+        seed: image.seed.clone(),
+        width: image.width,
+        height: image.height,
+        source_path: None,
+        content_hash: Some(image.content_hash.clone()),
+        perceptual_hash: image.perceptual_hash,
     }
-    None
 }
 
-fn resolve_image_extension(
-    filename: Option<&str>,
-    image_path: Option<&str>,
-    image_base64: Option<&str>,
-) -> String {
-    if let Some(name) = filename {
-        if let Some(ext) = name.split('.').last() {
-            if !ext.is_empty() {
-                return ext.to_string();
-            }
-        }
+fn new_prompt_images(images: &[StoredImage]) -> Vec<NewPromptImage> {
+    images.iter().map(new_prompt_image).collect()
+}
+
+fn discard_stored(data_dir: &Path, images: &[StoredImage]) {
+    for image in images.iter().filter(|image| !image.reused) {
+        image_store::remove_image_files(data_dir, &image.image_path, Some(&image.thumbnail_path));
     }
-    if let Some(path) = image_path {
-        if let Some(ext) = Path::new(path).extension().and_then(|e| e.to_str()) {
-            if !ext.is_empty() {
-                return ext.to_string();
-            }
-        }
+}
+
+/// The ComfyUI workflow JSON stored with a prompt, ready to be saved as a
+/// `.json` file and loaded back into ComfyUI.
+#[tauri::command]
+pub fn get_prompt_workflow(state: State<AppState>, id: i32) -> Result<Option<String>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.get_prompt_workflow(id).map_err(|e| e.to_string())
+}
+
+/// Writes an image of a prompt to `path` as a PNG carrying its generation
+/// settings, so other tools (and PromptVault) can read them back. Uses the
+/// cover unless `image_id` picks another image of the prompt.
+#[tauri::command]
+pub fn export_prompt_png(
+    state: State<AppState>,
+    id: i32,
+    path: String,
+    image_id: Option<i32>,
+    include_json: Option<bool>,
+) -> Result<(), String> {
+    // Decoding and encoding the image happen after the lock is released.
+    let (prompt, image_path, workflow, data_dir) = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        let prompt = db.get_prompt_by_id(id).map_err(|e| e.to_string())?;
+        let image_path = match image_id {
+            Some(image_id) => prompt
+                .images
+                .iter()
+                .find(|image| image.id == image_id)
+                .map(|image| image.image_path.clone())
+                .ok_or_else(|| "Image does not belong to this prompt".to_string())?,
+            None => prompt
+                .image_path
+                .clone()
+                .ok_or_else(|| "This prompt has no image to export".to_string())?,
+        };
+        let workflow = db.get_prompt_workflow(id).map_err(|e| e.to_string())?;
+        (prompt, image_path, workflow, db.get_data_dir().clone())
+    };
+
+    let png = export::prompt_png(
+        &data_dir,
+        &prompt,
+        &image_path,
+        workflow.as_deref(),
+        include_json.unwrap_or(false),
+    )?;
+    fs::write(&path, png).map_err(|e| e.to_string())
+}
+
+/// Imports every image in a folder as a prompt on a background job and
+/// returns the job id. See `import::spawn` for the events it sends.
+#[tauri::command]
+pub fn import_folder(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    path: String,
+    options: Option<ImportOptions>,
+) -> Result<String, String> {
+    let root = PathBuf::from(&path);
+    if !root.is_dir() {
+        return Err(format!("Folder not found: {}", path));
     }
-    if let Some(data_url) = image_base64 {
-        if let Some(mime) = data_url.strip_prefix("data:").and_then(|v| v.split(';').next()) {
-            return match mime {
-                "image/jpeg" => "jpg",
-                "image/jpg" => "jpg",
-                "image/png" => "png",
-                "image/webp" => "webp",
-                "image/gif" => "gif",
-                "image/bmp" => "bmp",
-                "image/tiff" => "tiff",
-                "image/svg+xml" => "svg",
-                _ => "png",
-            }
-            .to_string();
-        }
+    let options = options.unwrap_or_default();
+    {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        check_collection_exists(&db, options.collection_id)?;
     }
-    "png".to_string()
-}
-
-fn percent_decode(input: &[u8]) -> Vec<u8> {
-    // Minimal percent-decoder for data URLs (ASCII/UTF-8). Also converts '+' to space.
-    let mut out = Vec::with_capacity(input.len());
-    let mut i = 0;
-    while i < input.len() {
-        match input[i] {
-            b'%' if i + 2 < input.len() => {
-                let h1 = from_hex(input[i + 1]);
-                let h2 = from_hex(input[i + 2]);
-                if let (Some(h1), Some(h2)) = (h1, h2) {
-                    out.push((h1 << 4) | h2);
-                    i += 3;
-                    continue;
-                }
-                out.push(input[i]);
-                i += 1;
-            }
-            b'+' => {
-                out.push(b' ');
-                i += 1;
-            }
-            b => {
-                out.push(b);
-                i += 1;
-            }
+    Ok(import::spawn(app, root, options))
+}
+
+/// Stops a background job (such as a folder import) after the file it is
+/// working on.
+#[tauri::command]
+pub fn cancel_job(state: State<AppState>, job_id: String) -> Result<bool, String> {
+    Ok(state.jobs.cancel(&job_id))
+}
+
+#[tauri::command]
+pub fn get_watched_folders(state: State<AppState>) -> Result<Vec<WatchedFolder>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.get_watched_folders().map_err(|e| e.to_string())
+}
+
+/// Starts watching a folder; images that appear in it from now on are
+/// imported automatically.
+#[tauri::command]
+pub fn add_watched_folder(
+    state: State<AppState>,
+    path: String,
+    settings: Option<WatchedFolderSettings>,
+) -> Result<WatchedFolder, String> {
+    watcher::check_watcher(&state)?;
+    let root = match watcher::canonical_path(Path::new(path.trim())) {
+        Ok(root) if root.is_dir() => root.to_string_lossy().to_string(),
+        _ => return Err(format!("Folder not found: {}", path)),
+    };
+    let settings = settings.unwrap_or(WatchedFolderSettings {
+        collection_id: None,
+        model: None,
+        recursive: None,
+        is_active: None,
+    });
+
+    let folder = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        if db.find_watched_folder_id(&root).map_err(|e| e.to_string())?.is_some() {
+            return Err("This folder is already being watched".to_string());
         }
+        check_collection_exists(&db, settings.collection_id)?;
+        db.add_watched_folder(&root, &settings)
+            .map_err(|e| e.to_string())?
+    };
+    watcher::sync_watched_folders(&state)?;
+    Ok(folder)
+}
+
+#[tauri::command]
+pub fn update_watched_folder(
+    state: State<AppState>,
+    id: i32,
+    settings: WatchedFolderSettings,
+) -> Result<WatchedFolder, String> {
+    if settings.is_active == Some(true) {
+        watcher::check_watcher(&state)?;
+    }
+    let folder = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        check_collection_exists(&db, settings.collection_id)?;
+        db.update_watched_folder(id, &settings)
+            .map_err(|e| e.to_string())?
+    };
+    watcher::sync_watched_folders(&state)?;
+    Ok(folder)
+}
+
+#[tauri::command]
+pub fn remove_watched_folder(state: State<AppState>, id: i32) -> Result<(), String> {
+    {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.remove_watched_folder(id).map_err(|e| e.to_string())?;
+    }
+    watcher::sync_watched_folders(&state)
+}
+
+fn check_collection_exists(
+    db: &crate::db::Database,
+    collection_id: Option<i32>,
+) -> Result<(), String> {
+    let Some(collection_id) = collection_id else {
+        return Ok(());
+    };
+    check_manual_collection(db, collection_id)
+}
+
+/// Prompts can only be put in collections filled by hand; a smart
+/// collection's query decides what's in it.
+fn check_manual_collection(db: &crate::db::Database, collection_id: i32) -> Result<(), String> {
+    match db.get_collection(collection_id) {
+        Ok(collection) if collection.query.is_some() => Err(format!(
+            "\"{}\" is a smart collection; edit its query instead",
+            collection.name
+        )),
+        Ok(_) => Ok(()),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err("Collection not found".to_string()),
+        Err(e) => Err(e.to_string()),
     }
-    out
 }
 
-fn from_hex(b: u8) -> Option<u8> {
-    match b {
-        b'0'..=b'9' => Some(b - b'0'),
-        b'a'..=b'f' => Some(b - b'a' + 10),
-        b'A'..=b'F' => Some(b - b'A' + 10),
-        _ => None,
+fn check_manual_collections(
+    db: &crate::db::Database,
+    collection_ids: Option<&[i32]>,
+) -> Result<(), String> {
+    collection_ids
+        .unwrap_or_default()
+        .iter()
+        .try_for_each(|id| check_manual_collection(db, *id))
+}
+
+/// A smart collection query, trimmed, once it parses.
+fn check_collection_query(query: &str) -> Result<&str, String> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Err("Smart collection query cannot be empty".to_string());
     }
+    SearchQuery::parse(query).map_err(|e| e.to_string())?;
+    Ok(query)
 }
 
 #[tauri::command]
@@ -252,7 +538,11 @@ pub fn update_prompt(
     id: i32,
     prompt: UpdatePrompt,
 ) -> Result<Prompt, String> {
+    if prompt.rating.is_some_and(|rating| !(0..=5).contains(&rating)) {
+        return Err("Rating must be between 0 and 5".to_string());
+    }
     let db = state.db.lock().map_err(|e| e.to_string())?;
+    check_manual_collections(&db, prompt.collection_ids.as_deref())?;
     db.update_prompt(id, &prompt).map_err(|e| e.to_string())
 }
 
@@ -262,6 +552,131 @@ pub fn delete_prompt(state: State<AppState>, id: i32) -> Result<(), String> {
     db.delete_prompt(id).map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub fn get_trash(state: State<AppState>) -> Result<Vec<Prompt>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.get_trash().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn restore_prompt(state: State<AppState>, id: i32) -> Result<Prompt, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.restore_prompt(id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn purge_prompt(state: State<AppState>, id: i32) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.purge_prompt(id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn empty_trash(state: State<AppState>) -> Result<usize, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.empty_trash().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_trash_retention_days(state: State<AppState>) -> Result<u32, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.trash_retention_days().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_trash_retention_days(state: State<AppState>, days: u32) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_trash_retention_days(days).map_err(|e| e.to_string())
+}
+
+/// Backs up the whole vault (database, images and thumbnails) as a zip
+/// archive. Without `path` it goes to the `backups` folder of the vault.
+#[tauri::command]
+pub fn create_backup(state: State<AppState>, path: Option<String>) -> Result<BackupInfo, String> {
+    let dest = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let data_dir = state.db.lock().map_err(|e| e.to_string())?.get_data_dir().clone();
+            backup::backups_dir(&data_dir).join(backup::archive_name("vault-"))
+        }
+    };
+    backup::backup_vault(&state, &dest)
+}
+
+#[tauri::command]
+pub fn get_backups(state: State<AppState>) -> Result<Vec<BackupInfo>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    Ok(backup::list_backups(db.get_data_dir()))
+}
+
+/// Replaces the vault with a backup archive after checking it. The current
+/// vault is saved to the `backups` folder first.
+#[tauri::command]
+pub fn restore_backup(state: State<AppState>, path: String) -> Result<BackupManifest, String> {
+    let manifest = backup::restore_vault(&state, Path::new(&path))?;
+    // The restored vault brings its own watched folders.
+    watcher::sync_watched_folders(&state)?;
+    Ok(manifest)
+}
+
+#[tauri::command]
+pub fn get_backup_schedule(state: State<AppState>) -> Result<BackupSchedule, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.backup_schedule().map_err(|e| e.to_string())
+}
+
+/// Sets how often automatic backups run (`0` turns them off) and how many
+/// of them are kept.
+#[tauri::command]
+pub fn set_backup_schedule(
+    state: State<AppState>,
+    interval_hours: u32,
+    retention: u32,
+) -> Result<(), String> {
+    if retention == 0 {
+        return Err("At least one backup has to be kept".to_string());
+    }
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_backup_schedule(interval_hours, retention)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_prompt_revisions(
+    state: State<AppState>,
+    prompt_id: i32,
+) -> Result<Vec<PromptRevision>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.get_prompt_revisions(prompt_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn diff_prompt_revisions(
+    state: State<AppState>,
+    from_id: i32,
+    to_id: i32,
+) -> Result<RevisionDiff, String> {
+    let (from, to) = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        let from = db.get_prompt_revision(from_id).map_err(|e| e.to_string())?;
+        let to = db.get_prompt_revision(to_id).map_err(|e| e.to_string())?;
+        (from, to)
+    };
+    if from.prompt_id != to.prompt_id {
+        return Err("Revisions belong to different prompts".to_string());
+    }
+    Ok(RevisionDiff::between(from, to))
+}
+
+#[tauri::command]
+pub fn restore_prompt_revision(
+    state: State<AppState>,
+    revision_id: i32,
+) -> Result<Prompt, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.restore_prompt_revision(revision_id)
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub fn toggle_favorite(state: State<AppState>, id: i32) -> Result<bool, String> {
     let db = state.db.lock().map_err(|e| e.to_string())?;
@@ -269,9 +684,23 @@ pub fn toggle_favorite(state: State<AppState>, id: i32) -> Result<bool, String>
 }
 
 #[tauri::command]
-pub fn search_prompts(state: State<AppState>, query: String) -> Result<Vec<Prompt>, String> {
+pub fn search_prompts(
+    state: State<AppState>,
+    query: String,
+    filter: Option<PromptQuery>,
+) -> Result<Vec<SearchResult>, String> {
+    let query = SearchQuery::parse(&query).map_err(|e| e.to_string())?;
+    let filter = filter.unwrap_or_default();
+    filter.validate()?;
     let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.search_prompts(&query).map_err(|e| e.to_string())
+    db.search_prompts(&query, &filter).map_err(|e| e.to_string())
+}
+
+/// Where a search box query is malformed, if it is, so the mistake can be
+/// highlighted while typing.
+#[tauri::command]
+pub fn check_search_query(query: String) -> Option<QueryError> {
+    SearchQuery::parse(&query).err()
 }
 
 #[tauri::command]
@@ -281,15 +710,348 @@ pub fn get_collections(state: State<AppState>) -> Result<Vec<crate::db::Collecti
 }
 
 #[tauri::command]
-pub fn create_collection(state: State<AppState>, name: String) -> Result<crate::db::Collection, String> {
+pub fn create_collection(
+    state: State<AppState>,
+    name: String,
+    color: Option<String>,
+    icon: Option<String>,
+    parent_id: Option<i32>,
+    query: Option<String>,
+) -> Result<crate::db::Collection, String> {
     let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.create_collection(&name).map_err(|e| e.to_string())
+    check_collection_name(&db, &name, None)?;
+    if let Some(color) = color.as_deref() {
+        check_color(color)?;
+    }
+    let query = query.as_deref().map(check_collection_query).transpose()?;
+    let collection = db
+        .create_collection(&name, color.as_deref(), icon.as_deref(), parent_id)
+        .map_err(|e| e.to_string())?;
+    match query {
+        Some(query) => db
+            .set_collection_query(collection.id, query)
+            .map_err(|e| e.to_string()),
+        None => Ok(collection),
+    }
 }
 
+/// Changes the query of a smart collection.
 #[tauri::command]
-pub fn get_models(state: State<AppState>) -> Result<Vec<crate::db::Model>, String> {
+pub fn set_collection_query(
+    state: State<AppState>,
+    id: i32,
+    query: String,
+) -> Result<crate::db::Collection, String> {
     let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.get_models().map_err(|e| e.to_string())
+    let query = check_collection_query(&query)?;
+    let collection = db.get_collection(id).map_err(|e| e.to_string())?;
+    if collection.query.is_none() {
+        return Err("Only smart collections have a query".to_string());
+    }
+    db.set_collection_query(id, query).map_err(|e| e.to_string())
+}
+
+/// Freezes a smart collection into a regular one holding the prompts it
+/// matches now.
+#[tauri::command]
+pub fn convert_to_static_collection(
+    state: State<AppState>,
+    id: i32,
+) -> Result<crate::db::Collection, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let collection = db.get_collection(id).map_err(|e| e.to_string())?;
+    if collection.query.is_none() {
+        return Err("This collection is not a smart collection".to_string());
+    }
+    db.convert_to_static_collection(id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn move_collection(
+    state: State<AppState>,
+    id: i32,
+    parent_id: Option<i32>,
+) -> Result<crate::db::Collection, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    if let Some(parent_id) = parent_id {
+        let subtree = db.collection_subtree_ids(id).map_err(|e| e.to_string())?;
+        if subtree.contains(&parent_id) {
+            return Err("A collection cannot be moved inside itself or one of its descendants".to_string());
+        }
+    }
+    db.move_collection(id, parent_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn rename_collection(
+    state: State<AppState>,
+    id: i32,
+    name: String,
+) -> Result<crate::db::Collection, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    check_collection_name(&db, &name, Some(id))?;
+    db.rename_collection(id, &name).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_collection_color(
+    state: State<AppState>,
+    id: i32,
+    color: String,
+) -> Result<crate::db::Collection, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    check_color(&color)?;
+    db.set_collection_color(id, &color).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_collection_icon(
+    state: State<AppState>,
+    id: i32,
+    icon: String,
+) -> Result<crate::db::Collection, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    if icon.trim().is_empty() {
+        return Err("Icon cannot be empty".to_string());
+    }
+    db.set_collection_icon(id, icon.trim()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn reorder_collections(
+    state: State<AppState>,
+    ids: Vec<i32>,
+) -> Result<Vec<crate::db::Collection>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let mut parents = Vec::new();
+    for id in &ids {
+        let collection = db.get_collection(*id).map_err(|e| e.to_string())?;
+        parents.push(collection.parent_id);
+    }
+    if parents.windows(2).any(|pair| pair[0] != pair[1]) {
+        return Err("Only collections with the same parent can be reordered together".to_string());
+    }
+    db.reorder_collections(&ids).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn delete_collection(
+    state: State<AppState>,
+    id: i32,
+    move_to: Option<i32>,
+) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    if move_to == Some(id) {
+        return Err("Cannot move prompts into the collection being deleted".to_string());
+    }
+    if let Some(move_to) = move_to {
+        let collection = match db.get_collection(id) {
+            Ok(collection) => collection,
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                return Err("Collection not found".to_string())
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+        if collection.query.is_some() {
+            return Err(format!(
+                "\"{}\" is a smart collection; it has no prompts of its own to move",
+                collection.name
+            ));
+        }
+        check_manual_collection(&db, move_to)?;
+    }
+    db.delete_collection(id, move_to).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn add_prompts_to_collection(
+    state: State<AppState>,
+    collection_id: i32,
+    prompt_ids: Vec<i32>,
+) -> Result<crate::db::Collection, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    check_manual_collection(&db, collection_id)?;
+    db.add_prompts_to_collection(collection_id, &prompt_ids)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn remove_prompts_from_collection(
+    state: State<AppState>,
+    collection_id: i32,
+    prompt_ids: Vec<i32>,
+) -> Result<crate::db::Collection, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    check_manual_collection(&db, collection_id)?;
+    db.remove_prompts_from_collection(collection_id, &prompt_ids)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn move_prompts_to_collection(
+    state: State<AppState>,
+    from_collection_id: i32,
+    to_collection_id: i32,
+    prompt_ids: Vec<i32>,
+) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    check_manual_collection(&db, from_collection_id)?;
+    check_manual_collection(&db, to_collection_id)?;
+    db.move_prompts_to_collection(from_collection_id, to_collection_id, &prompt_ids)
+        .map_err(|e| e.to_string())
+}
+
+fn check_collection_name(db: &crate::db::Database, name: &str, id: Option<i32>) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Collection name cannot be empty".to_string());
+    }
+    match db.find_collection_id(name).map_err(|e| e.to_string())? {
+        Some(existing) if Some(existing) != id => Err(format!(
+            "A collection named \"{}\" already exists",
+            name.trim()
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn check_color(color: &str) -> Result<(), String> {
+    let hex = color.strip_prefix('#').unwrap_or("");
+    if matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(())
+    } else {
+        Err(format!("Invalid color \"{}\", expected #RGB or #RRGGBB", color))
+    }
+}
+
+#[tauri::command]
+pub fn get_tags(state: State<AppState>) -> Result<Vec<Tag>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.get_tags().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn rename_tag(state: State<AppState>, id: i32, name: String) -> Result<Tag, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    if name.trim().is_empty() {
+        return Err("Tag name cannot be empty".to_string());
+    }
+    if let Some(existing) = db.find_tag_id(&name).map_err(|e| e.to_string())? {
+        if existing != id {
+            return Err(format!(
+                "A tag named \"{}\" already exists. Merge the tags instead.",
+                name.trim()
+            ));
+        }
+    }
+    db.rename_tag(id, &name).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn merge_tags(
+    state: State<AppState>,
+    source_ids: Vec<i32>,
+    target_id: i32,
+) -> Result<Tag, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.merge_tags(&source_ids, target_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn delete_tag(state: State<AppState>, id: i32) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.delete_tag(id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_models(
+    state: State<AppState>,
+    include_inactive: Option<bool>,
+) -> Result<Vec<crate::db::Model>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.get_models(include_inactive.unwrap_or(false))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn create_model(
+    state: State<AppState>,
+    name: String,
+    short_name: Option<String>,
+) -> Result<crate::db::Model, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    check_model_name(&db, &name, None)?;
+    db.create_model(&name, short_name.as_deref().filter(|s| !s.trim().is_empty()))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn rename_model(
+    state: State<AppState>,
+    id: i32,
+    name: String,
+) -> Result<crate::db::Model, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    check_model_name(&db, &name, Some(id))?;
+    db.rename_model(id, &name).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_model_short_name(
+    state: State<AppState>,
+    id: i32,
+    short_name: String,
+) -> Result<crate::db::Model, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    if short_name.trim().is_empty() {
+        return Err("Short name cannot be empty".to_string());
+    }
+    db.set_model_short_name(id, &short_name)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_model_active(
+    state: State<AppState>,
+    id: i32,
+    is_active: bool,
+) -> Result<crate::db::Model, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_model_active(id, is_active).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn delete_model(
+    state: State<AppState>,
+    id: i32,
+    reassign_to: Option<i32>,
+) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    if reassign_to == Some(id) {
+        return Err("Cannot reassign prompts to the model being deleted".to_string());
+    }
+    if reassign_to.is_none() {
+        let model = db.get_model(id).map_err(|e| e.to_string())?;
+        if model.prompt_count > 0 {
+            return Err(format!(
+                "\"{}\" is used by {} prompt(s). Choose a model to reassign them to, or deactivate it instead.",
+                model.name, model.prompt_count
+            ));
+        }
+    }
+    db.delete_model(id, reassign_to).map_err(|e| e.to_string())
+}
+
+fn check_model_name(db: &crate::db::Database, name: &str, id: Option<i32>) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Model name cannot be empty".to_string());
+    }
+    match db.find_model_id(name).map_err(|e| e.to_string())? {
+        Some(existing) if Some(existing) != id => Err(format!(
+            "A model named \"{}\" already exists",
+            name.trim()
+        )),
+        _ => Ok(()),
+    }
 }
 
 #[tauri::command]
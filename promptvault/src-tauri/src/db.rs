@@ -1,10 +1,39 @@
-use rusqlite::{Connection, Result, params};
+use rusqlite::{Connection, Result, Row, params};
 use rusqlite::types::Value;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
 use tauri::Manager;
 
+mod backup;
+mod collections;
+mod images;
+mod listing;
+mod migrations;
+mod models;
+mod query;
+mod revisions;
+mod search_query;
+mod settings;
+mod similar;
+mod tags;
+mod trash;
+mod watched_folders;
+
+pub use backup::{DB_FILE, VAULT_ENTRIES};
+pub use collections::Collection;
+pub use images::{DuplicateGroup, NewPromptImage, PromptImage};
+pub use listing::{PageCursor, PageRequest, PromptPage, PromptSummary, SortDirection, SortKey};
+pub use migrations::latest_version;
+pub use models::Model;
+pub use query::PromptQuery;
+pub use revisions::{FieldDiff, PromptRevision, RevisionDiff};
+pub use search_query::{QueryError, SearchQuery};
+pub use settings::{BackupSchedule, LAST_BACKUP_AT};
+pub use similar::ImageHash;
+pub use tags::{Tag, TagMatch};
+pub use watched_folders::{WatchedFolder, WatchedFolderSettings};
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Prompt {
     pub id: i32,
@@ -12,26 +41,51 @@ pub struct Prompt {
     pub prompt_text: String,
     pub negative_prompt: String,
     pub model: String,
+    pub model_id: Option<i32>,
+    /// Hash of the checkpoint, as written by the generator.
+    pub model_hash: Option<String>,
+    /// Cover image, mirrored from `images`.
     pub image_path: Option<String>,
     pub thumbnail_path: Option<String>,
+    pub images: Vec<PromptImage>,
     pub dimensions: String,
     pub steps: Option<i32>,
     pub sampler: Option<String>,
     pub cfg_scale: Option<f64>,
     pub seed: Option<String>,
-    pub tags: String,
+    pub tags: Vec<String>,
     pub is_favorite: bool,
-    pub collection_id: Option<i32>,
+    /// 0 (unrated) to 5 stars.
+    pub rating: i32,
+    /// Whether a ComfyUI workflow is stored; see `get_prompt_workflow`.
+    pub has_workflow: bool,
+    /// C2PA content credentials read from the imported image.
+    pub provenance: Option<serde_json::Value>,
+    pub collection_ids: Vec<i32>,
     pub created_at: String,
     pub updated_at: String,
+    pub deleted_at: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    pub prompt: Prompt,
+    pub snippet: String,
+    pub rank: f64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct NewPrompt {
     pub title: String,
     pub prompt_text: String,
     pub negative_prompt: Option<String>,
     pub model: String,
+    /// Set when `model` was read from image metadata. Such names link to a
+    /// registry model that already exists but don't register new ones.
+    #[serde(skip)]
+    pub model_from_metadata: bool,
+    pub model_hash: Option<String>,
     pub dimensions: Option<String>,
     pub steps: Option<i32>,
     pub sampler: Option<String>,
@@ -39,6 +93,12 @@ pub struct NewPrompt {
     pub seed: Option<String>,
     pub tags: Option<Vec<String>>,
     pub collection_id: Option<i32>,
+    pub collection_ids: Option<Vec<i32>>,
+    /// ComfyUI workflow JSON the image was made with.
+    pub workflow_json: Option<String>,
+    /// Creation date taken from the image; defaults to now.
+    pub created_at: Option<String>,
+    pub provenance: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -54,24 +114,9 @@ pub struct UpdatePrompt {
     pub seed: Option<String>,
     pub tags: Option<Vec<String>>,
     pub is_favorite: Option<bool>,
-    pub collection_id: Option<i32>,
-}
-
-#[derive(Debug, Serialize)]
-pub struct Collection {
-    pub id: i32,
-    pub name: String,
-    pub icon: String,
-    pub color: String,
-    pub prompt_count: i32,
-}
-
-#[derive(Debug, Serialize)]
-pub struct Model {
-    pub id: i32,
-    pub name: String,
-    pub short_name: String,
-    pub is_active: bool,
+    pub rating: Option<i32>,
+    /// Replaces the prompt's collection memberships when present.
+    pub collection_ids: Option<Vec<i32>>,
 }
 
 pub struct Database {
@@ -91,237 +136,130 @@ impl Database {
         fs::create_dir_all(data_dir.join("images"))?;
         fs::create_dir_all(data_dir.join("thumbnails"))?;
 
-        let db_path = data_dir.join("promptvault.db");
-        let conn = Connection::open(&db_path)?;
-
+        let conn = backup::open_connection(&data_dir)?;
         let db = Self { conn, data_dir };
-        db.initialize_tables()?;
-        db.insert_default_data()?;
-        
-        Ok(db)
-    }
-
-    fn initialize_tables(&self) -> Result<()> {
-        self.conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS prompts (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                title TEXT NOT NULL,
-                prompt_text TEXT NOT NULL,
-                negative_prompt TEXT DEFAULT '',
-                model TEXT NOT NULL,
-                image_path TEXT,
-                thumbnail_path TEXT,
-                dimensions TEXT DEFAULT '1:1',
-                steps INTEGER,
-                sampler TEXT,
-                cfg_scale REAL,
-                seed TEXT,
-                tags TEXT DEFAULT '[]',
-                is_favorite INTEGER DEFAULT 0,
-                collection_id INTEGER,
-                created_at TEXT DEFAULT CURRENT_TIMESTAMP,
-                updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
-                FOREIGN KEY (collection_id) REFERENCES collections(id)
-            );
-
-            CREATE TABLE IF NOT EXISTS collections (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                name TEXT NOT NULL UNIQUE,
-                icon TEXT DEFAULT 'folder',
-                color TEXT DEFAULT '#6b7280',
-                created_at TEXT DEFAULT CURRENT_TIMESTAMP
-            );
-
-            CREATE TABLE IF NOT EXISTS models (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                name TEXT NOT NULL UNIQUE,
-                short_name TEXT,
-                is_active INTEGER DEFAULT 1
-            );
-
-            CREATE INDEX IF NOT EXISTS idx_prompts_model ON prompts(model);
-            CREATE INDEX IF NOT EXISTS idx_prompts_collection ON prompts(collection_id);
-            CREATE INDEX IF NOT EXISTS idx_prompts_favorite ON prompts(is_favorite);
-            CREATE INDEX IF NOT EXISTS idx_prompts_created ON prompts(created_at);"
-        )?;
-        Ok(())
-    }
 
-    fn insert_default_data(&self) -> Result<()> {
-        let models = [
-            ("Gemini", "Gemini"),
-            ("Chat GPT", "GPT"),
-            ("Stable Diffusion XL", "SDXL"),
-            ("Midjourney V6", "MJ"),
-            ("DALL-E 3", "DALL-E"),
-            ("Flux Pro", "Flux"),
-            ("Flux.1", "Flux.1"),
-            ("Leonardo AI", "Leo"),
-            ("Firefly", "Adobe"),
-        ];
-
-        for (name, short) in models.iter() {
-            self.conn.execute(
-                "INSERT OR IGNORE INTO models (name, short_name) VALUES (?1, ?2)",
-                params![name, short],
-            )?;
-        }
-        Ok(())
+        db.purge_trash_past_retention()?;
+        Ok(db)
     }
 
-    pub fn get_all_prompts(&self, filter: Option<&str>, collection_id: Option<i32>) -> Result<Vec<Prompt>> {
-        let mut sql = "SELECT * FROM prompts WHERE 1=1".to_string();
-        let mut params: Vec<Value> = Vec::new();
-
-        if let Some(model) = filter {
-            sql.push_str(" AND model = ?");
-            params.push(Value::from(model.to_string()));
-        }
-
-        if let Some(coll_id) = collection_id {
-            sql.push_str(" AND collection_id = ?");
-            params.push(Value::from(coll_id));
-        }
-
-        sql.push_str(" ORDER BY created_at DESC");
+    /// Prompts outside the trash that match `query`, newest first.
+    pub fn get_all_prompts(&self, query: &PromptQuery) -> Result<Vec<Prompt>> {
+        let (conditions, params) = self.query_conditions(query)?;
+        let sql = format!(
+            "SELECT {} FROM prompts p WHERE deleted_at IS NULL{} ORDER BY created_at DESC",
+            PROMPT_COLUMNS, conditions
+        );
 
         let mut stmt = self.conn.prepare(&sql)?;
-        let prompt_iter = stmt.query_map(rusqlite::params_from_iter(params.iter()), |row| {
-            Ok(Prompt {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                prompt_text: row.get(2)?,
-                negative_prompt: row.get(3)?,
-                model: row.get(4)?,
-                image_path: row.get(5)?,
-                thumbnail_path: row.get(6)?,
-                dimensions: row.get(7)?,
-                steps: row.get(8)?,
-                sampler: row.get(9)?,
-                cfg_scale: row.get(10)?,
-                seed: row.get(11)?,
-                tags: row.get(12)?,
-                is_favorite: row.get::<_, i32>(13)? != 0,
-                collection_id: row.get(14)?,
-                created_at: row.get(15)?,
-                updated_at: row.get(16)?,
-            })
-        })?;
+        let prompt_iter = stmt.query_map(rusqlite::params_from_iter(params.iter()), prompt_from_row)?;
 
         prompt_iter.collect()
     }
 
     pub fn get_prompt_by_id(&self, id: i32) -> Result<Prompt> {
         self.conn.query_row(
-            "SELECT * FROM prompts WHERE id = ?",
+            &format!("SELECT {} FROM prompts p WHERE id = ?", PROMPT_COLUMNS),
             params![id],
-            |row| {
-                Ok(Prompt {
-                    id: row.get(0)?,
-                    title: row.get(1)?,
-                    prompt_text: row.get(2)?,
-                    negative_prompt: row.get(3)?,
-                    model: row.get(4)?,
-                    image_path: row.get(5)?,
-                    thumbnail_path: row.get(6)?,
-                    dimensions: row.get(7)?,
-                    steps: row.get(8)?,
-                    sampler: row.get(9)?,
-                    cfg_scale: row.get(10)?,
-                    seed: row.get(11)?,
-                    tags: row.get(12)?,
-                    is_favorite: row.get::<_, i32>(13)? != 0,
-                    collection_id: row.get(14)?,
-                    created_at: row.get(15)?,
-                    updated_at: row.get(16)?,
-                })
-            },
+            prompt_from_row,
         )
     }
 
-    pub fn create_prompt(&self, prompt: &NewPrompt, image_path: Option<&str>, thumbnail_path: Option<&str>) -> Result<Prompt> {
-        let tags_json = serde_json::to_string(&prompt.tags.clone().unwrap_or_default())
-            .unwrap_or_else(|_| "[]".to_string());
-
-        self.conn.execute(
-            "INSERT INTO prompts (title, prompt_text, negative_prompt, model, image_path, thumbnail_path, 
-             dimensions, steps, sampler, cfg_scale, seed, tags, collection_id)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+    pub fn create_prompt(&self, prompt: &NewPrompt, images: &[NewPromptImage]) -> Result<Prompt> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO prompts (title, prompt_text, negative_prompt, model,
+             dimensions, steps, sampler, cfg_scale, seed, workflow_json, provenance, model_hash,
+             created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12,
+                     COALESCE(?13, CURRENT_TIMESTAMP))",
             params![
                 prompt.title,
                 prompt.prompt_text,
                 prompt.negative_prompt.as_deref().unwrap_or(""),
                 prompt.model,
-                image_path,
-                thumbnail_path,
                 prompt.dimensions.as_deref().unwrap_or("1:1"),
                 prompt.steps,
                 prompt.sampler,
                 prompt.cfg_scale,
                 prompt.seed,
-                tags_json,
-                prompt.collection_id,
+                prompt.workflow_json,
+                prompt.provenance.as_ref().map(|p| p.to_string()),
+                prompt.model_hash,
+                prompt.created_at,
             ],
         )?;
 
-        let id = self.conn.last_insert_rowid() as i32;
+        let id = tx.last_insert_rowid() as i32;
+        models::set_prompt_model(&tx, id, &prompt.model, !prompt.model_from_metadata)?;
+        images::insert_prompt_images(&tx, id, images)?;
+        tags::set_prompt_tags(&tx, id, prompt.tags.as_deref().unwrap_or_default())?;
+        let collection_ids: Vec<i32> = prompt
+            .collection_id
+            .into_iter()
+            .chain(prompt.collection_ids.iter().flatten().copied())
+            .collect();
+        collections::set_prompt_collections(&tx, id, &collection_ids)?;
+        revisions::record_revision(&tx, id)?;
+        tx.commit()?;
         self.get_prompt_by_id(id)
     }
 
     pub fn update_prompt(&self, id: i32, prompt: &UpdatePrompt) -> Result<Prompt> {
-        let existing = self.get_prompt_by_id(id)?;
-        
-        let tags_json = prompt.tags.as_ref()
-            .map(|t| serde_json::to_string(t).unwrap_or_else(|_| "[]".to_string()))
-            .unwrap_or(existing.tags);
-
-        self.conn.execute(
+        let tx = self.conn.unchecked_transaction()?;
+        let updated = tx.execute(
             "UPDATE prompts SET 
              title = COALESCE(?1, title),
              prompt_text = COALESCE(?2, prompt_text),
              negative_prompt = COALESCE(?3, negative_prompt),
-             model = COALESCE(?4, model),
-             dimensions = COALESCE(?5, dimensions),
-             steps = COALESCE(?6, steps),
-             sampler = COALESCE(?7, sampler),
-             cfg_scale = COALESCE(?8, cfg_scale),
-             seed = COALESCE(?9, seed),
-             tags = COALESCE(?10, tags),
-             is_favorite = COALESCE(?11, is_favorite),
-             collection_id = COALESCE(?12, collection_id),
+             dimensions = COALESCE(?4, dimensions),
+             steps = COALESCE(?5, steps),
+             sampler = COALESCE(?6, sampler),
+             cfg_scale = COALESCE(?7, cfg_scale),
+             seed = COALESCE(?8, seed),
+             is_favorite = COALESCE(?9, is_favorite),
+             rating = COALESCE(?10, rating),
              updated_at = CURRENT_TIMESTAMP
-             WHERE id = ?13",
+             WHERE id = ?11",
             params![
                 prompt.title,
                 prompt.prompt_text,
                 prompt.negative_prompt,
-                prompt.model,
                 prompt.dimensions,
                 prompt.steps,
                 prompt.sampler,
                 prompt.cfg_scale,
                 prompt.seed,
-                tags_json,
                 prompt.is_favorite.map(|f| if f { 1 } else { 0 }),
-                prompt.collection_id,
+                prompt.rating,
                 id,
             ],
         )?;
+        if updated == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+
+        if let Some(model) = &prompt.model {
+            models::set_prompt_model(&tx, id, model, true)?;
+        }
+        if let Some(tags) = &prompt.tags {
+            tags::set_prompt_tags(&tx, id, tags)?;
+        }
+        if let Some(collection_ids) = &prompt.collection_ids {
+            collections::set_prompt_collections(&tx, id, collection_ids)?;
+        }
+        revisions::record_revision(&tx, id)?;
+        tx.commit()?;
 
         self.get_prompt_by_id(id)
     }
 
+    /// Moves a prompt to the trash. Files stay on disk until it is purged.
     pub fn delete_prompt(&self, id: i32) -> Result<()> {
-        if let Ok(prompt) = self.get_prompt_by_id(id) {
-            if let Some(image_path) = prompt.image_path {
-                let _ = fs::remove_file(self.data_dir.join(&image_path));
-            }
-            if let Some(thumbnail_path) = prompt.thumbnail_path {
-                let _ = fs::remove_file(self.data_dir.join(&thumbnail_path));
-            }
-        }
-        
-        self.conn.execute("DELETE FROM prompts WHERE id = ?", params![id])?;
+        self.conn.execute(
+            "UPDATE prompts SET deleted_at = CURRENT_TIMESTAMP WHERE id = ? AND deleted_at IS NULL",
+            params![id],
+        )?;
         Ok(())
     }
 
@@ -341,99 +279,174 @@ impl Database {
         Ok(new_value)
     }
 
-    pub fn search_prompts(&self, query: &str) -> Result<Vec<Prompt>> {
-        let search_pattern = format!("%{}%", query);
+    /// Ranked full-text search. Bare words match as whole tokens except the
+    /// last one, which is treated as a prefix so results update while typing.
+    /// `"quoted phrases"` and explicit `word*` prefixes are honoured, as are
+    /// the field terms of `SearchQuery`. Only prompts matching `filter` are
+    /// searched; without words to rank by, results are newest first.
+    pub fn search_prompts(&self, query: &SearchQuery, filter: &PromptQuery) -> Result<Vec<SearchResult>> {
+        let (mut conditions, mut params) = self.query_conditions(filter)?;
+        conditions.push_str(&query.conditions);
+        params.extend(query.params.iter().cloned());
+
+        let Some(fts_query) = &query.fts else {
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT {} FROM prompts p WHERE deleted_at IS NULL{} ORDER BY created_at DESC",
+                PROMPT_COLUMNS, conditions
+            ))?;
+            let result_iter = stmt.query_map(rusqlite::params_from_iter(params.iter()), |row| {
+                Ok(SearchResult { prompt: prompt_from_row(row)?, snippet: String::new(), rank: 0.0 })
+            })?;
+            return result_iter.collect();
+        };
+        params.insert(0, Value::from(fts_query.clone()));
+
+        // bm25 weights follow the column order of prompts_fts:
+        // title, prompt_text, negative_prompt, tags, sampler.
         let mut stmt = self.conn.prepare(
-            "SELECT * FROM prompts WHERE 
-             title LIKE ?1 OR prompt_text LIKE ?1 OR tags LIKE ?1
-             ORDER BY created_at DESC"
+            &format!(
+                "SELECT {},
+                    snippet(prompts_fts, -1, '<mark>', '</mark>', '…', 16) AS snippet,
+                    bm25(prompts_fts, 10.0, 4.0, 1.0, 6.0, 1.0) AS rank
+             FROM prompts_fts
+             JOIN prompts p ON p.id = prompts_fts.rowid
+             WHERE prompts_fts MATCH ? AND p.deleted_at IS NULL{}
+             ORDER BY rank, p.created_at DESC",
+                PROMPT_COLUMNS, conditions
+            )
         )?;
-        
-        let prompt_iter = stmt.query_map(params![search_pattern], |row| {
-            Ok(Prompt {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                prompt_text: row.get(2)?,
-                negative_prompt: row.get(3)?,
-                model: row.get(4)?,
-                image_path: row.get(5)?,
-                thumbnail_path: row.get(6)?,
-                dimensions: row.get(7)?,
-                steps: row.get(8)?,
-                sampler: row.get(9)?,
-                cfg_scale: row.get(10)?,
-                seed: row.get(11)?,
-                tags: row.get(12)?,
-                is_favorite: row.get::<_, i32>(13)? != 0,
-                collection_id: row.get(14)?,
-                created_at: row.get(15)?,
-                updated_at: row.get(16)?,
+
+        let result_iter = stmt.query_map(rusqlite::params_from_iter(params.iter()), |row| {
+            Ok(SearchResult {
+                prompt: prompt_from_row(row)?,
+                snippet: row.get("snippet")?,
+                rank: row.get("rank")?,
             })
         })?;
 
-        prompt_iter.collect()
+        result_iter.collect()
     }
 
-    pub fn get_collections(&self) -> Result<Vec<Collection>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT c.*, COUNT(p.id) as prompt_count 
-             FROM collections c
-             LEFT JOIN prompts p ON c.id = p.collection_id
-             GROUP BY c.id
-             ORDER BY c.name"
-        )?;
-        
-        let coll_iter = stmt.query_map([], |row| {
-            Ok(Collection {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                icon: row.get(2)?,
-                color: row.get(3)?,
-                prompt_count: row.get(4)?,
-            })
-        })?;
-
-        coll_iter.collect()
+    pub fn get_prompt_workflow(&self, id: i32) -> Result<Option<String>> {
+        self.conn.query_row(
+            "SELECT workflow_json FROM prompts WHERE id = ?",
+            params![id],
+            |row| row.get(0),
+        )
     }
 
-    pub fn create_collection(&self, name: &str) -> Result<Collection> {
-        let colors = ["#8B5CF6", "#10B981", "#F59E0B", "#3B82F6", "#EC4899", "#EF4444"];
-        let color = colors[name.len() % colors.len()];
+    pub fn get_data_dir(&self) -> &PathBuf {
+        &self.data_dir
+    }
+}
 
-        self.conn.execute(
-            "INSERT INTO collections (name, color) VALUES (?1, ?2)",
-            params![name, color],
-        )?;
+/// Select list for `Prompt` rows; the table must be aliased as `p`.
+const PROMPT_COLUMNS: &str = "p.*,
+    (SELECT json_group_array(cp.collection_id) FROM collection_prompts cp
+     WHERE cp.prompt_id = p.id) AS collection_ids,
+    (SELECT json_group_array(json_object(
+        'id', pi.id, 'prompt_id', pi.prompt_id, 'image_path', pi.image_path,
+        'thumbnail_path', pi.thumbnail_path, 'position', pi.position,
+        'is_cover', json(CASE WHEN pi.is_cover THEN 'true' ELSE 'false' END),
+        'seed', pi.seed, 'width', pi.width, 'height', pi.height,
+        'content_hash', pi.content_hash, 'perceptual_hash', pi.perceptual_hash,
+        'created_at', pi.created_at))
+     FROM (SELECT * FROM prompt_images WHERE prompt_id = p.id ORDER BY position, id) pi) AS images";
+
+fn prompt_from_row(row: &Row) -> Result<Prompt> {
+    Ok(Prompt {
+        id: row.get("id")?,
+        title: row.get("title")?,
+        prompt_text: row.get("prompt_text")?,
+        negative_prompt: row.get("negative_prompt")?,
+        model: row.get("model")?,
+        model_id: row.get("model_id")?,
+        model_hash: row.get("model_hash")?,
+        image_path: row.get("image_path")?,
+        thumbnail_path: row.get("thumbnail_path")?,
+        images: row
+            .get::<_, Option<String>>("images")?
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default(),
+        dimensions: row.get("dimensions")?,
+        steps: row.get("steps")?,
+        sampler: row.get("sampler")?,
+        cfg_scale: row.get("cfg_scale")?,
+        seed: row.get("seed")?,
+        tags: row
+            .get::<_, Option<String>>("tags")?
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default(),
+        is_favorite: row.get::<_, i32>("is_favorite")? != 0,
+        rating: row.get("rating")?,
+        has_workflow: row.get::<_, Option<String>>("workflow_json")?.is_some(),
+        provenance: row
+            .get::<_, Option<String>>("provenance")?
+            .and_then(|json| serde_json::from_str(&json).ok()),
+        collection_ids: row
+            .get::<_, Option<String>>("collection_ids")?
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default(),
+        created_at: row.get("created_at")?,
+        updated_at: row.get("updated_at")?,
+        deleted_at: row.get("deleted_at")?,
+    })
+}
 
-        let id = self.conn.last_insert_rowid() as i32;
-        
-        Ok(Collection {
-            id,
-            name: name.to_string(),
-            icon: "folder".to_string(),
-            color: color.to_string(),
-            prompt_count: 0,
-        })
-    }
+/// Turns free text typed in the search box into an FTS5 MATCH expression.
+/// Every term is quoted so FTS5 operators and punctuation in the input can
+/// never produce a syntax error. Returns `None` when there is nothing to
+/// search for.
+fn build_fts_query(input: &str) -> Option<String> {
+    let mut terms = Vec::new();
+    let mut chars = input.chars().peekable();
+    let mut last_is_open_word = false;
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            last_is_open_word = false;
+            continue;
+        }
 
-    pub fn get_models(&self) -> Result<Vec<Model>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT * FROM models WHERE is_active = 1 ORDER BY name"
-        )?;
-        
-        let model_iter = stmt.query_map([], |row| {
-            Ok(Model {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                short_name: row.get(2)?,
-                is_active: row.get::<_, i32>(3)? != 0,
-            })
-        })?;
+        if c == '"' {
+            chars.next();
+            let phrase: String = chars.by_ref().take_while(|&c| c != '"').collect();
+            if !phrase.trim().is_empty() {
+                terms.push(format!("\"{}\"", phrase.trim().replace('"', "\"\"")));
+            }
+            last_is_open_word = false;
+            continue;
+        }
 
-        model_iter.collect()
+        let mut word = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() || c == '"' {
+                break;
+            }
+            word.push(c);
+            chars.next();
+        }
+        let explicit_prefix = word.ends_with('*');
+        let word = word.trim_end_matches('*');
+        if word.is_empty() {
+            continue;
+        }
+        let mut term = format!("\"{}\"", word.replace('"', "\"\""));
+        if explicit_prefix {
+            term.push('*');
+        }
+        terms.push(term);
+        last_is_open_word = !explicit_prefix;
     }
 
-    pub fn get_data_dir(&self) -> &PathBuf {
-        &self.data_dir
+    if terms.is_empty() {
+        return None;
+    }
+    if last_is_open_word {
+        if let Some(last) = terms.last_mut() {
+            last.push('*');
+        }
     }
+    Some(terms.join(" "))
 }
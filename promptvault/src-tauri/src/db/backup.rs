@@ -0,0 +1,106 @@
+use super::{migrations, Database};
+use rusqlite::{Connection, DatabaseName};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// What a vault consists of, relative to the data dir. Restores swap these
+/// and nothing else; `backups/` stays where it is.
+pub const VAULT_ENTRIES: [&str; 3] = [DB_FILE, "images", "thumbnails"];
+pub const DB_FILE: &str = "promptvault.db";
+
+impl Database {
+    /// Copies the live database to `dest` with SQLite's online backup API,
+    /// which gives a consistent snapshot without closing the connection.
+    pub fn snapshot_database(&self, dest: &Path) -> rusqlite::Result<()> {
+        self.conn.backup(DatabaseName::Main, dest, None)
+    }
+
+    pub fn schema_version(&self) -> rusqlite::Result<i32> {
+        self.conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+    }
+
+    /// Replaces the vault with the one extracted to `staging` (a directory
+    /// holding `VAULT_ENTRIES`). The restored database is opened and migrated
+    /// while still staged; then the current files are moved aside and put
+    /// back if anything fails, reopening the swapped-in database included.
+    pub fn swap_vault(&mut self, staging: &Path) -> Result<(), Box<dyn Error>> {
+        // A backup whose database can't be opened never replaces anything.
+        if staging.join(DB_FILE).exists() {
+            open_connection(staging)
+                .map_err(|e| format!("The backup's database cannot be opened: {}", e))?;
+        }
+
+        let previous = self.data_dir.join(format!(
+            ".pre-restore-{}",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        ));
+        fs::create_dir_all(&previous)?;
+
+        // Close the connection so the file can be moved on every platform.
+        self.conn = Connection::open_in_memory()?;
+
+        let mut moved_out = Vec::new();
+        let mut moved_in = Vec::new();
+        let result = (|| -> std::io::Result<()> {
+            for entry in VAULT_ENTRIES {
+                let current = self.data_dir.join(entry);
+                if current.exists() {
+                    fs::rename(&current, previous.join(entry))?;
+                    moved_out.push(entry);
+                }
+            }
+            for entry in VAULT_ENTRIES {
+                let staged = staging.join(entry);
+                if staged.exists() {
+                    fs::rename(&staged, self.data_dir.join(entry))?;
+                    moved_in.push(entry);
+                } else {
+                    fs::create_dir_all(self.data_dir.join(entry))?;
+                }
+            }
+            Ok(())
+        })()
+        .map_err(Box::<dyn Error>::from)
+        .and_then(|()| {
+            self.conn = open_connection(&self.data_dir)?;
+            Ok(())
+        });
+
+        if let Err(e) = result {
+            for entry in moved_in {
+                let _ = fs::rename(self.data_dir.join(entry), staging.join(entry));
+            }
+            for entry in moved_out {
+                remove_entry(&self.data_dir.join(entry));
+                let _ = fs::rename(previous.join(entry), self.data_dir.join(entry));
+            }
+            self.conn = open_connection(&self.data_dir)?;
+            // Only gone if everything was put back.
+            let _ = fs::remove_dir(&previous);
+            return Err(format!("Could not swap in the backup: {}", e).into());
+        }
+
+        // The new vault is open; the old one can go.
+        let _ = fs::remove_dir_all(&previous);
+        Ok(())
+    }
+}
+
+/// Opens `promptvault.db` in `data_dir`, bringing its schema up to date.
+pub(crate) fn open_connection(data_dir: &Path) -> Result<Connection, Box<dyn Error>> {
+    let mut conn = Connection::open(data_dir.join(DB_FILE))?;
+    migrations::run(&mut conn, &data_dir.join("backups"))?;
+    conn.pragma_update(None, "foreign_keys", true)?;
+    Ok(conn)
+}
+
+/// Deletes a file or a directory tree, ignoring errors.
+fn remove_entry(path: &Path) {
+    if path.is_dir() {
+        let _ = fs::remove_dir_all(path);
+    } else {
+        let _ = fs::remove_file(path);
+    }
+}
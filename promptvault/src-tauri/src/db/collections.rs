@@ -0,0 +1,437 @@
+use super::{Database, SearchQuery};
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+const COLLECTION_COLORS: [&str; 6] = ["#8B5CF6", "#10B981", "#F59E0B", "#3B82F6", "#EC4899", "#EF4444"];
+
+#[derive(Debug, Serialize)]
+pub struct Collection {
+    pub id: i32,
+    pub name: String,
+    pub icon: String,
+    pub color: String,
+    pub parent_id: Option<i32>,
+    pub sort_order: i32,
+    /// Prompts directly in this collection.
+    pub prompt_count: i32,
+    /// Distinct prompts in this collection and all of its descendants.
+    pub total_prompt_count: i32,
+    /// Search query picking the prompts of a smart collection, in the
+    /// search box syntax; `None` for collections filled by hand.
+    pub query: Option<String>,
+    pub children: Vec<Collection>,
+}
+
+/// Recursive CTE `subtree(root_id, id)` pairing every collection with itself
+/// and each of its descendants. UNION (not UNION ALL) keeps it finite even if
+/// the table somehow contains a cycle.
+const SUBTREE_CTE: &str = "WITH RECURSIVE subtree(root_id, id) AS (
+        SELECT id, id FROM collections
+        UNION
+        SELECT s.root_id, c.id FROM collections c JOIN subtree s ON c.parent_id = s.id
+    )";
+
+impl Database {
+    /// All collections as a tree: top-level collections with their
+    /// descendants nested in `children`, siblings in manual order.
+    pub fn get_collections(&self) -> Result<Vec<Collection>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT *, 0 AS prompt_count FROM collections ORDER BY sort_order, name",
+        )?;
+        let mut flat = stmt
+            .query_map([], collection_from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        // Prompts of every collection in one statement: hand-picked ones,
+        // plus those each smart collection's query matches.
+        let mut sql = String::from(
+            "SELECT cp.collection_id, p.id FROM collection_prompts cp
+             JOIN collections c ON c.id = cp.collection_id AND c.query IS NULL
+             JOIN prompts p ON p.id = cp.prompt_id AND p.deleted_at IS NULL",
+        );
+        let mut params = Vec::new();
+        for collection in &flat {
+            let Some(query) = &collection.query else { continue };
+            // As in `collection_conditions`, a query that no longer parses
+            // matches nothing.
+            if let Ok(search) = SearchQuery::parse_saved(query) {
+                let (conditions, query_params) = search.to_conditions();
+                sql.push_str(&format!(
+                    " UNION SELECT ?, p.id FROM prompts p WHERE deleted_at IS NULL{}",
+                    conditions
+                ));
+                params.push(Value::from(collection.id));
+                params.extend(query_params);
+            }
+        }
+        let mut members: HashMap<i32, HashSet<i32>> = HashMap::new();
+        let mut stmt = self.conn.prepare(&sql)?;
+        let mut rows = stmt.query(rusqlite::params_from_iter(params.iter()))?;
+        while let Some(row) = rows.next()? {
+            members.entry(row.get(0)?).or_default().insert(row.get(1)?);
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare(&format!("{} SELECT root_id, id FROM subtree", SUBTREE_CTE))?;
+        let mut subtree_members: HashMap<i32, HashSet<i32>> = HashMap::new();
+        for pair in stmt.query_map([], |row| Ok((row.get::<_, i32>(0)?, row.get::<_, i32>(1)?)))? {
+            let (root_id, id) = pair?;
+            if let Some(prompts) = members.get(&id) {
+                subtree_members.entry(root_id).or_default().extend(prompts);
+            }
+        }
+        let totals: HashMap<i32, i32> = subtree_members
+            .into_iter()
+            .map(|(id, prompts)| (id, prompts.len() as i32))
+            .collect();
+        for collection in flat.iter_mut() {
+            collection.prompt_count = members.get(&collection.id).map_or(0, |p| p.len() as i32);
+        }
+
+        Ok(build_tree(flat, &totals))
+    }
+
+    /// A single collection with its counts. `children` is left empty; the
+    /// tree comes from `get_collections`.
+    pub fn get_collection(&self, id: i32) -> Result<Collection> {
+        let mut collection = self.conn.query_row(
+            "SELECT *, 0 AS prompt_count FROM collections WHERE id = ?",
+            params![id],
+            collection_from_row,
+        )?;
+        collection.prompt_count = self.count_collection_prompts(&[id])?;
+        collection.total_prompt_count =
+            self.count_collection_prompts(&self.collection_subtree_ids(id)?)?;
+        Ok(collection)
+    }
+
+    /// Ids of a collection and all of its descendants.
+    pub fn collection_subtree_ids(&self, id: i32) -> Result<Vec<i32>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{} SELECT id FROM subtree WHERE root_id = ?",
+            SUBTREE_CTE
+        ))?;
+        let id_iter = stmt.query_map(params![id], |row| row.get(0))?;
+        id_iter.collect()
+    }
+
+    /// The `AND …` condition selecting, from `prompts p`, the prompts in any
+    /// of `collection_ids`: those added by hand and those matched by the
+    /// query of a smart collection.
+    pub(crate) fn collection_conditions(&self, collection_ids: &[i32]) -> Result<(String, Vec<Value>)> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, query FROM collections WHERE id IN (SELECT value FROM json_each(?))",
+        )?;
+        let collections = stmt
+            .query_map(params![json_ids(collection_ids)], |row| {
+                Ok((row.get::<_, i32>(0)?, row.get::<_, Option<String>>(1)?))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut manual = Vec::new();
+        let mut smart = String::new();
+        let mut params = Vec::new();
+        for (id, query) in collections {
+            let Some(query) = query else {
+                manual.push(id);
+                continue;
+            };
+            // Queries are checked when saved; one that no longer parses
+            // matches nothing rather than everything.
+            if let Ok(search) = SearchQuery::parse_saved(&query) {
+                let (conditions, query_params) = search.to_conditions();
+                smart.push_str(&format!(" OR (1{})", conditions));
+                params.extend(query_params);
+            }
+        }
+        params.insert(0, Value::from(json_ids(&manual)));
+        let sql = format!(
+            " AND (p.id IN (SELECT prompt_id FROM collection_prompts
+              WHERE collection_id IN (SELECT value FROM json_each(?))){})",
+            smart
+        );
+        Ok((sql, params))
+    }
+
+    fn count_collection_prompts(&self, collection_ids: &[i32]) -> Result<i32> {
+        let (conditions, params) = self.collection_conditions(collection_ids)?;
+        self.conn.query_row(
+            &format!("SELECT COUNT(*) FROM prompts p WHERE deleted_at IS NULL{}", conditions),
+            rusqlite::params_from_iter(params.iter()),
+            |row| row.get(0),
+        )
+    }
+
+    /// Makes a collection smart, or changes the query of a smart one. The
+    /// query must parse, see `SearchQuery`.
+    pub fn set_collection_query(&self, id: i32, query: &str) -> Result<Collection> {
+        self.conn.execute(
+            "UPDATE collections SET query = ?1 WHERE id = ?2",
+            params![query.trim(), id],
+        )?;
+        self.get_collection(id)
+    }
+
+    /// Turns a smart collection into a manual one holding the prompts its
+    /// query matches right now.
+    pub fn convert_to_static_collection(&self, id: i32) -> Result<Collection> {
+        let (conditions, mut params) = self.collection_conditions(&[id])?;
+        params.insert(0, Value::from(id));
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM collection_prompts WHERE collection_id = ?", params![id])?;
+        tx.execute(
+            &format!(
+                "INSERT OR IGNORE INTO collection_prompts (collection_id, prompt_id)
+                 SELECT ?, p.id FROM prompts p WHERE deleted_at IS NULL{}",
+                conditions
+            ),
+            rusqlite::params_from_iter(params.iter()),
+        )?;
+        tx.execute("UPDATE collections SET query = NULL WHERE id = ?", params![id])?;
+        tx.commit()?;
+        self.get_collection(id)
+    }
+
+    /// Moves a collection under `parent_id` (or to the top level), placing it
+    /// after its new siblings. A move into its own subtree, which would make
+    /// a cycle, is ignored; callers should report it, see
+    /// `collection_subtree_ids`.
+    pub fn move_collection(&self, id: i32, parent_id: Option<i32>) -> Result<Collection> {
+        self.conn.execute(
+            &format!(
+                "{}
+                 UPDATE collections SET parent_id = ?1,
+                 sort_order = (SELECT COALESCE(MAX(sort_order), -1) + 1 FROM collections
+                               WHERE parent_id IS ?1 AND id <> ?2)
+                 WHERE id = ?2
+                   AND (?1 IS NULL OR ?1 NOT IN (SELECT id FROM subtree WHERE root_id = ?2))",
+                SUBTREE_CTE
+            ),
+            params![parent_id, id],
+        )?;
+        self.get_collection(id)
+    }
+
+    pub fn find_collection_id(&self, name: &str) -> Result<Option<i32>> {
+        self.conn
+            .query_row(
+                "SELECT id FROM collections WHERE name = ?",
+                params![name.trim()],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    /// Creates a collection after its siblings in the manual order. Without
+    /// an explicit color one is picked from the palette.
+    pub fn create_collection(
+        &self,
+        name: &str,
+        color: Option<&str>,
+        icon: Option<&str>,
+        parent_id: Option<i32>,
+    ) -> Result<Collection> {
+        let color = color.unwrap_or(COLLECTION_COLORS[name.len() % COLLECTION_COLORS.len()]);
+
+        self.conn.execute(
+            "INSERT INTO collections (name, color, icon, parent_id, sort_order)
+             VALUES (?1, ?2, ?3, ?4, (SELECT COALESCE(MAX(sort_order), -1) + 1 FROM collections
+                                      WHERE parent_id IS ?4))",
+            params![name.trim(), color, icon.unwrap_or("folder"), parent_id],
+        )?;
+
+        let id = self.conn.last_insert_rowid() as i32;
+        self.get_collection(id)
+    }
+
+    pub fn rename_collection(&self, id: i32, name: &str) -> Result<Collection> {
+        self.conn.execute(
+            "UPDATE collections SET name = ?1 WHERE id = ?2",
+            params![name.trim(), id],
+        )?;
+        self.get_collection(id)
+    }
+
+    pub fn set_collection_color(&self, id: i32, color: &str) -> Result<Collection> {
+        self.conn.execute(
+            "UPDATE collections SET color = ?1 WHERE id = ?2",
+            params![color, id],
+        )?;
+        self.get_collection(id)
+    }
+
+    pub fn set_collection_icon(&self, id: i32, icon: &str) -> Result<Collection> {
+        self.conn.execute(
+            "UPDATE collections SET icon = ?1 WHERE id = ?2",
+            params![icon, id],
+        )?;
+        self.get_collection(id)
+    }
+
+    /// Persists the manual order of siblings: `ids[0]` becomes the first
+    /// child of their parent. Siblings missing from `ids` keep their relative
+    /// order after them. All `ids` must share a parent.
+    pub fn reorder_collections(&self, ids: &[i32]) -> Result<Vec<Collection>> {
+        let Some(first) = ids.first() else {
+            return self.get_collections();
+        };
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "UPDATE collections SET sort_order = sort_order + ?1
+             WHERE parent_id IS (SELECT parent_id FROM collections WHERE id = ?2)",
+            params![ids.len() as i64, first],
+        )?;
+        for (position, id) in ids.iter().enumerate() {
+            tx.execute(
+                "UPDATE collections SET sort_order = ?1 WHERE id = ?2",
+                params![position as i64, id],
+            )?;
+        }
+        tx.commit()?;
+        self.get_collections()
+    }
+
+    /// Deletes a collection. Its prompts (trashed ones included) are added to
+    /// `move_to` when given; otherwise they just lose this membership. Only
+    /// prompts added by hand move, so a smart collection moves none. Child
+    /// collections move up to the deleted collection's parent.
+    pub fn delete_collection(&self, id: i32, move_to: Option<i32>) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "UPDATE collections SET parent_id = (SELECT parent_id FROM collections WHERE id = ?1)
+             WHERE parent_id = ?1",
+            params![id],
+        )?;
+        if let Some(move_to) = move_to {
+            tx.execute(
+                "INSERT OR IGNORE INTO collection_prompts (collection_id, prompt_id)
+                 SELECT ?1, prompt_id FROM collection_prompts WHERE collection_id = ?2",
+                params![move_to, id],
+            )?;
+        }
+        tx.execute("DELETE FROM collections WHERE id = ?", params![id])?;
+        tx.commit()
+    }
+
+    pub fn add_prompts_to_collection(&self, collection_id: i32, prompt_ids: &[i32]) -> Result<Collection> {
+        let tx = self.conn.unchecked_transaction()?;
+        for prompt_id in prompt_ids {
+            tx.execute(
+                "INSERT OR IGNORE INTO collection_prompts (collection_id, prompt_id) VALUES (?1, ?2)",
+                params![collection_id, prompt_id],
+            )?;
+        }
+        tx.commit()?;
+        self.get_collection(collection_id)
+    }
+
+    pub fn remove_prompts_from_collection(&self, collection_id: i32, prompt_ids: &[i32]) -> Result<Collection> {
+        let tx = self.conn.unchecked_transaction()?;
+        for prompt_id in prompt_ids {
+            tx.execute(
+                "DELETE FROM collection_prompts WHERE collection_id = ?1 AND prompt_id = ?2",
+                params![collection_id, prompt_id],
+            )?;
+        }
+        tx.commit()?;
+        self.get_collection(collection_id)
+    }
+
+    /// Moves prompts from one collection to another, leaving their other
+    /// memberships untouched.
+    pub fn move_prompts_to_collection(&self, from_id: i32, to_id: i32, prompt_ids: &[i32]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for prompt_id in prompt_ids {
+            tx.execute(
+                "DELETE FROM collection_prompts WHERE collection_id = ?1 AND prompt_id = ?2",
+                params![from_id, prompt_id],
+            )?;
+            tx.execute(
+                "INSERT OR IGNORE INTO collection_prompts (collection_id, prompt_id) VALUES (?1, ?2)",
+                params![to_id, prompt_id],
+            )?;
+        }
+        tx.commit()
+    }
+}
+
+fn collection_from_row(row: &Row) -> Result<Collection> {
+    Ok(Collection {
+        id: row.get("id")?,
+        name: row.get("name")?,
+        icon: row.get("icon")?,
+        color: row.get("color")?,
+        parent_id: row.get("parent_id")?,
+        sort_order: row.get("sort_order")?,
+        prompt_count: row.get("prompt_count")?,
+        total_prompt_count: 0,
+        query: row.get("query")?,
+        children: Vec::new(),
+    })
+}
+
+/// Nests a flat, ordered list of collections under their parents. A
+/// collection whose parent is missing, or that is part of a `parent_id`
+/// cycle, is treated as top-level so it never goes missing.
+fn build_tree(flat: Vec<Collection>, totals: &HashMap<i32, i32>) -> Vec<Collection> {
+    let parents: HashMap<i32, Option<i32>> = flat.iter().map(|c| (c.id, c.parent_id)).collect();
+    let in_cycle = |id: i32| {
+        let mut seen = HashSet::new();
+        let mut current = parents.get(&id).copied().flatten();
+        while let Some(ancestor) = current {
+            if ancestor == id {
+                return true;
+            }
+            if !seen.insert(ancestor) {
+                return false;
+            }
+            current = parents.get(&ancestor).copied().flatten();
+        }
+        false
+    };
+
+    let mut by_parent: HashMap<Option<i32>, Vec<Collection>> = HashMap::new();
+    for mut collection in flat {
+        collection.total_prompt_count = totals.get(&collection.id).copied().unwrap_or(0);
+        let parent = collection
+            .parent_id
+            .filter(|p| parents.contains_key(p) && !in_cycle(collection.id));
+        by_parent.entry(parent).or_default().push(collection);
+    }
+
+    fn attach(nodes: Vec<Collection>, by_parent: &mut HashMap<Option<i32>, Vec<Collection>>) -> Vec<Collection> {
+        nodes
+            .into_iter()
+            .map(|mut node| {
+                let children = by_parent.remove(&Some(node.id)).unwrap_or_default();
+                node.children = attach(children, by_parent);
+                node
+            })
+            .collect()
+    }
+
+    let roots = by_parent.remove(&None).unwrap_or_default();
+    attach(roots, &mut by_parent)
+}
+
+fn json_ids(ids: &[i32]) -> String {
+    serde_json::to_string(ids).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// Replaces all collection memberships of a prompt.
+pub(crate) fn set_prompt_collections(conn: &Connection, prompt_id: i32, collection_ids: &[i32]) -> Result<()> {
+    conn.execute(
+        "DELETE FROM collection_prompts WHERE prompt_id = ?",
+        params![prompt_id],
+    )?;
+    for collection_id in collection_ids {
+        conn.execute(
+            "INSERT OR IGNORE INTO collection_prompts (collection_id, prompt_id) VALUES (?1, ?2)",
+            params![collection_id, prompt_id],
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,435 @@
+use super::{Database, Prompt};
+use crate::image_store::remove_image_files;
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PromptImage {
+    pub id: i32,
+    pub prompt_id: i32,
+    pub image_path: String,
+    pub thumbnail_path: Option<String>,
+    pub position: i32,
+    pub is_cover: bool,
+    pub seed: Option<String>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    /// SHA-256 of the file; `None` for images stored before hashing existed
+    /// until a dedupe scan fills it in.
+    pub content_hash: Option<String>,
+    /// dHash of the picture, for finding similar images.
+    pub perceptual_hash: Option<i64>,
+    pub created_at: String,
+}
+
+/// Prompts that contain the same image file.
+#[derive(Debug, Serialize)]
+pub struct DuplicateGroup {
+    pub content_hash: String,
+    pub prompt_ids: Vec<i32>,
+}
+
+#[derive(Debug, Default)]
+pub struct NewPromptImage {
+    pub image_path: String,
+    pub thumbnail_path: Option<String>,
+    pub seed: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// File the image was imported from, for folder imports.
+    pub source_path: Option<String>,
+    pub content_hash: Option<String>,
+    pub perceptual_hash: Option<i64>,
+}
+
+impl Database {
+    /// Whether a file has been imported before, judged by its original path.
+    /// Prompts in the trash don't count, so their files can be imported again.
+    pub fn is_source_imported(&self, source_path: &str) -> Result<bool> {
+        self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM prompt_images i
+             JOIN prompts p ON p.id = i.prompt_id
+             WHERE i.source_path = ? AND p.deleted_at IS NULL)",
+            params![source_path],
+            |row| row.get(0),
+        )
+    }
+
+    /// An image with the given content hash, preferring prompts that are not
+    /// in the trash.
+    pub fn find_image_by_hash(&self, content_hash: &str) -> Result<Option<PromptImage>> {
+        self.conn
+            .query_row(
+                "SELECT i.* FROM prompt_images i
+                 JOIN prompts p ON p.id = i.prompt_id
+                 WHERE i.content_hash = ?
+                 ORDER BY p.deleted_at IS NOT NULL, i.id
+                 LIMIT 1",
+                params![content_hash],
+                image_from_row,
+            )
+            .optional()
+    }
+
+    /// Prompts outside the trash with an image of the given content hash.
+    pub fn find_prompts_with_image(&self, content_hash: &str) -> Result<Vec<Prompt>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT DISTINCT i.prompt_id FROM prompt_images i
+             JOIN prompts p ON p.id = i.prompt_id
+             WHERE i.content_hash = ? AND p.deleted_at IS NULL
+             ORDER BY i.prompt_id",
+        )?;
+        let ids = stmt
+            .query_map(params![content_hash], |row| row.get::<_, i32>(0))?
+            .collect::<Result<Vec<_>>>()?;
+        ids.into_iter().map(|id| self.get_prompt_by_id(id)).collect()
+    }
+
+    /// Images stored before content hashes were recorded, as `(id, image_path)`.
+    pub fn get_unhashed_images(&self) -> Result<Vec<(i32, String)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, image_path FROM prompt_images WHERE content_hash IS NULL")?;
+        let image_iter = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        image_iter.collect()
+    }
+
+    /// Records hashes computed from `(id, image_path, content_hash)`. An
+    /// image whose file was replaced or hashed in the meantime is left alone.
+    pub fn set_image_hashes(&self, hashes: &[(i32, String, String)]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for (id, image_path, content_hash) in hashes {
+            tx.execute(
+                "UPDATE prompt_images SET content_hash = ?1
+                 WHERE id = ?2 AND image_path = ?3 AND content_hash IS NULL",
+                params![content_hash, id, image_path],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// Points every image at one copy of its file per content hash and
+    /// deletes the copies nothing refers to any more. Returns how many files
+    /// were removed and how many bytes that freed.
+    pub fn merge_duplicate_images(&self) -> Result<(usize, u64)> {
+        let mut stmt = self.conn.prepare(
+            "SELECT * FROM prompt_images WHERE content_hash IN (
+                SELECT content_hash FROM prompt_images
+                WHERE content_hash IS NOT NULL
+                GROUP BY content_hash HAVING COUNT(DISTINCT image_path) > 1
+             )
+             ORDER BY content_hash, id",
+        )?;
+        let images = stmt
+            .query_map([], image_from_row)?
+            .collect::<Result<Vec<_>>>()?;
+        let mut groups: HashMap<String, Vec<PromptImage>> = HashMap::new();
+        for image in images {
+            if let Some(content_hash) = image.content_hash.clone() {
+                groups.entry(content_hash).or_default().push(image);
+            }
+        }
+
+        let mut replaced = Vec::new();
+        let tx = self.conn.unchecked_transaction()?;
+        for images in groups.values() {
+            // Keep the oldest copy that is still on disk.
+            let Some(kept) = images
+                .iter()
+                .find(|image| self.data_dir.join(&image.image_path).is_file())
+            else {
+                continue;
+            };
+            for image in images.iter().filter(|i| i.image_path != kept.image_path) {
+                tx.execute(
+                    "UPDATE prompt_images SET image_path = ?1, thumbnail_path = ?2 WHERE id = ?3",
+                    params![kept.image_path, kept.thumbnail_path, image.id],
+                )?;
+                refresh_cover(&tx, image.prompt_id)?;
+                replaced.push((image.image_path.clone(), image.thumbnail_path.clone()));
+            }
+        }
+        tx.commit()?;
+
+        let mut removed = 0;
+        let mut freed = 0;
+        for (image_path, thumbnail_path) in replaced {
+            let size = fs::metadata(self.data_dir.join(&image_path)).map_or(0, |m| m.len());
+            if self.remove_unused_image_files(&image_path, thumbnail_path.as_deref())? {
+                removed += 1;
+                freed += size;
+            }
+        }
+        Ok((removed, freed))
+    }
+
+    /// Content hashes that appear in more than one prompt outside the trash.
+    pub fn get_duplicate_groups(&self) -> Result<Vec<DuplicateGroup>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT i.content_hash, GROUP_CONCAT(DISTINCT i.prompt_id) FROM prompt_images i
+             JOIN prompts p ON p.id = i.prompt_id
+             WHERE i.content_hash IS NOT NULL AND p.deleted_at IS NULL
+             GROUP BY i.content_hash HAVING COUNT(DISTINCT i.prompt_id) > 1
+             ORDER BY MIN(i.id)",
+        )?;
+        let group_iter = stmt.query_map([], |row| {
+            let ids: String = row.get(1)?;
+            let mut prompt_ids: Vec<i32> = ids.split(',').filter_map(|id| id.parse().ok()).collect();
+            prompt_ids.sort_unstable();
+            Ok(DuplicateGroup {
+                content_hash: row.get(0)?,
+                prompt_ids,
+            })
+        })?;
+        group_iter.collect()
+    }
+
+    /// Whether any image, trashed prompts included, points at `image_path`.
+    pub fn is_image_path_used(&self, image_path: &str) -> Result<bool> {
+        self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM prompt_images WHERE image_path = ?)",
+            params![image_path],
+            |row| row.get(0),
+        )
+    }
+
+    /// Deletes the files of an image unless another image still uses them.
+    /// Returns whether the original was deleted.
+    pub(crate) fn remove_unused_image_files(
+        &self,
+        image_path: &str,
+        thumbnail_path: Option<&str>,
+    ) -> Result<bool> {
+        let in_use = |column: &str, path: &str| -> Result<bool> {
+            self.conn.query_row(
+                &format!(
+                    "SELECT EXISTS(SELECT 1 FROM prompt_images WHERE {} = ?)",
+                    column
+                ),
+                params![path],
+                |row| row.get(0),
+            )
+        };
+        if in_use("image_path", image_path)? {
+            return Ok(false);
+        }
+        let thumbnail_path = match thumbnail_path {
+            Some(thumbnail) if in_use("thumbnail_path", thumbnail)? => None,
+            other => other,
+        };
+        remove_image_files(&self.data_dir, image_path, thumbnail_path);
+        Ok(true)
+    }
+
+    /// Images of a prompt in display order.
+    pub fn get_prompt_images(&self, prompt_id: i32) -> Result<Vec<PromptImage>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT * FROM prompt_images WHERE prompt_id = ? ORDER BY position, id",
+        )?;
+        let image_iter = stmt.query_map(params![prompt_id], image_from_row)?;
+        image_iter.collect()
+    }
+
+    pub fn get_prompt_image(&self, id: i32) -> Result<PromptImage> {
+        self.conn.query_row(
+            "SELECT * FROM prompt_images WHERE id = ?",
+            params![id],
+            image_from_row,
+        )
+    }
+
+    /// Appends images to a prompt. The first image of a prompt without
+    /// images becomes its cover.
+    pub fn add_prompt_images(&self, prompt_id: i32, images: &[NewPromptImage]) -> Result<Prompt> {
+        let tx = self.conn.unchecked_transaction()?;
+        insert_prompt_images(&tx, prompt_id, images)?;
+        touch_prompt(&tx, prompt_id)?;
+        tx.commit()?;
+        self.get_prompt_by_id(prompt_id)
+    }
+
+    /// Removes an image and its files. If it was the cover, the next image
+    /// in order takes its place.
+    pub fn remove_prompt_image(&self, id: i32) -> Result<Prompt> {
+        let image = self.get_prompt_image(id)?;
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM prompt_images WHERE id = ?", params![id])?;
+        refresh_cover(&tx, image.prompt_id)?;
+        touch_prompt(&tx, image.prompt_id)?;
+        tx.commit()?;
+
+        self.remove_unused_image_files(&image.image_path, image.thumbnail_path.as_deref())?;
+        self.get_prompt_by_id(image.prompt_id)
+    }
+
+    pub fn set_prompt_cover_image(&self, id: i32) -> Result<Prompt> {
+        let image = self.get_prompt_image(id)?;
+
+        let tx = self.conn.unchecked_transaction()?;
+        set_cover(&tx, image.prompt_id, id)?;
+        touch_prompt(&tx, image.prompt_id)?;
+        tx.commit()?;
+
+        self.get_prompt_by_id(image.prompt_id)
+    }
+
+    /// Adds an image to a prompt and makes it the cover.
+    pub fn attach_prompt_image(&self, prompt_id: i32, image: &NewPromptImage) -> Result<Prompt> {
+        let tx = self.conn.unchecked_transaction()?;
+        insert_prompt_images(&tx, prompt_id, std::slice::from_ref(image))?;
+        let id = tx.last_insert_rowid() as i32;
+        set_cover(&tx, prompt_id, id)?;
+        touch_prompt(&tx, prompt_id)?;
+        tx.commit()?;
+        self.get_prompt_by_id(prompt_id)
+    }
+
+    /// Swaps the file behind an image, keeping its place and cover status.
+    /// The old files are deleted once the change is saved, unless another
+    /// image shares them.
+    pub fn replace_prompt_image(&self, id: i32, image: &NewPromptImage) -> Result<Prompt> {
+        let old = self.get_prompt_image(id)?;
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "UPDATE prompt_images SET
+             image_path = ?1,
+             thumbnail_path = ?2,
+             seed = COALESCE(?3, seed),
+             width = ?4,
+             height = ?5,
+             content_hash = ?6,
+             perceptual_hash = ?7
+             WHERE id = ?8",
+            params![
+                image.image_path,
+                image.thumbnail_path,
+                image.seed,
+                image.width,
+                image.height,
+                image.content_hash,
+                image.perceptual_hash,
+                id,
+            ],
+        )?;
+        refresh_cover(&tx, old.prompt_id)?;
+        touch_prompt(&tx, old.prompt_id)?;
+        tx.commit()?;
+
+        self.remove_unused_image_files(&old.image_path, old.thumbnail_path.as_deref())?;
+        self.get_prompt_by_id(old.prompt_id)
+    }
+
+    /// Removes the cover image of a prompt; the next image, if any, becomes
+    /// the cover.
+    pub fn detach_prompt_image(&self, prompt_id: i32) -> Result<Prompt> {
+        match self.get_prompt_images(prompt_id)?.into_iter().find(|i| i.is_cover) {
+            Some(cover) => self.remove_prompt_image(cover.id),
+            None => self.get_prompt_by_id(prompt_id),
+        }
+    }
+
+    /// Sets the display order of a prompt's images. Ids that don't belong to
+    /// the prompt are ignored; images left out keep their place after the
+    /// listed ones.
+    pub fn reorder_prompt_images(&self, prompt_id: i32, image_ids: &[i32]) -> Result<Prompt> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "UPDATE prompt_images SET position = position + ?1 WHERE prompt_id = ?2",
+            params![image_ids.len() as i32, prompt_id],
+        )?;
+        for (position, id) in image_ids.iter().enumerate() {
+            tx.execute(
+                "UPDATE prompt_images SET position = ?1 WHERE id = ?2 AND prompt_id = ?3",
+                params![position as i32, id, prompt_id],
+            )?;
+        }
+        tx.commit()?;
+        self.get_prompt_by_id(prompt_id)
+    }
+}
+
+fn image_from_row(row: &Row) -> Result<PromptImage> {
+    Ok(PromptImage {
+        id: row.get("id")?,
+        prompt_id: row.get("prompt_id")?,
+        image_path: row.get("image_path")?,
+        thumbnail_path: row.get("thumbnail_path")?,
+        position: row.get("position")?,
+        is_cover: row.get::<_, i32>("is_cover")? != 0,
+        seed: row.get("seed")?,
+        width: row.get("width")?,
+        height: row.get("height")?,
+        content_hash: row.get("content_hash")?,
+        perceptual_hash: row.get("perceptual_hash")?,
+        created_at: row.get("created_at")?,
+    })
+}
+
+fn touch_prompt(conn: &Connection, prompt_id: i32) -> Result<()> {
+    conn.execute(
+        "UPDATE prompts SET updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+        params![prompt_id],
+    )?;
+    Ok(())
+}
+
+pub(crate) fn insert_prompt_images(
+    conn: &Connection,
+    prompt_id: i32,
+    images: &[NewPromptImage],
+) -> Result<()> {
+    for image in images {
+        conn.execute(
+            "INSERT INTO prompt_images (prompt_id, image_path, thumbnail_path, position, seed, width, height, source_path, content_hash, perceptual_hash)
+             SELECT ?1, ?2, ?3, COALESCE(MAX(position) + 1, 0), ?4, ?5, ?6, ?7, ?8, ?9
+             FROM prompt_images WHERE prompt_id = ?1",
+            params![
+                prompt_id,
+                image.image_path,
+                image.thumbnail_path,
+                image.seed,
+                image.width,
+                image.height,
+                image.source_path,
+                image.content_hash,
+                image.perceptual_hash,
+            ],
+        )?;
+    }
+    refresh_cover(conn, prompt_id)
+}
+
+fn set_cover(conn: &Connection, prompt_id: i32, image_id: i32) -> Result<()> {
+    conn.execute(
+        "UPDATE prompt_images SET is_cover = 0 WHERE prompt_id = ? AND is_cover = 1",
+        params![prompt_id],
+    )?;
+    conn.execute(
+        "UPDATE prompt_images SET is_cover = 1 WHERE id = ?1 AND prompt_id = ?2",
+        params![image_id, prompt_id],
+    )?;
+    refresh_cover(conn, prompt_id)
+}
+
+/// Makes sure a prompt with images has exactly one cover and copies its
+/// paths to `prompts.image_path` / `thumbnail_path`, which the gallery reads.
+pub(crate) fn refresh_cover(conn: &Connection, prompt_id: i32) -> Result<()> {
+    conn.execute(
+        "UPDATE prompt_images SET is_cover = 1
+         WHERE id = (SELECT id FROM prompt_images WHERE prompt_id = ?1 ORDER BY position, id LIMIT 1)
+           AND NOT EXISTS (SELECT 1 FROM prompt_images WHERE prompt_id = ?1 AND is_cover = 1)",
+        params![prompt_id],
+    )?;
+    conn.execute(
+        "UPDATE prompts SET
+         image_path = (SELECT image_path FROM prompt_images WHERE prompt_id = prompts.id AND is_cover = 1),
+         thumbnail_path = (SELECT thumbnail_path FROM prompt_images WHERE prompt_id = prompts.id AND is_cover = 1)
+         WHERE id = ?",
+        params![prompt_id],
+    )?;
+    Ok(())
+}
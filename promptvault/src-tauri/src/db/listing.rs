@@ -0,0 +1,228 @@
+use super::{Database, PromptQuery};
+use rusqlite::types::Value;
+use rusqlite::{Result, Row};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_PAGE_SIZE: u32 = 100;
+const MAX_PAGE_SIZE: u32 = 500;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    #[default]
+    Created,
+    Updated,
+    Title,
+    Rating,
+    Model,
+    /// Shuffled, but stable for a given seed so pages don't overlap.
+    Random,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+/// Position after the last prompt of a page. Pass it back unchanged to get
+/// the next page.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageCursor {
+    pub key: serde_json::Value,
+    pub id: i32,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PageRequest {
+    pub sort: Option<SortKey>,
+    /// Newest, latest and best rated first by default; A to Z otherwise.
+    pub direction: Option<SortDirection>,
+    /// Seed of the `random` order.
+    pub seed: Option<i64>,
+    pub cursor: Option<PageCursor>,
+    pub limit: Option<u32>,
+}
+
+/// What a gallery card shows; the full prompt is loaded on demand.
+#[derive(Debug, Serialize)]
+pub struct PromptSummary {
+    pub id: i32,
+    pub title: String,
+    pub model: String,
+    pub thumbnail_path: Option<String>,
+    pub image_count: i32,
+    pub dimensions: String,
+    pub tags: Vec<String>,
+    pub is_favorite: bool,
+    pub rating: i32,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PromptPage {
+    pub items: Vec<PromptSummary>,
+    /// `None` on the last page.
+    pub next_cursor: Option<PageCursor>,
+    /// Prompts matching the filter across all pages.
+    pub total: i64,
+}
+
+impl SortKey {
+    /// SQL expression to order by, and the collation it compares with.
+    fn expression(self, seed: i64) -> (String, &'static str) {
+        match self {
+            SortKey::Created => ("COALESCE(p.created_at, '')".to_string(), ""),
+            SortKey::Updated => ("COALESCE(p.updated_at, '')".to_string(), ""),
+            SortKey::Title => ("p.title".to_string(), " COLLATE NOCASE"),
+            SortKey::Rating => ("p.rating".to_string(), ""),
+            SortKey::Model => ("COALESCE(p.model, '')".to_string(), " COLLATE NOCASE"),
+            // Multiplicative hashing spreads neighbouring ids apart; XOR-ing
+            // in the scrambled seed before the second multiplication makes
+            // each seed a different order rather than a rotation of one.
+            // SQLite has no XOR: a ^ b = (a | b) - (a & b).
+            SortKey::Random => {
+                let spread = "((p.id * 2654435761) % 4294967296)";
+                (
+                    format!(
+                        "(((({spread} | {seed}) - ({spread} & {seed})) * 73244475) % 4294967296)",
+                        spread = spread,
+                        seed = scramble_seed(seed)
+                    ),
+                    "",
+                )
+            }
+        }
+    }
+
+    fn default_direction(self) -> SortDirection {
+        match self {
+            SortKey::Created | SortKey::Updated | SortKey::Rating => SortDirection::Desc,
+            SortKey::Title | SortKey::Model | SortKey::Random => SortDirection::Asc,
+        }
+    }
+}
+
+impl Database {
+    /// One page of prompt summaries, using keyset pagination so deep pages
+    /// cost the same as the first one. Ties are broken by id.
+    pub fn list_prompts(&self, query: &PromptQuery, page: &PageRequest) -> Result<PromptPage> {
+        let (conditions, mut params) = self.query_conditions(query)?;
+        let total = self.conn.query_row(
+            &format!("SELECT COUNT(*) FROM prompts p WHERE deleted_at IS NULL{}", conditions),
+            rusqlite::params_from_iter(params.iter()),
+            |row| row.get(0),
+        )?;
+
+        let sort = page.sort.unwrap_or_default();
+        let (key, collate) = sort.expression(page.seed.unwrap_or(0));
+        let (order, comparison) = match page.direction.unwrap_or(sort.default_direction()) {
+            SortDirection::Asc => ("ASC", ">"),
+            SortDirection::Desc => ("DESC", "<"),
+        };
+
+        let mut keyset = String::new();
+        if let Some(cursor) = &page.cursor {
+            keyset = format!(
+                " AND ({key}{collate} {cmp} ? OR ({key}{collate} = ? AND p.id {cmp} ?))",
+                key = key,
+                collate = collate,
+                cmp = comparison
+            );
+            let value = json_to_sql(&cursor.key);
+            params.push(value.clone());
+            params.push(value);
+            params.push(Value::from(cursor.id));
+        }
+        let limit = page.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+        // One extra row tells whether another page follows.
+        params.push(Value::from(limit as i64 + 1));
+
+        let sql = format!(
+            "SELECT p.id, p.title, p.model, p.thumbnail_path, p.dimensions, p.tags,
+                p.is_favorite, p.rating, p.created_at, p.updated_at,
+                (SELECT COUNT(*) FROM prompt_images WHERE prompt_id = p.id) AS image_count,
+                {key} AS sort_key
+             FROM prompts p
+             WHERE deleted_at IS NULL{conditions}{keyset}
+             ORDER BY {key}{collate} {order}, p.id {order}
+             LIMIT ?",
+            key = key,
+            collate = collate,
+            conditions = conditions,
+            keyset = keyset,
+            order = order
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let mut rows = stmt
+            .query_map(rusqlite::params_from_iter(params.iter()), |row| {
+                Ok((summary_from_row(row)?, row.get::<_, Value>("sort_key")?))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        let next_cursor = if rows.len() > limit as usize {
+            rows.truncate(limit as usize);
+            rows.last().map(|(summary, key)| PageCursor {
+                key: sql_to_json(key),
+                id: summary.id,
+            })
+        } else {
+            None
+        };
+        Ok(PromptPage {
+            items: rows.into_iter().map(|(summary, _)| summary).collect(),
+            next_cursor,
+            total,
+        })
+    }
+}
+
+/// Spreads the bits of a seed over 32 bits (SplitMix64 finalizer), so
+/// nearby seeds give unrelated orders.
+fn scramble_seed(seed: i64) -> i64 {
+    let mut z = (seed as u64).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    ((z ^ (z >> 31)) & 0xFFFF_FFFF) as i64
+}
+
+fn summary_from_row(row: &Row) -> Result<PromptSummary> {
+    Ok(PromptSummary {
+        id: row.get("id")?,
+        title: row.get("title")?,
+        model: row.get::<_, Option<String>>("model")?.unwrap_or_default(),
+        thumbnail_path: row.get("thumbnail_path")?,
+        image_count: row.get("image_count")?,
+        dimensions: row.get("dimensions")?,
+        tags: row
+            .get::<_, Option<String>>("tags")?
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default(),
+        is_favorite: row.get::<_, i32>("is_favorite")? != 0,
+        rating: row.get("rating")?,
+        created_at: row.get("created_at")?,
+        updated_at: row.get("updated_at")?,
+    })
+}
+
+fn json_to_sql(value: &serde_json::Value) -> Value {
+    match value {
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Real(n.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(s) => Value::Text(s.clone()),
+        _ => Value::Null,
+    }
+}
+
+fn sql_to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Integer(i) => serde_json::Value::from(*i),
+        Value::Real(f) => serde_json::Value::from(*f),
+        Value::Text(s) => serde_json::Value::from(s.as_str()),
+        _ => serde_json::Value::Null,
+    }
+}
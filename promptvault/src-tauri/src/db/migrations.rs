@@ -0,0 +1,512 @@
+use rusqlite::{params, Connection, Transaction};
+use std::fs;
+use std::path::Path;
+
+/// A single schema step. `version` is the value `PRAGMA user_version` holds
+/// once the step has been applied.
+struct Migration {
+    version: i32,
+    description: &'static str,
+    up: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+/// Ordered list of schema steps. Never edit or reorder an entry that has
+/// shipped: append a new one instead.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        up: initial_schema,
+    },
+    Migration {
+        version: 2,
+        description: "full-text index over prompts",
+        up: full_text_index,
+    },
+    Migration {
+        version: 3,
+        description: "normalized tag tables",
+        up: normalized_tags,
+    },
+    Migration {
+        version: 4,
+        description: "prompt revision history",
+        up: prompt_revisions,
+    },
+    Migration {
+        version: 5,
+        description: "soft delete and settings",
+        up: trash_and_settings,
+    },
+    Migration {
+        version: 6,
+        description: "manual collection order",
+        up: collection_sort_order,
+    },
+    Migration {
+        version: 7,
+        description: "many-to-many collection membership",
+        up: collection_membership,
+    },
+    Migration {
+        version: 8,
+        description: "nested collections",
+        up: nested_collections,
+    },
+    Migration {
+        version: 9,
+        description: "model foreign key and default models",
+        up: model_registry,
+    },
+    Migration {
+        version: 10,
+        description: "multiple images per prompt",
+        up: prompt_images,
+    },
+    Migration {
+        version: 11,
+        description: "ComfyUI workflow storage",
+        up: prompt_workflow,
+    },
+    Migration {
+        version: 12,
+        description: "C2PA provenance",
+        up: prompt_provenance,
+    },
+    Migration {
+        version: 13,
+        description: "source path of imported images",
+        up: image_source_path,
+    },
+    Migration {
+        version: 14,
+        description: "watched folders",
+        up: watched_folders,
+    },
+    Migration {
+        version: 15,
+        description: "content hash of images",
+        up: image_content_hash,
+    },
+    Migration {
+        version: 16,
+        description: "perceptual hash of images",
+        up: image_perceptual_hash,
+    },
+    Migration {
+        version: 17,
+        description: "prompt rating and listing indexes",
+        up: prompt_rating,
+    },
+    Migration {
+        version: 18,
+        description: "smart collections",
+        up: smart_collections,
+    },
+    Migration {
+        version: 19,
+        description: "model hash of prompts",
+        up: prompt_model_hash,
+    },
+];
+
+/// Schema version this build of the app writes.
+pub fn latest_version() -> i32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+fn current_version(conn: &Connection) -> rusqlite::Result<i32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+fn has_user_tables(conn: &Connection) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%')",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Brings the database up to `latest_version()`.
+///
+/// Each step runs in its own transaction together with the `user_version`
+/// bump, so a failure leaves the database at the last completed version.
+/// Before touching a non-empty database a snapshot is written to
+/// `backups_dir`.
+pub fn run(conn: &mut Connection, backups_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let current = current_version(conn)?;
+    let latest = latest_version();
+
+    if current > latest {
+        return Err(format!(
+            "promptvault.db was written by a newer version of PromptVault \
+             (schema v{}, this build supports up to v{}). Update the app to open it.",
+            current, latest
+        )
+        .into());
+    }
+    if current == latest {
+        return Ok(());
+    }
+
+    if has_user_tables(conn)? {
+        fs::create_dir_all(backups_dir)?;
+        let backup_path = backups_dir.join(format!(
+            "promptvault-v{}-{}.db",
+            current,
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        ));
+        conn.execute(
+            "VACUUM INTO ?1",
+            params![backup_path.to_string_lossy().to_string()],
+        )?;
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let tx = conn.transaction()?;
+        (migration.up)(&tx).map_err(|e| {
+            format!(
+                "Migration v{} ({}) failed: {}",
+                migration.version, migration.description, e
+            )
+        })?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+fn initial_schema(tx: &Transaction) -> rusqlite::Result<()> {
+    // Databases created before migrations existed already have these tables
+    // at user_version 0, hence IF NOT EXISTS.
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS prompts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            title TEXT NOT NULL,
+            prompt_text TEXT NOT NULL,
+            negative_prompt TEXT DEFAULT '',
+            model TEXT NOT NULL,
+            image_path TEXT,
+            thumbnail_path TEXT,
+            dimensions TEXT DEFAULT '1:1',
+            steps INTEGER,
+            sampler TEXT,
+            cfg_scale REAL,
+            seed TEXT,
+            tags TEXT DEFAULT '[]',
+            is_favorite INTEGER DEFAULT 0,
+            collection_id INTEGER,
+            created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (collection_id) REFERENCES collections(id)
+        );
+
+        CREATE TABLE IF NOT EXISTS collections (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            icon TEXT DEFAULT 'folder',
+            color TEXT DEFAULT '#6b7280',
+            created_at TEXT DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE IF NOT EXISTS models (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            short_name TEXT,
+            is_active INTEGER DEFAULT 1
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_prompts_model ON prompts(model);
+        CREATE INDEX IF NOT EXISTS idx_prompts_collection ON prompts(collection_id);
+        CREATE INDEX IF NOT EXISTS idx_prompts_favorite ON prompts(is_favorite);
+        CREATE INDEX IF NOT EXISTS idx_prompts_created ON prompts(created_at);",
+    )
+}
+
+fn full_text_index(tx: &Transaction) -> rusqlite::Result<()> {
+    // External-content table: the text lives in `prompts` and the index is
+    // kept in sync by the triggers below.
+    tx.execute_batch(
+        "CREATE VIRTUAL TABLE prompts_fts USING fts5(
+            title,
+            prompt_text,
+            negative_prompt,
+            tags,
+            sampler,
+            content = 'prompts',
+            content_rowid = 'id',
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+
+        CREATE TRIGGER prompts_fts_after_insert AFTER INSERT ON prompts BEGIN
+            INSERT INTO prompts_fts (rowid, title, prompt_text, negative_prompt, tags, sampler)
+            VALUES (new.id, new.title, new.prompt_text, new.negative_prompt, new.tags, new.sampler);
+        END;
+
+        CREATE TRIGGER prompts_fts_after_delete AFTER DELETE ON prompts BEGIN
+            INSERT INTO prompts_fts (prompts_fts, rowid, title, prompt_text, negative_prompt, tags, sampler)
+            VALUES ('delete', old.id, old.title, old.prompt_text, old.negative_prompt, old.tags, old.sampler);
+        END;
+
+        CREATE TRIGGER prompts_fts_after_update
+        AFTER UPDATE OF title, prompt_text, negative_prompt, tags, sampler ON prompts BEGIN
+            INSERT INTO prompts_fts (prompts_fts, rowid, title, prompt_text, negative_prompt, tags, sampler)
+            VALUES ('delete', old.id, old.title, old.prompt_text, old.negative_prompt, old.tags, old.sampler);
+            INSERT INTO prompts_fts (rowid, title, prompt_text, negative_prompt, tags, sampler)
+            VALUES (new.id, new.title, new.prompt_text, new.negative_prompt, new.tags, new.sampler);
+        END;
+
+        INSERT INTO prompts_fts (prompts_fts) VALUES ('rebuild');",
+    )
+}
+
+fn normalized_tags(tx: &Transaction) -> rusqlite::Result<()> {
+    // `prompts.tags` stays as a JSON cache of the ordered tag names so the
+    // full-text triggers keep indexing it; `prompt_tags` is authoritative.
+    tx.execute_batch(
+        "CREATE TABLE tags (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            created_at TEXT DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE prompt_tags (
+            prompt_id INTEGER NOT NULL REFERENCES prompts(id) ON DELETE CASCADE,
+            tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+            position INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (prompt_id, tag_id)
+        ) WITHOUT ROWID;
+
+        CREATE INDEX idx_prompt_tags_tag ON prompt_tags(tag_id);
+
+        CREATE TEMP VIEW legacy_tags AS
+            SELECT p.id AS prompt_id, CAST(j.key AS INTEGER) AS position, trim(j.value) AS name
+            FROM prompts p,
+                 json_each(CASE WHEN json_valid(p.tags) THEN p.tags ELSE '[]' END) j
+            WHERE j.type = 'text' AND trim(j.value) <> '';
+
+        INSERT OR IGNORE INTO tags (name)
+            SELECT name FROM legacy_tags ORDER BY prompt_id, position;
+
+        INSERT OR IGNORE INTO prompt_tags (prompt_id, tag_id, position)
+            SELECT l.prompt_id, t.id, l.position
+            FROM legacy_tags l
+            JOIN tags t ON t.name = l.name;
+
+        DROP VIEW legacy_tags;
+
+        UPDATE prompts SET tags = (
+            SELECT json_group_array(name) FROM (
+                SELECT t.name FROM prompt_tags pt
+                JOIN tags t ON t.id = pt.tag_id
+                WHERE pt.prompt_id = prompts.id
+                ORDER BY pt.position
+            )
+        );",
+    )
+}
+
+fn prompt_revisions(tx: &Transaction) -> rusqlite::Result<()> {
+    // Each row is a full snapshot of the editable fields after a change.
+    // Existing prompts start with their current state as revision 1.
+    tx.execute_batch(
+        "CREATE TABLE prompt_revisions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            prompt_id INTEGER NOT NULL REFERENCES prompts(id) ON DELETE CASCADE,
+            revision INTEGER NOT NULL,
+            title TEXT NOT NULL,
+            prompt_text TEXT NOT NULL,
+            negative_prompt TEXT,
+            model TEXT NOT NULL,
+            dimensions TEXT,
+            steps INTEGER,
+            sampler TEXT,
+            cfg_scale REAL,
+            seed TEXT,
+            tags TEXT NOT NULL DEFAULT '[]',
+            created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (prompt_id, revision)
+        );
+
+        INSERT INTO prompt_revisions (prompt_id, revision, title, prompt_text, negative_prompt,
+            model, dimensions, steps, sampler, cfg_scale, seed, tags, created_at)
+        SELECT id, 1, title, prompt_text, negative_prompt, model, dimensions, steps, sampler,
+            cfg_scale, seed, COALESCE(tags, '[]'), COALESCE(updated_at, created_at, CURRENT_TIMESTAMP)
+        FROM prompts;",
+    )
+}
+
+fn trash_and_settings(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE prompts ADD COLUMN deleted_at TEXT;
+        CREATE INDEX idx_prompts_deleted ON prompts(deleted_at);
+
+        CREATE TABLE settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );",
+    )
+}
+
+fn collection_sort_order(tx: &Transaction) -> rusqlite::Result<()> {
+    // Start from the alphabetical order collections were listed in so far.
+    tx.execute_batch(
+        "ALTER TABLE collections ADD COLUMN sort_order INTEGER NOT NULL DEFAULT 0;
+
+        UPDATE collections SET sort_order = (
+            SELECT COUNT(*) FROM collections c WHERE c.name < collections.name
+        );",
+    )
+}
+
+fn collection_membership(tx: &Transaction) -> rusqlite::Result<()> {
+    // `prompts.collection_id` can't be dropped while it carries a foreign
+    // key, so it is cleared and left unused.
+    tx.execute_batch(
+        "CREATE TABLE collection_prompts (
+            collection_id INTEGER NOT NULL REFERENCES collections(id) ON DELETE CASCADE,
+            prompt_id INTEGER NOT NULL REFERENCES prompts(id) ON DELETE CASCADE,
+            added_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (collection_id, prompt_id)
+        ) WITHOUT ROWID;
+
+        CREATE INDEX idx_collection_prompts_prompt ON collection_prompts(prompt_id);
+
+        INSERT OR IGNORE INTO collection_prompts (collection_id, prompt_id, added_at)
+            SELECT p.collection_id, p.id, p.created_at
+            FROM prompts p
+            JOIN collections c ON c.id = p.collection_id;
+
+        DROP INDEX IF EXISTS idx_prompts_collection;
+        UPDATE prompts SET collection_id = NULL;",
+    )
+}
+
+fn nested_collections(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE collections ADD COLUMN parent_id INTEGER REFERENCES collections(id) ON DELETE SET NULL;
+        CREATE INDEX idx_collections_parent ON collections(parent_id);",
+    )
+}
+
+fn model_registry(tx: &Transaction) -> rusqlite::Result<()> {
+    // Defaults used to be re-inserted on every startup, which brought back
+    // models the user had renamed or deleted. They are seeded once here.
+    let defaults = [
+        ("Gemini", "Gemini"),
+        ("Chat GPT", "GPT"),
+        ("Stable Diffusion XL", "SDXL"),
+        ("Midjourney V6", "MJ"),
+        ("DALL-E 3", "DALL-E"),
+        ("Flux Pro", "Flux"),
+        ("Flux.1", "Flux.1"),
+        ("Leonardo AI", "Leo"),
+        ("Firefly", "Adobe"),
+    ];
+    for (name, short) in defaults.iter() {
+        tx.execute(
+            "INSERT OR IGNORE INTO models (name, short_name) VALUES (?1, ?2)",
+            params![name, short],
+        )?;
+    }
+
+    // `prompts.model` is kept as a cache of the model name.
+    tx.execute_batch(
+        "INSERT OR IGNORE INTO models (name, short_name)
+            SELECT model, model FROM prompts
+            WHERE model <> ''
+              AND NOT EXISTS (SELECT 1 FROM models WHERE name = prompts.model COLLATE NOCASE)
+            GROUP BY model COLLATE NOCASE;
+
+        ALTER TABLE prompts ADD COLUMN model_id INTEGER REFERENCES models(id);
+        UPDATE prompts SET model_id = (
+            SELECT id FROM models WHERE name = prompts.model COLLATE NOCASE ORDER BY id LIMIT 1
+        );
+        UPDATE prompts SET model = (SELECT name FROM models WHERE id = prompts.model_id)
+            WHERE model_id IS NOT NULL;
+        CREATE INDEX idx_prompts_model_id ON prompts(model_id);",
+    )
+}
+
+fn prompt_images(tx: &Transaction) -> rusqlite::Result<()> {
+    // `prompts.image_path` / `thumbnail_path` stay as a copy of the cover.
+    tx.execute_batch(
+        "CREATE TABLE prompt_images (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            prompt_id INTEGER NOT NULL REFERENCES prompts(id) ON DELETE CASCADE,
+            image_path TEXT NOT NULL,
+            thumbnail_path TEXT,
+            position INTEGER NOT NULL DEFAULT 0,
+            is_cover INTEGER NOT NULL DEFAULT 0,
+            seed TEXT,
+            width INTEGER,
+            height INTEGER,
+            created_at TEXT DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE INDEX idx_prompt_images_prompt ON prompt_images(prompt_id, position);
+        CREATE UNIQUE INDEX idx_prompt_images_cover ON prompt_images(prompt_id) WHERE is_cover = 1;
+
+        INSERT INTO prompt_images (prompt_id, image_path, thumbnail_path, is_cover, seed, created_at)
+            SELECT id, image_path, thumbnail_path, 1, seed, created_at
+            FROM prompts WHERE image_path IS NOT NULL;",
+    )
+}
+
+fn prompt_workflow(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("ALTER TABLE prompts ADD COLUMN workflow_json TEXT;")
+}
+
+fn prompt_provenance(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("ALTER TABLE prompts ADD COLUMN provenance TEXT;")
+}
+
+fn image_source_path(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE prompt_images ADD COLUMN source_path TEXT;
+        CREATE INDEX idx_prompt_images_source ON prompt_images(source_path);",
+    )
+}
+
+fn watched_folders(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE watched_folders (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            path TEXT NOT NULL UNIQUE,
+            collection_id INTEGER REFERENCES collections(id) ON DELETE SET NULL,
+            model TEXT,
+            recursive INTEGER NOT NULL DEFAULT 1,
+            is_active INTEGER NOT NULL DEFAULT 1,
+            created_at TEXT DEFAULT CURRENT_TIMESTAMP
+        );",
+    )
+}
+
+fn image_content_hash(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE prompt_images ADD COLUMN content_hash TEXT;
+        CREATE INDEX idx_prompt_images_hash ON prompt_images(content_hash);",
+    )
+}
+
+fn image_perceptual_hash(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("ALTER TABLE prompt_images ADD COLUMN perceptual_hash INTEGER;")
+}
+
+fn prompt_rating(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE prompts ADD COLUMN rating INTEGER NOT NULL DEFAULT 0;
+        CREATE INDEX idx_prompts_updated ON prompts(updated_at, id);
+        CREATE INDEX idx_prompts_rating ON prompts(rating, id);
+        CREATE INDEX idx_prompts_title ON prompts(title COLLATE NOCASE, id);",
+    )
+}
+
+fn smart_collections(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("ALTER TABLE collections ADD COLUMN query TEXT;")
+}
+
+fn prompt_model_hash(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("ALTER TABLE prompts ADD COLUMN model_hash TEXT;")
+}
@@ -0,0 +1,197 @@
+use super::Database;
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct Model {
+    pub id: i32,
+    pub name: String,
+    pub short_name: String,
+    pub is_active: bool,
+    /// Prompts using this model, trashed ones included.
+    pub prompt_count: i32,
+}
+
+impl Database {
+    pub fn get_models(&self, include_inactive: bool) -> Result<Vec<Model>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT m.*, COUNT(p.id) AS prompt_count
+             FROM models m
+             LEFT JOIN prompts p ON p.model_id = m.id
+             WHERE m.is_active = 1 OR ?1
+             GROUP BY m.id
+             ORDER BY m.name",
+        )?;
+
+        let model_iter = stmt.query_map(params![include_inactive], model_from_row)?;
+        model_iter.collect()
+    }
+
+    pub fn get_model(&self, id: i32) -> Result<Model> {
+        self.conn.query_row(
+            "SELECT m.*, COUNT(p.id) AS prompt_count
+             FROM models m
+             LEFT JOIN prompts p ON p.model_id = m.id
+             WHERE m.id = ?
+             GROUP BY m.id",
+            params![id],
+            model_from_row,
+        )
+    }
+
+    /// Case-insensitive lookup by name.
+    pub fn find_model_id(&self, name: &str) -> Result<Option<i32>> {
+        find_model_id(&self.conn, name)
+    }
+
+    pub fn create_model(&self, name: &str, short_name: Option<&str>) -> Result<Model> {
+        let name = name.trim();
+        self.conn.execute(
+            "INSERT INTO models (name, short_name) VALUES (?1, ?2)",
+            params![name, short_name.map(str::trim).unwrap_or(name)],
+        )?;
+        let id = self.conn.last_insert_rowid() as i32;
+        self.get_model(id)
+    }
+
+    /// Renames a model and every prompt that uses it, revisions included, so
+    /// restoring an old revision doesn't bring back the old name.
+    pub fn rename_model(&self, id: i32, name: &str) -> Result<Model> {
+        let tx = self.conn.unchecked_transaction()?;
+        rename_in_revisions(&tx, id, name.trim())?;
+        tx.execute(
+            "UPDATE models SET name = ?1 WHERE id = ?2",
+            params![name.trim(), id],
+        )?;
+        tx.execute(
+            "UPDATE prompts SET model = ?1 WHERE model_id = ?2",
+            params![name.trim(), id],
+        )?;
+        tx.commit()?;
+        self.get_model(id)
+    }
+
+    pub fn set_model_short_name(&self, id: i32, short_name: &str) -> Result<Model> {
+        self.conn.execute(
+            "UPDATE models SET short_name = ?1 WHERE id = ?2",
+            params![short_name.trim(), id],
+        )?;
+        self.get_model(id)
+    }
+
+    /// Inactive models are hidden from pickers but keep their prompts.
+    pub fn set_model_active(&self, id: i32, is_active: bool) -> Result<Model> {
+        self.conn.execute(
+            "UPDATE models SET is_active = ?1 WHERE id = ?2",
+            params![is_active, id],
+        )?;
+        self.get_model(id)
+    }
+
+    /// Deletes a model, first moving its prompts to `reassign_to` when given.
+    /// Without a target the foreign key makes deleting a model in use fail.
+    pub fn delete_model(&self, id: i32, reassign_to: Option<i32>) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        if let Some(target) = reassign_to {
+            let target_name: String =
+                tx.query_row("SELECT name FROM models WHERE id = ?", params![target], |row| {
+                    row.get(0)
+                })?;
+            rename_in_revisions(&tx, id, &target_name)?;
+            tx.execute(
+                "UPDATE prompts SET model_id = m.id, model = m.name
+                 FROM models m
+                 WHERE m.id = ?1 AND prompts.model_id = ?2",
+                params![target, id],
+            )?;
+        }
+        tx.execute("DELETE FROM models WHERE id = ?", params![id])?;
+        tx.commit()
+    }
+}
+
+/// Revisions record the model by name; those naming model `id` get `name`.
+fn rename_in_revisions(conn: &Connection, id: i32, name: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE prompt_revisions SET model = ?1
+         WHERE model = (SELECT name FROM models WHERE id = ?2) COLLATE NOCASE",
+        params![name, id],
+    )?;
+    Ok(())
+}
+
+fn model_from_row(row: &Row) -> Result<Model> {
+    let name: String = row.get("name")?;
+    Ok(Model {
+        id: row.get("id")?,
+        short_name: row
+            .get::<_, Option<String>>("short_name")?
+            .unwrap_or_else(|| name.clone()),
+        name,
+        is_active: row.get::<_, i32>("is_active")? != 0,
+        prompt_count: row.get("prompt_count")?,
+    })
+}
+
+fn find_model_id(conn: &Connection, name: &str) -> Result<Option<i32>> {
+    conn.query_row(
+        "SELECT id FROM models WHERE name = ? COLLATE NOCASE ORDER BY id LIMIT 1",
+        params![name.trim()],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// A model whose name, or else short name, is `name`.
+fn find_model_alias(conn: &Connection, name: &str) -> Result<Option<i32>> {
+    conn.query_row(
+        "SELECT id FROM models
+         WHERE name = ?1 COLLATE NOCASE OR short_name = ?1 COLLATE NOCASE
+         ORDER BY name = ?1 COLLATE NOCASE DESC, id LIMIT 1",
+        params![name.trim()],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Points a prompt at the model called `name` and refreshes the
+/// `prompts.model` name cache. With `register` a new name becomes a new
+/// model; without it, as for names read from metadata, it may also match a
+/// short name (`SDXL`) and is otherwise kept as unlinked text. An empty name
+/// clears the model.
+pub(crate) fn set_prompt_model(
+    conn: &Connection,
+    prompt_id: i32,
+    name: &str,
+    register: bool,
+) -> Result<()> {
+    let existing = if register {
+        find_model_id(conn, name)?
+    } else {
+        find_model_alias(conn, name)?
+    };
+    let model_id = match existing {
+        Some(id) => id,
+        None if name.trim().is_empty() || !register => {
+            conn.execute(
+                "UPDATE prompts SET model_id = NULL, model = ?1 WHERE id = ?2",
+                params![name.trim(), prompt_id],
+            )?;
+            return Ok(());
+        }
+        None => {
+            conn.execute(
+                "INSERT INTO models (name, short_name) VALUES (?1, ?1)",
+                params![name.trim()],
+            )?;
+            conn.last_insert_rowid() as i32
+        }
+    };
+    conn.execute(
+        "UPDATE prompts SET model_id = m.id, model = m.name
+         FROM models m
+         WHERE m.id = ?1 AND prompts.id = ?2",
+        params![model_id, prompt_id],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,228 @@
+use super::{build_fts_query, tags, Database, TagMatch};
+use chrono::{NaiveDate, NaiveDateTime};
+use rusqlite::types::Value;
+use rusqlite::Result;
+use serde::{Deserialize, Serialize};
+
+/// Criteria that select prompts, shared by the listings, search and export
+/// so a filter means the same everywhere. Every field is optional and they
+/// all have to match.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PromptQuery {
+    /// Full-text search over title, prompt, negative prompt, tags and sampler.
+    pub text: Option<String>,
+    /// Any of these models, compared without case.
+    pub models: Option<Vec<String>>,
+    pub tags: Option<Vec<String>>,
+    pub tag_match: Option<TagMatch>,
+    pub collection_id: Option<i32>,
+    pub include_descendants: Option<bool>,
+    pub favorites_only: Option<bool>,
+    pub has_image: Option<bool>,
+    /// Dates as `YYYY-MM-DD` or `YYYY-MM-DD HH:MM:SS`; both ends inclusive.
+    pub created_from: Option<String>,
+    pub created_to: Option<String>,
+    pub updated_from: Option<String>,
+    pub updated_to: Option<String>,
+    /// Any of these samplers, compared without case.
+    pub samplers: Option<Vec<String>>,
+    pub steps_min: Option<i32>,
+    pub steps_max: Option<i32>,
+    pub cfg_min: Option<f64>,
+    pub cfg_max: Option<f64>,
+    /// Any of these aspect ratios, e.g. `16:9`.
+    pub dimensions: Option<Vec<String>>,
+}
+
+impl PromptQuery {
+    /// Only the filters the older listing arguments could express.
+    pub fn from_filters(
+        model: Option<String>,
+        collection_id: Option<i32>,
+        include_descendants: bool,
+        tags: Vec<String>,
+        tag_match: TagMatch,
+    ) -> Self {
+        PromptQuery {
+            models: model.map(|model| vec![model]),
+            tags: Some(tags),
+            tag_match: Some(tag_match),
+            collection_id,
+            include_descendants: Some(include_descendants),
+            ..Default::default()
+        }
+    }
+
+    /// Checks dates and ranges, with a message for the user.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        for date in [
+            &self.created_from,
+            &self.created_to,
+            &self.updated_from,
+            &self.updated_to,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if parse_date(date).is_none() {
+                return Err(format!("Invalid date: {}", date));
+            }
+        }
+        if let (Some(min), Some(max)) = (self.steps_min, self.steps_max) {
+            if min > max {
+                return Err("Minimum steps is above the maximum".to_string());
+            }
+        }
+        if let (Some(min), Some(max)) = (self.cfg_min, self.cfg_max) {
+            if min > max {
+                return Err("Minimum CFG scale is above the maximum".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Database {
+    /// The `AND …` conditions selecting the prompts of `query` from
+    /// `prompts p`, with their parameters.
+    pub(crate) fn query_conditions(&self, query: &PromptQuery) -> Result<(String, Vec<Value>)> {
+        let mut sql = String::new();
+        let mut params: Vec<Value> = Vec::new();
+
+        if let Some(fts_query) = query.text.as_deref().and_then(build_fts_query) {
+            sql.push_str(" AND p.id IN (SELECT rowid FROM prompts_fts WHERE prompts_fts MATCH ?)");
+            params.push(Value::from(fts_query));
+        }
+
+        if let Some(models) = non_empty(&query.models) {
+            sql.push_str(
+                " AND p.model COLLATE NOCASE IN (SELECT value FROM json_each(?))",
+            );
+            params.push(json_list(&models));
+        }
+
+        if let Some(coll_id) = query.collection_id {
+            let collection_ids = if query.include_descendants.unwrap_or(false) {
+                self.collection_subtree_ids(coll_id)?
+            } else {
+                vec![coll_id]
+            };
+            let (conditions, collection_params) = self.collection_conditions(&collection_ids)?;
+            sql.push_str(&conditions);
+            params.extend(collection_params);
+        }
+
+        let mut tag_names: Vec<String> = Vec::new();
+        for name in query.tags.iter().flatten().map(|t| tags::normalize_tag(t)) {
+            if !name.is_empty() && !tag_names.iter().any(|t| t.eq_ignore_ascii_case(&name)) {
+                tag_names.push(name);
+            }
+        }
+        if !tag_names.is_empty() {
+            let tag_match = query.tag_match.unwrap_or_default();
+            sql.push_str(if let TagMatch::None = tag_match {
+                " AND p.id NOT IN ("
+            } else {
+                " AND p.id IN ("
+            });
+            sql.push_str(
+                "SELECT pt.prompt_id FROM prompt_tags pt
+                  JOIN tags t ON t.id = pt.tag_id
+                  WHERE t.name IN (SELECT value FROM json_each(?))
+                  GROUP BY pt.prompt_id",
+            );
+            params.push(json_list(&tag_names));
+            if let TagMatch::All = tag_match {
+                sql.push_str(" HAVING COUNT(*) = ?");
+                params.push(Value::from(tag_names.len() as i64));
+            }
+            sql.push(')');
+        }
+
+        if query.favorites_only.unwrap_or(false) {
+            sql.push_str(" AND p.is_favorite = 1");
+        }
+        match query.has_image {
+            Some(true) => sql.push_str(" AND p.image_path IS NOT NULL"),
+            Some(false) => sql.push_str(" AND p.image_path IS NULL"),
+            None => {}
+        }
+
+        let date_ranges = [
+            ("p.created_at", &query.created_from, &query.created_to),
+            ("p.updated_at", &query.updated_from, &query.updated_to),
+        ];
+        for (column, from, to) in date_ranges {
+            if let Some(from) = from.as_deref().and_then(parse_date) {
+                sql.push_str(&format!(" AND {} >= ?", column));
+                params.push(Value::from(from.format("%Y-%m-%d %H:%M:%S").to_string()));
+            }
+            if let Some(to) = to.as_deref() {
+                // A bare date includes the whole day.
+                let to = match NaiveDate::parse_from_str(to.trim(), "%Y-%m-%d") {
+                    Ok(day) => day.and_hms_opt(23, 59, 59),
+                    Err(_) => parse_date(to),
+                };
+                if let Some(to) = to {
+                    sql.push_str(&format!(" AND {} <= ?", column));
+                    params.push(Value::from(to.format("%Y-%m-%d %H:%M:%S").to_string()));
+                }
+            }
+        }
+
+        if let Some(samplers) = non_empty(&query.samplers) {
+            sql.push_str(" AND p.sampler COLLATE NOCASE IN (SELECT value FROM json_each(?))");
+            params.push(json_list(&samplers));
+        }
+        if let Some(min) = query.steps_min {
+            sql.push_str(" AND p.steps >= ?");
+            params.push(Value::from(min));
+        }
+        if let Some(max) = query.steps_max {
+            sql.push_str(" AND p.steps <= ?");
+            params.push(Value::from(max));
+        }
+        if let Some(min) = query.cfg_min {
+            sql.push_str(" AND p.cfg_scale >= ?");
+            params.push(Value::from(min));
+        }
+        if let Some(max) = query.cfg_max {
+            sql.push_str(" AND p.cfg_scale <= ?");
+            params.push(Value::from(max));
+        }
+        if let Some(dimensions) = non_empty(&query.dimensions) {
+            sql.push_str(" AND p.dimensions IN (SELECT value FROM json_each(?))");
+            params.push(json_list(&dimensions));
+        }
+
+        Ok((sql, params))
+    }
+}
+
+/// Trimmed, non-empty entries of a list, or `None` if there are none.
+fn non_empty(values: &Option<Vec<String>>) -> Option<Vec<String>> {
+    let values: Vec<String> = values
+        .iter()
+        .flatten()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect();
+    (!values.is_empty()).then_some(values)
+}
+
+fn json_list(values: &[String]) -> Value {
+    Value::from(serde_json::to_string(values).unwrap_or_else(|_| "[]".to_string()))
+}
+
+fn parse_date(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim();
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S"))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+        })
+}
@@ -0,0 +1,171 @@
+use super::{models, tags, Database, Prompt};
+use crate::diff::{diff_words, DiffSpan};
+use rusqlite::{params, Connection, Result, Row};
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct PromptRevision {
+    pub id: i32,
+    pub prompt_id: i32,
+    pub revision: i32,
+    pub title: String,
+    pub prompt_text: String,
+    pub negative_prompt: String,
+    pub model: String,
+    pub dimensions: String,
+    pub steps: Option<i32>,
+    pub sampler: Option<String>,
+    pub cfg_scale: Option<f64>,
+    pub seed: Option<String>,
+    pub tags: Vec<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldDiff {
+    pub field: &'static str,
+    pub spans: Vec<DiffSpan>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevisionDiff {
+    pub from: PromptRevision,
+    pub to: PromptRevision,
+    /// Only the fields that differ between the two revisions.
+    pub fields: Vec<FieldDiff>,
+}
+
+impl RevisionDiff {
+    /// Compares two revisions field by field. Pure computation, so callers
+    /// can run it without holding the database.
+    pub fn between(from: PromptRevision, to: PromptRevision) -> Self {
+        let pairs = [
+            ("title", from.title.clone(), to.title.clone()),
+            ("prompt_text", from.prompt_text.clone(), to.prompt_text.clone()),
+            ("negative_prompt", from.negative_prompt.clone(), to.negative_prompt.clone()),
+            ("model", from.model.clone(), to.model.clone()),
+            ("dimensions", from.dimensions.clone(), to.dimensions.clone()),
+            ("steps", display(&from.steps), display(&to.steps)),
+            ("sampler", display(&from.sampler), display(&to.sampler)),
+            ("cfg_scale", display(&from.cfg_scale), display(&to.cfg_scale)),
+            ("seed", display(&from.seed), display(&to.seed)),
+            ("tags", from.tags.join(", "), to.tags.join(", ")),
+        ];
+
+        let fields = pairs
+            .into_iter()
+            .filter(|(_, old, new)| old != new)
+            .map(|(field, old, new)| FieldDiff { field, spans: diff_words(&old, &new) })
+            .collect();
+
+        RevisionDiff { from, to, fields }
+    }
+}
+
+impl Database {
+    /// Revisions of a prompt, newest first.
+    pub fn get_prompt_revisions(&self, prompt_id: i32) -> Result<Vec<PromptRevision>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT * FROM prompt_revisions WHERE prompt_id = ? ORDER BY revision DESC",
+        )?;
+        let revision_iter = stmt.query_map(params![prompt_id], revision_from_row)?;
+        revision_iter.collect()
+    }
+
+    pub fn get_prompt_revision(&self, id: i32) -> Result<PromptRevision> {
+        self.conn.query_row(
+            "SELECT * FROM prompt_revisions WHERE id = ?",
+            params![id],
+            revision_from_row,
+        )
+    }
+
+    /// Makes an old revision the current state of its prompt. The restore is
+    /// itself recorded as a new revision, so nothing is lost.
+    pub fn restore_prompt_revision(&self, revision_id: i32) -> Result<Prompt> {
+        let revision = self.get_prompt_revision(revision_id)?;
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "UPDATE prompts SET
+             title = ?1,
+             prompt_text = ?2,
+             negative_prompt = ?3,
+             dimensions = ?4,
+             steps = ?5,
+             sampler = ?6,
+             cfg_scale = ?7,
+             seed = ?8,
+             updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?9",
+            params![
+                revision.title,
+                revision.prompt_text,
+                revision.negative_prompt,
+                revision.dimensions,
+                revision.steps,
+                revision.sampler,
+                revision.cfg_scale,
+                revision.seed,
+                revision.prompt_id,
+            ],
+        )?;
+        models::set_prompt_model(&tx, revision.prompt_id, &revision.model, false)?;
+        tags::set_prompt_tags(&tx, revision.prompt_id, &revision.tags)?;
+        record_revision(&tx, revision.prompt_id)?;
+        tx.commit()?;
+
+        self.get_prompt_by_id(revision.prompt_id)
+    }
+}
+
+fn display<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn revision_from_row(row: &Row) -> Result<PromptRevision> {
+    Ok(PromptRevision {
+        id: row.get("id")?,
+        prompt_id: row.get("prompt_id")?,
+        revision: row.get("revision")?,
+        title: row.get("title")?,
+        prompt_text: row.get("prompt_text")?,
+        negative_prompt: row.get::<_, Option<String>>("negative_prompt")?.unwrap_or_default(),
+        model: row.get("model")?,
+        dimensions: row.get::<_, Option<String>>("dimensions")?.unwrap_or_default(),
+        steps: row.get("steps")?,
+        sampler: row.get("sampler")?,
+        cfg_scale: row.get("cfg_scale")?,
+        seed: row.get("seed")?,
+        tags: serde_json::from_str(&row.get::<_, String>("tags")?).unwrap_or_default(),
+        created_at: row.get("created_at")?,
+    })
+}
+
+/// Snapshots the current state of a prompt as its next revision, unless it
+/// is identical to the latest one (e.g. only `is_favorite` changed).
+pub(crate) fn record_revision(conn: &Connection, prompt_id: i32) -> Result<()> {
+    conn.execute(
+        "INSERT INTO prompt_revisions (prompt_id, revision, title, prompt_text, negative_prompt,
+         model, dimensions, steps, sampler, cfg_scale, seed, tags)
+         SELECT p.id, COALESCE(latest.revision, 0) + 1, p.title, p.prompt_text, p.negative_prompt,
+                p.model, p.dimensions, p.steps, p.sampler, p.cfg_scale, p.seed, COALESCE(p.tags, '[]')
+         FROM prompts p
+         LEFT JOIN prompt_revisions latest ON latest.prompt_id = p.id
+              AND latest.revision = (SELECT MAX(revision) FROM prompt_revisions WHERE prompt_id = p.id)
+         WHERE p.id = ?1
+           AND (latest.id IS NULL
+                OR latest.title IS NOT p.title
+                OR latest.prompt_text IS NOT p.prompt_text
+                OR latest.negative_prompt IS NOT p.negative_prompt
+                OR latest.model IS NOT p.model
+                OR latest.dimensions IS NOT p.dimensions
+                OR latest.steps IS NOT p.steps
+                OR latest.sampler IS NOT p.sampler
+                OR latest.cfg_scale IS NOT p.cfg_scale
+                OR latest.seed IS NOT p.seed
+                OR latest.tags IS NOT COALESCE(p.tags, '[]'))",
+        params![prompt_id],
+    )?;
+    Ok(())
+}
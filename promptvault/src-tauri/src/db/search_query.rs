@@ -0,0 +1,632 @@
+use super::{build_fts_query, tags};
+use chrono::{Datelike, NaiveDate};
+use rusqlite::types::Value;
+use serde::Serialize;
+use std::fmt;
+
+/// A malformed search query. `start` and `end` are character offsets into
+/// the query, so the search box can underline the offending part.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueryError {
+    pub message: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (at character {})", self.message, self.start + 1)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+/// A search box query compiled to SQL over `prompts p`.
+///
+/// Plain words and `"quoted phrases"` are full-text searched as before.
+/// On top of that:
+///
+/// - `model:sdxl`, `sampler:euler`, `title:cat` match part of the field,
+///   `model=sdxl` the whole of it; models also match the registry's name
+///   and short name
+/// - `tag:portrait`, `seed:1234`, `ar:16:9`
+/// - `steps>=30`, `cfg<7`, `rating:5` with `:`, `=`, `>`, `>=`, `<`, `<=`
+/// - `fav:yes`, `has:image`, `has:workflow`
+/// - `created>=2024-03`, `updated:2024-03-15`, `created>=30d` (last 30 days;
+///   also `w`, `m` and `y`)
+/// - `-` in front of any term excludes what it matches
+///
+/// Values with spaces can be quoted: `model:"flux dev"`. Anything else that
+/// looks like a field, such as `masterpiece:1.2` or a URL, is plain text.
+#[derive(Debug, Default)]
+pub struct SearchQuery {
+    /// FTS5 expression for the plain words and phrases.
+    pub(crate) fts: Option<String>,
+    /// `AND …` conditions for the field terms and exclusions.
+    pub(crate) conditions: String,
+    pub(crate) params: Vec<Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Colon,
+    Eq,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Op {
+    fn sql(self) -> &'static str {
+        match self {
+            Op::Colon | Op::Eq => "=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Span {
+    start: usize,
+    end: usize,
+}
+
+const FIELDS: [&str; 15] = [
+    "model", "tag", "sampler", "title", "seed", "steps", "cfg", "rating", "fav", "favorite", "has",
+    "ar", "dims", "created", "updated",
+];
+
+impl SearchQuery {
+    pub fn parse(input: &str) -> Result<Self, QueryError> {
+        Self::parse_with(input, true)
+    }
+
+    /// Parses a query saved with a smart collection. It isn't being typed,
+    /// so its last word matches whole rather than as a prefix.
+    pub fn parse_saved(input: &str) -> Result<Self, QueryError> {
+        Self::parse_with(input, false)
+    }
+
+    fn parse_with(input: &str, trailing_prefix: bool) -> Result<Self, QueryError> {
+        Parser {
+            chars: input.chars().collect(),
+            pos: 0,
+            query: SearchQuery::default(),
+            text: Vec::new(),
+            last_is_word: false,
+            trailing_prefix,
+        }
+        .run()
+    }
+
+    /// Whether the query has only plain words and phrases.
+    pub fn is_plain_text(&self) -> bool {
+        self.conditions.is_empty()
+    }
+
+    /// All of the query as `AND …` conditions over `prompts p`, full-text
+    /// part included, for when results don't need ranking.
+    pub(crate) fn to_conditions(&self) -> (String, Vec<Value>) {
+        let mut sql = String::new();
+        let mut params = Vec::new();
+        if let Some(fts) = &self.fts {
+            sql.push_str(" AND p.id IN (SELECT rowid FROM prompts_fts WHERE prompts_fts MATCH ?)");
+            params.push(Value::from(fts.clone()));
+        }
+        sql.push_str(&self.conditions);
+        params.extend(self.params.iter().cloned());
+        (sql, params)
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    query: SearchQuery,
+    /// Plain words and phrases, in FTS input syntax.
+    text: Vec<String>,
+    last_is_word: bool,
+    /// Whether a word still being typed at the end matches as a prefix.
+    trailing_prefix: bool,
+}
+
+impl Parser {
+    fn run(mut self) -> Result<SearchQuery, QueryError> {
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() {
+                self.pos += 1;
+                continue;
+            }
+            self.term()?;
+        }
+        // A word still being typed at the end matches as a prefix, as in the
+        // plain search; anything after it turns that off.
+        let mut text = self.text.join(" ");
+        if !(self.trailing_prefix && self.last_is_word) {
+            text.push(' ');
+        }
+        self.query.fts = build_fts_query(&text);
+        Ok(self.query)
+    }
+
+    fn term(&mut self) -> Result<(), QueryError> {
+        let negated = self.peek() == Some('-');
+        if negated {
+            self.pos += 1;
+            // A lone '-' is most likely an exclusion still being typed.
+            if self.peek().map_or(true, char::is_whitespace) {
+                return Ok(());
+            }
+        }
+
+        if self.peek() == Some('"') {
+            let phrase = self.quoted();
+            self.last_is_word = false;
+            return self.text_term(format!("\"{}\"", phrase), negated);
+        }
+
+        let name_start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') {
+            self.pos += 1;
+        }
+        let name: String = self.chars[name_start..self.pos].iter().collect();
+        let op_start = self.pos;
+        let is_field = FIELDS.contains(&name.to_ascii_lowercase().as_str());
+        match self.operator() {
+            Some(op) if is_field => {
+                let op_span = Span { start: op_start, end: self.pos };
+                let value_start = self.pos;
+                let value = if self.peek() == Some('"') {
+                    self.quoted()
+                } else {
+                    self.word()
+                };
+                if value.trim().is_empty() {
+                    return Err(error(
+                        &format!("Missing value for '{}'", name),
+                        name_start,
+                        self.pos,
+                    ));
+                }
+                let value_span = Span { start: value_start, end: self.pos };
+                let name_span = Span { start: name_start, end: op_start };
+                self.last_is_word = false;
+                self.field_term(&name, name_span, op, op_span, value.trim(), value_span, negated)
+            }
+            _ => {
+                self.pos = name_start;
+                let word = self.word();
+                self.last_is_word = !negated;
+                self.text_term(word, negated)
+            }
+        }
+    }
+
+    fn text_term(&mut self, text: String, negated: bool) -> Result<(), QueryError> {
+        if !negated {
+            self.text.push(text);
+            return Ok(());
+        }
+        // Trailing space: an excluded word never matches as a prefix.
+        if let Some(fts) = build_fts_query(&format!("{} ", text)) {
+            self.condition(
+                "p.id IN (SELECT rowid FROM prompts_fts WHERE prompts_fts MATCH ?)",
+                vec![Value::from(fts)],
+                true,
+            );
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn field_term(
+        &mut self,
+        name: &str,
+        name_span: Span,
+        op: Op,
+        op_span: Span,
+        value: &str,
+        value_span: Span,
+        negated: bool,
+    ) -> Result<(), QueryError> {
+        let field = name.to_ascii_lowercase();
+        let equality_only = |op: Op| {
+            if matches!(op, Op::Colon | Op::Eq) {
+                Ok(())
+            } else {
+                Err(error(
+                    &format!("'{}' can only be matched with ':' or '='", name),
+                    op_span.start,
+                    op_span.end,
+                ))
+            }
+        };
+
+        let (sql, params) = match field.as_str() {
+            "model" => {
+                equality_only(op)?;
+                // The prompt's own text or the registry model it's linked
+                // to, by name or short name, so `model:sdxl` finds
+                // "Stable Diffusion XL".
+                let (cmp, value): (fn(&str) -> String, String) = if op == Op::Eq {
+                    (|column| format!("{} = ? COLLATE NOCASE", column), value.to_string())
+                } else {
+                    // Separators are ignored, which also finds names taken
+                    // from metadata such as `sd_xl_base_1.0`.
+                    (
+                        |column| format!("{} LIKE ? ESCAPE '\\'", strip_separators_sql(column)),
+                        format!("%{}%", escape_like(&strip_separators(value))),
+                    )
+                };
+                (
+                    format!(
+                        "({} OR p.model_id IN (SELECT id FROM models WHERE {} OR {}))",
+                        cmp("p.model"),
+                        cmp("name"),
+                        cmp("short_name")
+                    ),
+                    vec![Value::from(value); 3],
+                )
+            }
+            "sampler" | "title" => {
+                equality_only(op)?;
+                let column = format!("p.{}", field);
+                if op == Op::Eq {
+                    (format!("{} = ? COLLATE NOCASE", column), vec![Value::from(value.to_string())])
+                } else {
+                    (
+                        format!("{} LIKE ? ESCAPE '\\'", column),
+                        vec![Value::from(format!("%{}%", escape_like(value)))],
+                    )
+                }
+            }
+            "tag" => {
+                equality_only(op)?;
+                (
+                    "p.id IN (SELECT pt.prompt_id FROM prompt_tags pt
+                      JOIN tags t ON t.id = pt.tag_id WHERE t.name = ? COLLATE NOCASE)"
+                        .to_string(),
+                    vec![Value::from(tags::normalize_tag(value))],
+                )
+            }
+            "seed" => {
+                equality_only(op)?;
+                ("p.seed = ?".to_string(), vec![Value::from(value.to_string())])
+            }
+            "ar" | "dims" => {
+                equality_only(op)?;
+                ("p.dimensions = ?".to_string(), vec![Value::from(value.to_string())])
+            }
+            "steps" | "rating" => {
+                let number: i64 = value.parse().map_err(|_| {
+                    error("Expected a whole number", value_span.start, value_span.end)
+                })?;
+                (format!("p.{} {} ?", field, op.sql()), vec![Value::from(number)])
+            }
+            "cfg" => {
+                let number: f64 = value
+                    .parse()
+                    .map_err(|_| error("Expected a number", value_span.start, value_span.end))?;
+                (format!("p.cfg_scale {} ?", op.sql()), vec![Value::from(number)])
+            }
+            "fav" | "favorite" => {
+                equality_only(op)?;
+                let yes = match value.to_ascii_lowercase().as_str() {
+                    "yes" | "true" | "1" => true,
+                    "no" | "false" | "0" => false,
+                    _ => {
+                        return Err(error(
+                            "Expected yes or no",
+                            value_span.start,
+                            value_span.end,
+                        ))
+                    }
+                };
+                (format!("p.is_favorite = {}", i32::from(yes)), Vec::new())
+            }
+            "has" => {
+                equality_only(op)?;
+                let sql = match value.to_ascii_lowercase().as_str() {
+                    "image" | "images" => "p.image_path IS NOT NULL",
+                    "workflow" => "p.workflow_json IS NOT NULL",
+                    _ => {
+                        return Err(error(
+                            "Expected image or workflow",
+                            value_span.start,
+                            value_span.end,
+                        ))
+                    }
+                };
+                (sql.to_string(), Vec::new())
+            }
+            "created" | "updated" => date_condition(&format!("p.{}_at", field), op, value)
+                .ok_or_else(|| {
+                    error(
+                        "Expected a date like 2024-03-15, 2024-03 or 2024, or an age like 30d",
+                        value_span.start,
+                        value_span.end,
+                    )
+                })?,
+            _ => {
+                let message = format!("Unknown field '{}'", name);
+                return Err(error(&message, name_span.start, name_span.end));
+            }
+        };
+        self.condition(&sql, params, negated);
+        Ok(())
+    }
+
+    fn condition(&mut self, sql: &str, params: Vec<Value>, negated: bool) {
+        if negated {
+            // Prompts without a value count as not matching, so they stay.
+            self.query
+                .conditions
+                .push_str(&format!(" AND NOT COALESCE(({}), 0)", sql));
+        } else {
+            self.query.conditions.push_str(&format!(" AND ({})", sql));
+        }
+        self.query.params.extend(params);
+    }
+
+    fn operator(&mut self) -> Option<Op> {
+        let op = match (self.peek(), self.chars.get(self.pos + 1).copied()) {
+            (Some('>'), Some('=')) => Op::Ge,
+            (Some('<'), Some('=')) => Op::Le,
+            (Some(':'), _) => Op::Colon,
+            (Some('='), _) => Op::Eq,
+            (Some('>'), _) => Op::Gt,
+            (Some('<'), _) => Op::Lt,
+            _ => return None,
+        };
+        self.pos += if matches!(op, Op::Ge | Op::Le) { 2 } else { 1 };
+        Some(op)
+    }
+
+    /// Text up to the next space or quote.
+    fn word(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| !c.is_whitespace() && c != '"') {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// Text between double quotes, starting at the opening one. A quote
+    /// still open at the end runs to the end, as it does while typing.
+    fn quoted(&mut self) -> String {
+        self.pos += 1;
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c != '"') {
+            self.pos += 1;
+        }
+        let text = self.chars[start..self.pos].iter().collect();
+        if self.peek().is_some() {
+            self.pos += 1;
+        }
+        text
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+}
+
+fn error(message: &str, start: usize, end: usize) -> QueryError {
+    QueryError {
+        message: message.to_string(),
+        start,
+        end: end.max(start + 1),
+    }
+}
+
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn strip_separators(value: &str) -> String {
+    value.chars().filter(|c| !matches!(c, ' ' | '_' | '-')).collect()
+}
+
+/// `column` without spaces, `_` and `-`, as `strip_separators` does.
+fn strip_separators_sql(column: &str) -> String {
+    format!("REPLACE(REPLACE(REPLACE({}, ' ', ''), '_', ''), '-', '')", column)
+}
+
+/// Condition comparing a timestamp column with a calendar period
+/// (`2024`, `2024-03`, `2024-03-15`) or an age (`30d`, `2w`, `6m`, `1y`).
+fn date_condition(column: &str, op: Op, value: &str) -> Option<(String, Vec<Value>)> {
+    if let Some(modifier) = age_modifier(value) {
+        // "Within the last …" unless asked for older prompts.
+        let cmp = match op {
+            Op::Lt | Op::Le => "<",
+            _ => ">=",
+        };
+        return Some((
+            format!("{} {} datetime('now', ?)", column, cmp),
+            vec![Value::from(modifier)],
+        ));
+    }
+
+    let (start, end) = period(value)?;
+    let start = Value::from(start.format("%Y-%m-%d 00:00:00").to_string());
+    let end = Value::from(end.format("%Y-%m-%d 00:00:00").to_string());
+    Some(match op {
+        Op::Colon | Op::Eq => (format!("{0} >= ? AND {0} < ?", column), vec![start, end]),
+        Op::Ge => (format!("{} >= ?", column), vec![start]),
+        Op::Gt => (format!("{} >= ?", column), vec![end]),
+        Op::Lt => (format!("{} < ?", column), vec![start]),
+        Op::Le => (format!("{} < ?", column), vec![end]),
+    })
+}
+
+/// First day of the period and first day after it.
+fn period(value: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let parts: Vec<&str> = value.split('-').collect();
+    let number = |i: usize| parts.get(i)?.parse::<u32>().ok();
+    match parts.len() {
+        1 if parts[0].len() == 4 => {
+            let year = number(0)? as i32;
+            Some((
+                NaiveDate::from_ymd_opt(year, 1, 1)?,
+                NaiveDate::from_ymd_opt(year + 1, 1, 1)?,
+            ))
+        }
+        2 => {
+            let start = NaiveDate::from_ymd_opt(number(0)? as i32, number(1)?, 1)?;
+            let end = if start.month() == 12 {
+                NaiveDate::from_ymd_opt(start.year() + 1, 1, 1)?
+            } else {
+                NaiveDate::from_ymd_opt(start.year(), start.month() + 1, 1)?
+            };
+            Some((start, end))
+        }
+        3 => {
+            let day = NaiveDate::from_ymd_opt(number(0)? as i32, number(1)?, number(2)?)?;
+            Some((day, day.succ_opt()?))
+        }
+        _ => None,
+    }
+}
+
+/// SQLite date modifier for an age such as `30d`.
+fn age_modifier(value: &str) -> Option<String> {
+    let unit = value.chars().last()?;
+    let amount: u32 = value[..value.len() - unit.len_utf8()].parse().ok()?;
+    Some(match unit.to_ascii_lowercase() {
+        'd' => format!("-{} days", amount),
+        'w' => format!("-{} days", amount.checked_mul(7)?),
+        'm' => format!("-{} months", amount),
+        'y' => format!("-{} years", amount),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::{params_from_iter, Connection};
+
+    fn text(value: &Value) -> &str {
+        match value {
+            Value::Text(text) => text,
+            other => panic!("expected text, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_the_full_syntax() {
+        let input = r#"model:sdxl tag:portrait steps>=30 -tag:nsfw "golden hour" fav:yes"#;
+        let query = SearchQuery::parse(input).unwrap();
+        assert!(!query.is_plain_text());
+        assert!(query.fts.is_some());
+        assert_eq!(query.conditions.matches(" AND ").count(), 5);
+        assert!(query.conditions.contains("p.steps >= ?"));
+        assert!(query.conditions.contains("NOT COALESCE((p.id IN (SELECT pt.prompt_id"));
+        assert!(query.conditions.contains("p.is_favorite = 1"));
+        assert_eq!(query.params.len(), 6);
+        assert_eq!(text(&query.params[0]), "%sdxl%");
+        assert_eq!(query.params[4], Value::Integer(30));
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_part() {
+        let at = |input: &str| {
+            let e = SearchQuery::parse(input).unwrap_err();
+            (e.start, e.end)
+        };
+        assert_eq!(at("cat steps>=abc"), (11, 14));
+        assert_eq!(at("tag>3"), (3, 4));
+        assert_eq!(at("model:sdxl cfg<=high"), (16, 20));
+        assert_eq!(at("fav:maybe"), (4, 9));
+        assert_eq!(at("created:yesterday"), (8, 17));
+        // A missing value underlines the field name and operator.
+        assert_eq!(at("steps>="), (0, 7));
+        assert_eq!(at(r#"model:"""#), (0, 8));
+    }
+
+    #[test]
+    fn oversized_ages_are_errors() {
+        assert!(SearchQuery::parse("created>=2w").is_ok());
+        let e = SearchQuery::parse("created>=999999999w").unwrap_err();
+        assert_eq!((e.start, e.end), (9, 19));
+    }
+
+    #[test]
+    fn quoted_values_keep_their_spaces() {
+        let query = SearchQuery::parse(r#"title:"flux dev" sampler="dpm++ 2m""#).unwrap();
+        assert_eq!(text(&query.params[0]), "%flux dev%");
+        assert_eq!(text(&query.params[1]), "dpm++ 2m");
+        assert!(query.fts.is_none());
+    }
+
+    #[test]
+    fn saved_queries_match_whole_words() {
+        let typed = SearchQuery::parse("model:flux product").unwrap();
+        assert!(typed.fts.unwrap().ends_with('*'));
+        let saved = SearchQuery::parse_saved("model:flux product").unwrap();
+        assert!(!saved.fts.unwrap().contains('*'));
+        assert!(SearchQuery::parse_saved("product*").unwrap().fts.unwrap().ends_with('*'));
+    }
+
+    #[test]
+    fn unknown_fields_are_plain_text() {
+        for input in ["colour:red", "masterpiece:1.2", "https://example.com", "Steps_x=3"] {
+            let query = SearchQuery::parse(input).unwrap();
+            assert!(query.is_plain_text(), "{}", input);
+            assert!(query.fts.is_some(), "{}", input);
+        }
+        assert!(SearchQuery::parse("golden hour").unwrap().is_plain_text());
+        assert!(SearchQuery::parse("-colour:red").unwrap().conditions.contains("NOT COALESCE"));
+    }
+
+    /// A database at the current schema, with `rows` inserted into prompts.
+    fn vault(rows: &str) -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::db::migrations::run(&mut conn, std::path::Path::new("unused")).unwrap();
+        conn.execute_batch(rows).unwrap();
+        conn
+    }
+
+    fn matching_ids(conn: &Connection, input: &str) -> Vec<i64> {
+        let query = SearchQuery::parse(input).unwrap();
+        let sql = format!("SELECT id FROM prompts p WHERE 1 {} ORDER BY id", query.conditions);
+        let mut stmt = conn.prepare(&sql).unwrap();
+        let rows = stmt.query_map(params_from_iter(query.params.iter()), |row| row.get(0));
+        rows.unwrap().collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn model_matches_the_registry() {
+        let conn = vault(
+            "INSERT INTO prompts (id, title, prompt_text, model, model_id)
+             SELECT 1, 'a', 'a', name, id FROM models WHERE short_name = 'SDXL';
+             INSERT INTO prompts (id, title, prompt_text, model)
+             VALUES (2, 'b', 'b', 'sd_xl_base_1.0'), (3, 'c', 'c', 'flux1-dev');",
+        );
+        assert_eq!(matching_ids(&conn, "model:sdxl"), vec![1, 2]);
+        assert_eq!(matching_ids(&conn, "model=SDXL"), vec![1]);
+        assert_eq!(matching_ids(&conn, r#"model:"stable diffusion""#), vec![1]);
+        assert_eq!(matching_ids(&conn, "-model:sdxl"), vec![3]);
+        assert_eq!(matching_ids(&conn, "model:flux"), vec![3]);
+    }
+
+    #[test]
+    fn negation_keeps_prompts_without_a_value() {
+        let conn = vault(
+            "INSERT INTO prompts (id, title, prompt_text, model, steps, cfg_scale, sampler)
+             VALUES (1, 'a', 'a', 'sdxl', 30, 7.0, 'euler'), (2, 'b', 'b', 'flux', 20, NULL, NULL),
+                    (3, 'c', 'c', '', NULL, NULL, NULL);",
+        );
+        assert_eq!(matching_ids(&conn, "steps>=25"), vec![1]);
+        assert_eq!(matching_ids(&conn, "-steps>=25"), vec![2, 3]);
+        assert_eq!(matching_ids(&conn, "-sampler:euler"), vec![2, 3]);
+        assert_eq!(matching_ids(&conn, "-cfg>5"), vec![2, 3]);
+    }
+}
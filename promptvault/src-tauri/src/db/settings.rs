@@ -0,0 +1,75 @@
+use super::Database;
+use rusqlite::{params, OptionalExtension, Result};
+use serde::Serialize;
+
+/// Days a prompt stays in the trash before it is purged on startup.
+/// `0` disables the automatic purge.
+pub const TRASH_RETENTION_DAYS: &str = "trash_retention_days";
+const DEFAULT_TRASH_RETENTION_DAYS: u32 = 30;
+
+/// Hours between automatic vault backups. `0` turns them off.
+pub const BACKUP_INTERVAL_HOURS: &str = "backup_interval_hours";
+/// How many automatic backups are kept; older ones are deleted.
+pub const BACKUP_RETENTION: &str = "backup_retention";
+/// When the last automatic backup was made, as RFC 3339.
+pub const LAST_BACKUP_AT: &str = "last_backup_at";
+const DEFAULT_BACKUP_INTERVAL_HOURS: u32 = 0;
+const DEFAULT_BACKUP_RETENTION: u32 = 7;
+
+#[derive(Debug, Serialize)]
+pub struct BackupSchedule {
+    pub interval_hours: u32,
+    pub retention: u32,
+    pub last_backup_at: Option<String>,
+}
+
+impl Database {
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        self.conn
+            .query_row(
+                "SELECT value FROM settings WHERE key = ?",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    pub fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO settings (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+
+    pub fn trash_retention_days(&self) -> Result<u32> {
+        Ok(self
+            .get_setting(TRASH_RETENTION_DAYS)?
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_TRASH_RETENTION_DAYS))
+    }
+
+    pub fn set_trash_retention_days(&self, days: u32) -> Result<()> {
+        self.set_setting(TRASH_RETENTION_DAYS, &days.to_string())
+    }
+
+    pub fn backup_schedule(&self) -> Result<BackupSchedule> {
+        Ok(BackupSchedule {
+            interval_hours: self
+                .get_setting(BACKUP_INTERVAL_HOURS)?
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_BACKUP_INTERVAL_HOURS),
+            retention: self
+                .get_setting(BACKUP_RETENTION)?
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_BACKUP_RETENTION),
+            last_backup_at: self.get_setting(LAST_BACKUP_AT)?,
+        })
+    }
+
+    pub fn set_backup_schedule(&self, interval_hours: u32, retention: u32) -> Result<()> {
+        self.set_setting(BACKUP_INTERVAL_HOURS, &interval_hours.to_string())?;
+        self.set_setting(BACKUP_RETENTION, &retention.to_string())
+    }
+}
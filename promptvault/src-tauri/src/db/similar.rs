@@ -0,0 +1,50 @@
+use super::Database;
+use rusqlite::{params, Result};
+
+/// The perceptual hash of one image, with the prompt it belongs to.
+#[derive(Debug, Clone, Copy)]
+pub struct ImageHash {
+    pub image_id: i32,
+    pub prompt_id: i32,
+    pub hash: i64,
+}
+
+impl Database {
+    /// Perceptual hashes of all images of prompts outside the trash.
+    pub fn get_perceptual_hashes(&self) -> Result<Vec<ImageHash>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT i.id, i.prompt_id, i.perceptual_hash FROM prompt_images i
+             JOIN prompts p ON p.id = i.prompt_id
+             WHERE i.perceptual_hash IS NOT NULL AND p.deleted_at IS NULL",
+        )?;
+        let hash_iter = stmt.query_map([], |row| {
+            Ok(ImageHash {
+                image_id: row.get(0)?,
+                prompt_id: row.get(1)?,
+                hash: row.get(2)?,
+            })
+        })?;
+        hash_iter.collect()
+    }
+
+    /// Images without a perceptual hash yet, as `(id, image_path,
+    /// thumbnail_path)`.
+    pub fn get_images_without_perceptual_hash(
+        &self,
+    ) -> Result<Vec<(i32, String, Option<String>)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, image_path, thumbnail_path FROM prompt_images
+             WHERE perceptual_hash IS NULL ORDER BY id",
+        )?;
+        let image_iter = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        image_iter.collect()
+    }
+
+    pub fn set_perceptual_hash(&self, image_id: i32, hash: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE prompt_images SET perceptual_hash = ?1 WHERE id = ?2",
+            params![hash, image_id],
+        )?;
+        Ok(())
+    }
+}
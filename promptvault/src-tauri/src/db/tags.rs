@@ -0,0 +1,169 @@
+use super::Database;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize)]
+pub struct Tag {
+    pub id: i32,
+    pub name: String,
+    pub prompt_count: i32,
+}
+
+/// How a list of tags filters prompts: `all` (AND), `any` (OR) or `none`
+/// (prompts with none of the tags).
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatch {
+    #[default]
+    All,
+    Any,
+    None,
+}
+
+impl Database {
+    pub fn get_tags(&self) -> Result<Vec<Tag>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT t.id, t.name, COUNT(p.id) AS prompt_count
+             FROM tags t
+             LEFT JOIN prompt_tags pt ON pt.tag_id = t.id
+             LEFT JOIN prompts p ON p.id = pt.prompt_id AND p.deleted_at IS NULL
+             GROUP BY t.id
+             ORDER BY t.name COLLATE NOCASE",
+        )?;
+
+        let tag_iter = stmt.query_map([], tag_from_row)?;
+        tag_iter.collect()
+    }
+
+    pub fn get_tag(&self, id: i32) -> Result<Tag> {
+        self.conn.query_row(
+            "SELECT t.id, t.name, COUNT(p.id) AS prompt_count
+             FROM tags t
+             LEFT JOIN prompt_tags pt ON pt.tag_id = t.id
+             LEFT JOIN prompts p ON p.id = pt.prompt_id AND p.deleted_at IS NULL
+             WHERE t.id = ?
+             GROUP BY t.id",
+            params![id],
+            tag_from_row,
+        )
+    }
+
+    /// Case-insensitive lookup, matching the collation of `tags.name`.
+    pub fn find_tag_id(&self, name: &str) -> Result<Option<i32>> {
+        self.conn
+            .query_row(
+                "SELECT id FROM tags WHERE name = ?",
+                params![name.trim()],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    pub fn rename_tag(&self, id: i32, name: &str) -> Result<Tag> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "UPDATE tags SET name = ?1 WHERE id = ?2",
+            params![normalize_tag(name), id],
+        )?;
+        refresh_tags_column(&tx, &prompts_with_tags(&tx, &[id])?)?;
+        tx.commit()?;
+        self.get_tag(id)
+    }
+
+    /// Moves every prompt tagged with one of `source_ids` to `target_id` and
+    /// removes the source tags.
+    pub fn merge_tags(&self, source_ids: &[i32], target_id: i32) -> Result<Tag> {
+        let source_ids: Vec<i32> = source_ids
+            .iter()
+            .copied()
+            .filter(|id| *id != target_id)
+            .collect();
+
+        let tx = self.conn.unchecked_transaction()?;
+        let affected = prompts_with_tags(&tx, &source_ids)?;
+        for source_id in &source_ids {
+            tx.execute(
+                "INSERT OR IGNORE INTO prompt_tags (prompt_id, tag_id, position)
+                 SELECT prompt_id, ?1, position FROM prompt_tags WHERE tag_id = ?2",
+                params![target_id, source_id],
+            )?;
+            tx.execute("DELETE FROM tags WHERE id = ?", params![source_id])?;
+        }
+        refresh_tags_column(&tx, &affected)?;
+        tx.commit()?;
+        self.get_tag(target_id)
+    }
+
+    pub fn delete_tag(&self, id: i32) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let affected = prompts_with_tags(&tx, &[id])?;
+        tx.execute("DELETE FROM tags WHERE id = ?", params![id])?;
+        refresh_tags_column(&tx, &affected)?;
+        tx.commit()
+    }
+}
+
+fn tag_from_row(row: &rusqlite::Row) -> Result<Tag> {
+    Ok(Tag {
+        id: row.get("id")?,
+        name: row.get("name")?,
+        prompt_count: row.get("prompt_count")?,
+    })
+}
+
+/// Trims and collapses internal whitespace, so "golden  hour " and
+/// "golden hour" are the same tag.
+pub(crate) fn normalize_tag(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Replaces the tags of a prompt, keeping the given order. Names are matched
+/// case-insensitively against existing tags, so the first spelling ever used
+/// for a tag is the one that sticks.
+pub(crate) fn set_prompt_tags(conn: &Connection, prompt_id: i32, tags: &[String]) -> Result<()> {
+    conn.execute("DELETE FROM prompt_tags WHERE prompt_id = ?", params![prompt_id])?;
+
+    let mut position = 0;
+    for name in tags.iter().map(|t| normalize_tag(t)) {
+        if name.is_empty() {
+            continue;
+        }
+        conn.execute("INSERT OR IGNORE INTO tags (name) VALUES (?)", params![name])?;
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO prompt_tags (prompt_id, tag_id, position)
+             SELECT ?1, id, ?2 FROM tags WHERE name = ?3",
+            params![prompt_id, position, name],
+        )?;
+        position += inserted as i32;
+    }
+
+    refresh_tags_column(conn, &[prompt_id])
+}
+
+fn prompts_with_tags(conn: &Connection, tag_ids: &[i32]) -> Result<Vec<i32>> {
+    let ids_json = serde_json::to_string(tag_ids).unwrap_or_else(|_| "[]".to_string());
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT prompt_id FROM prompt_tags
+         WHERE tag_id IN (SELECT value FROM json_each(?))",
+    )?;
+    let id_iter = stmt.query_map(params![ids_json], |row| row.get(0))?;
+    id_iter.collect()
+}
+
+/// Rewrites the `prompts.tags` JSON cache from `prompt_tags`.
+fn refresh_tags_column(conn: &Connection, prompt_ids: &[i32]) -> Result<()> {
+    let ids_json = serde_json::to_string(prompt_ids).unwrap_or_else(|_| "[]".to_string());
+    conn.execute(
+        "UPDATE prompts SET tags = (
+            SELECT json_group_array(name) FROM (
+                SELECT t.name FROM prompt_tags pt
+                JOIN tags t ON t.id = pt.tag_id
+                WHERE pt.prompt_id = prompts.id
+                ORDER BY pt.position
+            )
+         )
+         WHERE id IN (SELECT value FROM json_each(?))",
+        params![ids_json],
+    )?;
+    Ok(())
+}
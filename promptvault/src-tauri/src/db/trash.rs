@@ -0,0 +1,79 @@
+use super::{prompt_from_row, Database, Prompt, PROMPT_COLUMNS};
+use rusqlite::{params, Result};
+
+impl Database {
+    /// Trashed prompts, most recently deleted first.
+    pub fn get_trash(&self) -> Result<Vec<Prompt>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM prompts p WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC",
+            PROMPT_COLUMNS
+        ))?;
+        let prompt_iter = stmt.query_map([], prompt_from_row)?;
+        prompt_iter.collect()
+    }
+
+    pub fn restore_prompt(&self, id: i32) -> Result<Prompt> {
+        self.conn.execute(
+            "UPDATE prompts SET deleted_at = NULL WHERE id = ?",
+            params![id],
+        )?;
+        self.get_prompt_by_id(id)
+    }
+
+    /// Permanently removes a trashed prompt together with its image files.
+    /// Prompts that are not in the trash are left alone.
+    pub fn purge_prompt(&self, id: i32) -> Result<()> {
+        let prompt = self.get_prompt_by_id(id)?;
+        if prompt.deleted_at.is_none() {
+            return Ok(());
+        }
+
+        self.conn.execute("DELETE FROM prompts WHERE id = ?", params![id])?;
+        self.remove_prompt_files(&prompt)
+    }
+
+    /// Purges everything in the trash and returns how many prompts were removed.
+    pub fn empty_trash(&self) -> Result<usize> {
+        let ids = self.trashed_ids(None)?;
+        for id in &ids {
+            self.purge_prompt(*id)?;
+        }
+        Ok(ids.len())
+    }
+
+    /// Purges prompts that have been in the trash for more than `days` days.
+    pub fn purge_expired_trash(&self, days: u32) -> Result<usize> {
+        let ids = self.trashed_ids(Some(days))?;
+        for id in &ids {
+            self.purge_prompt(*id)?;
+        }
+        Ok(ids.len())
+    }
+
+    /// Purges what has been in the trash longer than the retention setting
+    /// allows; nothing when it's 0 (keep forever).
+    pub fn purge_trash_past_retention(&self) -> Result<usize> {
+        match self.trash_retention_days()? {
+            0 => Ok(0),
+            days => self.purge_expired_trash(days),
+        }
+    }
+
+    fn trashed_ids(&self, older_than_days: Option<u32>) -> Result<Vec<i32>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id FROM prompts
+             WHERE deleted_at IS NOT NULL
+               AND (?1 IS NULL OR deleted_at <= datetime('now', '-' || ?1 || ' days'))",
+        )?;
+        let id_iter = stmt.query_map(params![older_than_days], |row| row.get(0))?;
+        id_iter.collect()
+    }
+
+    /// Deletes the image files of a purged prompt that no other prompt shares.
+    fn remove_prompt_files(&self, prompt: &Prompt) -> Result<()> {
+        for image in &prompt.images {
+            self.remove_unused_image_files(&image.image_path, image.thumbnail_path.as_deref())?;
+        }
+        Ok(())
+    }
+}
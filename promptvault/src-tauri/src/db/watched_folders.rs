@@ -0,0 +1,115 @@
+use super::Database;
+use rusqlite::{params, OptionalExtension, Result, Row};
+use serde::{Deserialize, Serialize};
+
+/// A folder whose new images are imported automatically.
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchedFolder {
+    pub id: i32,
+    pub path: String,
+    /// Collection new prompts are added to.
+    pub collection_id: Option<i32>,
+    /// Model for images whose metadata doesn't name one.
+    pub model: Option<String>,
+    pub recursive: bool,
+    pub is_active: bool,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WatchedFolderSettings {
+    pub collection_id: Option<i32>,
+    pub model: Option<String>,
+    pub recursive: Option<bool>,
+    pub is_active: Option<bool>,
+}
+
+impl Database {
+    pub fn get_watched_folders(&self) -> Result<Vec<WatchedFolder>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT * FROM watched_folders ORDER BY path")?;
+        let folder_iter = stmt.query_map([], folder_from_row)?;
+        folder_iter.collect()
+    }
+
+    pub fn get_watched_folder(&self, id: i32) -> Result<WatchedFolder> {
+        self.conn.query_row(
+            "SELECT * FROM watched_folders WHERE id = ?",
+            params![id],
+            folder_from_row,
+        )
+    }
+
+    pub fn find_watched_folder_id(&self, path: &str) -> Result<Option<i32>> {
+        self.conn
+            .query_row(
+                "SELECT id FROM watched_folders WHERE path = ?",
+                params![path],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    pub fn add_watched_folder(
+        &self,
+        path: &str,
+        settings: &WatchedFolderSettings,
+    ) -> Result<WatchedFolder> {
+        self.conn.execute(
+            "INSERT INTO watched_folders (path, collection_id, model, recursive, is_active)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                path,
+                settings.collection_id,
+                settings.model.as_deref().filter(|m| !m.trim().is_empty()),
+                settings.recursive.unwrap_or(true),
+                settings.is_active.unwrap_or(true),
+            ],
+        )?;
+        self.get_watched_folder(self.conn.last_insert_rowid() as i32)
+    }
+
+    /// Replaces the settings of a watched folder. Fields left out keep their
+    /// value, except `collection_id` and `model`, which are cleared.
+    pub fn update_watched_folder(
+        &self,
+        id: i32,
+        settings: &WatchedFolderSettings,
+    ) -> Result<WatchedFolder> {
+        self.conn.execute(
+            "UPDATE watched_folders SET
+                collection_id = ?1,
+                model = ?2,
+                recursive = COALESCE(?3, recursive),
+                is_active = COALESCE(?4, is_active)
+             WHERE id = ?5",
+            params![
+                settings.collection_id,
+                settings.model.as_deref().filter(|m| !m.trim().is_empty()),
+                settings.recursive,
+                settings.is_active,
+                id,
+            ],
+        )?;
+        self.get_watched_folder(id)
+    }
+
+    pub fn remove_watched_folder(&self, id: i32) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM watched_folders WHERE id = ?", params![id])?;
+        Ok(())
+    }
+}
+
+fn folder_from_row(row: &Row) -> Result<WatchedFolder> {
+    Ok(WatchedFolder {
+        id: row.get("id")?,
+        path: row.get("path")?,
+        collection_id: row.get("collection_id")?,
+        model: row.get("model")?,
+        recursive: row.get::<_, i32>("recursive")? != 0,
+        is_active: row.get::<_, i32>("is_active")? != 0,
+        created_at: row.get("created_at")?,
+    })
+}
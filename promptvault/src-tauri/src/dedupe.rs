@@ -0,0 +1,49 @@
+use crate::db::DuplicateGroup;
+use crate::image_store;
+use crate::AppState;
+use serde::Serialize;
+use std::fs;
+
+#[derive(Debug, Serialize)]
+pub struct DedupeReport {
+    /// Images that got a content hash in this scan.
+    pub hashed: usize,
+    /// Images whose file could not be read, left unhashed.
+    pub missing: usize,
+    /// Duplicate files deleted after their images were pointed at one copy.
+    pub removed_files: usize,
+    pub bytes_freed: u64,
+    /// Prompts that still share an image, for the user to review.
+    pub duplicates: Vec<DuplicateGroup>,
+}
+
+/// Vault-wide dedupe: hashes the images stored before content hashes were
+/// recorded, then keeps one file per distinct image. Files are read without
+/// holding the database lock.
+pub fn dedupe_vault(state: &AppState) -> Result<DedupeReport, String> {
+    let (unhashed, data_dir) = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        let unhashed = db.get_unhashed_images().map_err(|e| e.to_string())?;
+        (unhashed, db.get_data_dir().clone())
+    };
+
+    let mut hashes = Vec::with_capacity(unhashed.len());
+    let mut missing = 0;
+    for (id, image_path) in unhashed {
+        match fs::read(data_dir.join(&image_path)) {
+            Ok(data) => hashes.push((id, image_path, image_store::content_hash(&data))),
+            Err(_) => missing += 1,
+        }
+    }
+
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_image_hashes(&hashes).map_err(|e| e.to_string())?;
+    let (removed_files, bytes_freed) = db.merge_duplicate_images().map_err(|e| e.to_string())?;
+    Ok(DedupeReport {
+        hashed: hashes.len(),
+        missing,
+        removed_files,
+        bytes_freed,
+        duplicates: db.get_duplicate_groups().map_err(|e| e.to_string())?,
+    })
+}
@@ -0,0 +1,180 @@
+use serde::Serialize;
+
+/// Largest LCS table worth building; beyond it the changed middle of the
+/// texts is shown as one deletion and one insertion.
+const MAX_TABLE_CELLS: usize = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffSpan {
+    pub op: DiffOp,
+    pub text: String,
+}
+
+/// Splits text into words and the whitespace between them, so joining the
+/// tokens gives back the original string.
+fn tokenize(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut in_space = None;
+
+    for (i, c) in text.char_indices() {
+        let is_space = c.is_whitespace();
+        if in_space.is_some_and(|prev| prev != is_space) {
+            tokens.push(&text[start..i]);
+            start = i;
+        }
+        in_space = Some(is_space);
+    }
+    if start < text.len() {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
+
+/// Word-level diff from `old` to `new` using a longest common subsequence
+/// table over the part between their common prefix and suffix. The table is
+/// quadratic, so when that part is too long for it (pasted essays rather
+/// than prompts) it's shown as replaced whole. Adjacent spans with the same
+/// op are merged.
+pub fn diff_words(old: &str, new: &str) -> Vec<DiffSpan> {
+    let a = tokenize(old);
+    let b = tokenize(new);
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut spans: Vec<DiffSpan> = Vec::new();
+    let mut push = |op: DiffOp, text: &str| match spans.last_mut() {
+        Some(last) if last.op == op => last.text.push_str(text),
+        _ => spans.push(DiffSpan { op, text: text.to_string() }),
+    };
+
+    for token in &a[..prefix] {
+        push(DiffOp::Equal, token);
+    }
+    if (a_mid.len() + 1).saturating_mul(b_mid.len() + 1) > MAX_TABLE_CELLS {
+        for token in a_mid {
+            push(DiffOp::Delete, token);
+        }
+        for token in b_mid {
+            push(DiffOp::Insert, token);
+        }
+    } else {
+        diff_tokens(a_mid, b_mid, &mut push);
+    }
+    for token in &a[a.len() - suffix..] {
+        push(DiffOp::Equal, token);
+    }
+
+    spans
+}
+
+fn diff_tokens(a: &[&str], b: &[&str], push: &mut impl FnMut(DiffOp, &str)) {
+    // lcs[i][j] = length of the LCS of a[i..] and b[j..]
+    let mut lcs = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            push(DiffOp::Equal, a[i]);
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            push(DiffOp::Delete, a[i]);
+            i += 1;
+        } else {
+            push(DiffOp::Insert, b[j]);
+            j += 1;
+        }
+    }
+    for token in &a[i..] {
+        push(DiffOp::Delete, token);
+    }
+    for token in &b[j..] {
+        push(DiffOp::Insert, token);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ops(spans: &[DiffSpan]) -> Vec<(DiffOp, &str)> {
+        spans.iter().map(|span| (span.op, span.text.as_str())).collect()
+    }
+
+    #[test]
+    fn identical_text_is_one_equal_span() {
+        let spans = diff_words("a cat, golden hour", "a cat, golden hour");
+        assert_eq!(ops(&spans), vec![(DiffOp::Equal, "a cat, golden hour")]);
+        assert!(diff_words("", "").is_empty());
+    }
+
+    #[test]
+    fn insertions_and_deletions() {
+        let spans = diff_words("a cat", "a fluffy cat");
+        assert_eq!(
+            ops(&spans),
+            vec![(DiffOp::Equal, "a "), (DiffOp::Insert, "fluffy "), (DiffOp::Equal, "cat")]
+        );
+        let spans = diff_words("a fluffy cat", "a cat");
+        assert_eq!(
+            ops(&spans),
+            vec![(DiffOp::Equal, "a "), (DiffOp::Delete, "fluffy "), (DiffOp::Equal, "cat")]
+        );
+        assert_eq!(ops(&diff_words("", "cat")), vec![(DiffOp::Insert, "cat")]);
+    }
+
+    #[test]
+    fn replacements_delete_then_insert() {
+        let spans = diff_words("a cat in the sun", "a dog in the rain");
+        assert_eq!(
+            ops(&spans),
+            vec![
+                (DiffOp::Equal, "a "),
+                (DiffOp::Delete, "cat"),
+                (DiffOp::Insert, "dog"),
+                (DiffOp::Equal, " in the "),
+                (DiffOp::Delete, "sun"),
+                (DiffOp::Insert, "rain"),
+            ]
+        );
+    }
+
+    #[test]
+    fn long_texts_fall_back_to_a_whole_replacement() {
+        let words = |prefix: &str| {
+            let words: Vec<String> = (0..3000).map(|i| format!("{}{}", prefix, i)).collect();
+            words.join(" ")
+        };
+        let (old, new) = (words("old"), words("new"));
+        let spans = diff_words(&format!("start {} end", old), &format!("start {} end", new));
+        assert_eq!(spans.len(), 4);
+        assert_eq!(ops(&spans)[0], (DiffOp::Equal, "start "));
+        assert_eq!(ops(&spans)[1], (DiffOp::Delete, old.as_str()));
+        assert_eq!(ops(&spans)[2], (DiffOp::Insert, new.as_str()));
+        assert_eq!(ops(&spans)[3], (DiffOp::Equal, " end"));
+    }
+}
@@ -0,0 +1,199 @@
+use crate::db::Prompt;
+use serde::Serialize;
+use std::io::Cursor;
+use std::path::Path;
+
+/// Keyword of the PNG chunk holding the PromptVault JSON.
+pub const PROMPTVAULT_CHUNK: &str = "promptvault";
+
+/// The prompt as stored in the `promptvault` chunk.
+#[derive(Serialize)]
+struct PromptRecord<'a> {
+    version: u32,
+    title: &'a str,
+    prompt_text: &'a str,
+    negative_prompt: &'a str,
+    model: &'a str,
+    dimensions: &'a str,
+    steps: Option<i32>,
+    sampler: Option<&'a str>,
+    cfg_scale: Option<f64>,
+    seed: Option<&'a str>,
+    tags: &'a [String],
+    created_at: &'a str,
+}
+
+impl<'a> PromptRecord<'a> {
+    fn new(prompt: &'a Prompt) -> Self {
+        PromptRecord {
+            version: 1,
+            title: &prompt.title,
+            prompt_text: &prompt.prompt_text,
+            negative_prompt: &prompt.negative_prompt,
+            model: &prompt.model,
+            dimensions: &prompt.dimensions,
+            steps: prompt.steps,
+            sampler: prompt.sampler.as_deref(),
+            cfg_scale: prompt.cfg_scale,
+            seed: prompt.seed.as_deref(),
+            tags: &prompt.tags,
+            created_at: &prompt.created_at,
+        }
+    }
+}
+
+/// Prompts as a JSON array, each in the format of the `promptvault` chunk.
+pub fn prompts_json(prompts: &[Prompt]) -> Result<String, String> {
+    let records: Vec<PromptRecord> = prompts.iter().map(PromptRecord::new).collect();
+    serde_json::to_string_pretty(&records).map_err(|e| e.to_string())
+}
+
+/// Re-encodes an image of `prompt` as PNG carrying its generation settings:
+/// an A1111 `parameters` chunk, the ComfyUI `workflow` if one is stored, and
+/// with `include_json` a `promptvault` chunk with the full prompt.
+/// `image_path` is relative to the data dir.
+pub fn prompt_png(
+    data_dir: &Path,
+    prompt: &Prompt,
+    image_path: &str,
+    workflow: Option<&str>,
+    include_json: bool,
+) -> Result<Vec<u8>, String> {
+    let image = image::open(data_dir.join(image_path)).map_err(|e| e.to_string())?;
+    let (width, height) = (image.width(), image.height());
+    let (color, pixels) = if image.color().has_alpha() {
+        (png::ColorType::Rgba, image.into_rgba8().into_raw())
+    } else {
+        (png::ColorType::Rgb, image.into_rgb8().into_raw())
+    };
+
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(Cursor::new(&mut out), width, height);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    // tEXt is Latin-1, so prompts with other characters go in an iTXt chunk.
+    let parameters = parameters_text(prompt, width, height);
+    if parameters.chars().all(|c| (c as u32) < 0x100) {
+        encoder.add_text_chunk("parameters".to_string(), parameters)
+    } else {
+        encoder.add_itxt_chunk("parameters".to_string(), parameters)
+    }
+    .map_err(|e| e.to_string())?;
+    if let Some(workflow) = workflow {
+        encoder
+            .add_itxt_chunk("workflow".to_string(), workflow.to_string())
+            .map_err(|e| e.to_string())?;
+    }
+    if include_json {
+        let json = serde_json::to_string(&PromptRecord::new(prompt)).map_err(|e| e.to_string())?;
+        encoder
+            .add_itxt_chunk(PROMPTVAULT_CHUNK.to_string(), json)
+            .map_err(|e| e.to_string())?;
+    }
+
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(&pixels).map_err(|e| e.to_string())?;
+    writer.finish().map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+/// Formats a prompt the way Automatic1111 writes its `parameters` chunk:
+/// the prompt, an optional `Negative prompt:` line and one settings line.
+pub fn parameters_text(prompt: &Prompt, width: u32, height: u32) -> String {
+    let mut text = prompt.prompt_text.trim().to_string();
+    if !prompt.negative_prompt.trim().is_empty() {
+        text.push_str("\nNegative prompt: ");
+        text.push_str(prompt.negative_prompt.trim());
+    }
+
+    let mut settings: Vec<String> = Vec::new();
+    if let Some(steps) = prompt.steps {
+        settings.push(format!("Steps: {}", steps));
+    }
+    if let Some(sampler) = prompt.sampler.as_deref().filter(|s| !s.is_empty()) {
+        settings.push(format!("Sampler: {}", setting_value(sampler)));
+    }
+    if let Some(cfg_scale) = prompt.cfg_scale {
+        settings.push(format!("CFG scale: {}", cfg_scale));
+    }
+    if let Some(seed) = prompt.seed.as_deref().filter(|s| !s.is_empty()) {
+        settings.push(format!("Seed: {}", setting_value(seed)));
+    }
+    settings.push(format!("Size: {}x{}", width, height));
+    if let Some(hash) = prompt.model_hash.as_deref().filter(|h| !h.is_empty()) {
+        settings.push(format!("Model hash: {}", setting_value(hash)));
+    }
+    if !prompt.model.is_empty() {
+        settings.push(format!("Model: {}", setting_value(&prompt.model)));
+    }
+    text.push('\n');
+    text.push_str(&settings.join(", "));
+    text
+}
+
+/// A1111 quotes setting values that contain its separators.
+fn setting_value(value: &str) -> String {
+    if value.contains([',', ':', '"']) {
+        serde_json::to_string(value).unwrap_or_else(|_| value.to_string())
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::read_metadata;
+    use serde_json::json;
+
+    fn prompt(model: &str, sampler: &str) -> Prompt {
+        serde_json::from_value(json!({
+            "id": 1, "title": "Cat", "prompt_text": "a cat, golden hour\ndetailed",
+            "negative_prompt": "blurry", "model": model, "model_id": null,
+            "model_hash": "31e35c80fc", "image_path": null, "thumbnail_path": null,
+            "images": [], "dimensions": "4:3", "steps": 30, "sampler": sampler,
+            "cfg_scale": 6.5, "seed": "1234", "tags": [], "is_favorite": false, "rating": 0,
+            "has_workflow": false, "provenance": null, "collection_ids": [],
+            "created_at": "2024-03-01 12:00:00", "updated_at": "2024-03-01 12:00:00",
+            "deleted_at": null
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn quotes_values_with_separators() {
+        assert_eq!(setting_value("DPM++ 2M"), "DPM++ 2M");
+        assert_eq!(setting_value("a, b"), r#""a, b""#);
+        assert_eq!(setting_value(r#"say "hi": now"#), r#""say \"hi\": now""#);
+
+        let text = parameters_text(&prompt("SDXL, tuned", "Euler a"), 800, 600);
+        assert_eq!(
+            text,
+            "a cat, golden hour\ndetailed\nNegative prompt: blurry\n\
+             Steps: 30, Sampler: Euler a, CFG scale: 6.5, Seed: 1234, Size: 800x600, \
+             Model hash: 31e35c80fc, Model: \"SDXL, tuned\""
+        );
+    }
+
+    #[test]
+    fn exported_png_reads_back() {
+        let dir = std::env::temp_dir().join(format!("promptvault-export-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        image::RgbImage::new(4, 3).save(dir.join("cat.png")).unwrap();
+
+        let prompt = prompt("juggernaut: \"xl\", v9", "DPM++ 2M Karras");
+        let png = prompt_png(&dir, &prompt, "cat.png", Some("{}"), true);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let meta = read_metadata(&png.unwrap()).unwrap();
+        assert_eq!(meta.prompt_text.as_deref(), Some("a cat, golden hour\ndetailed"));
+        assert_eq!(meta.negative_prompt.as_deref(), Some("blurry"));
+        assert_eq!(meta.model.as_deref(), Some("juggernaut: \"xl\", v9"));
+        assert_eq!(meta.model_hash.as_deref(), Some("31e35c80fc"));
+        assert_eq!(meta.sampler.as_deref(), Some("DPM++ 2M Karras"));
+        assert_eq!((meta.steps, meta.cfg_scale), (Some(30), Some(6.5)));
+        assert_eq!(meta.seed.as_deref(), Some("1234"));
+        assert_eq!((meta.width, meta.height), (Some(4), Some(3)));
+    }
+}
@@ -0,0 +1,348 @@
+use crate::db::{Database, PromptImage};
+use crate::metadata::{self, ImageMetadata};
+use crate::similar;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use image::imageops::FilterType;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use uuid::Uuid;
+
+pub const NO_IMAGE_DATA: &str =
+    "No se recibieron datos de imagen. Vuelve a seleccionar el archivo e intenta de nuevo.";
+
+/// One image sent by the frontend. The bytes can arrive in any of the ways
+/// `create_prompt` has always accepted: raw bytes, a data URL or a path.
+#[derive(Debug, Default, Deserialize)]
+pub struct ImageUpload {
+    pub image_data: Option<Vec<u8>>,
+    pub filename: Option<String>,
+    pub image_path: Option<String>,
+    pub image_base64: Option<String>,
+    pub seed: Option<String>,
+}
+
+/// An image copied into the vault, with paths relative to the data dir.
+#[derive(Debug)]
+pub struct StoredImage {
+    pub image_path: String,
+    pub thumbnail_path: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// The seed given with the upload, or else the one in the metadata.
+    pub seed: Option<String>,
+    pub metadata: Option<ImageMetadata>,
+    pub content_hash: String,
+    /// `None` when the image can't be decoded.
+    pub perceptual_hash: Option<i64>,
+    /// The files belong to an identical image already in the vault and must
+    /// not be deleted with this one.
+    pub reused: bool,
+}
+
+impl ImageUpload {
+    /// Whether none of the ways to send an image was used.
+    pub fn is_empty(&self) -> bool {
+        self.image_data.is_none()
+            && self.image_path.as_deref().map_or(true, str::is_empty)
+            && self.image_base64.as_deref().map_or(true, str::is_empty)
+    }
+}
+
+/// Reads an upload and stores it under `images/YYYY-MM/` with a thumbnail,
+/// or shares the files of an identical image already in the vault.
+/// Returns `None` when the upload carries no image at all.
+pub fn store_upload(db: &Database, upload: ImageUpload) -> Result<Option<StoredImage>, String> {
+    let seed = upload.seed.clone();
+    let Some((data, ext)) = read_upload(upload)? else {
+        return Ok(None);
+    };
+    let existing = db
+        .find_image_by_hash(&content_hash(&data))
+        .map_err(|e| e.to_string())?;
+    let mut stored = match existing {
+        Some(existing) if db.get_data_dir().join(&existing.image_path).is_file() => {
+            reuse_image(&existing, &data)
+        }
+        _ => store_image(db.get_data_dir(), &data, &ext)?,
+    };
+    if seed.is_some() {
+        stored.seed = seed;
+    }
+    Ok(Some(stored))
+}
+
+/// The bytes and file extension of an upload, without storing anything.
+pub fn read_upload(upload: ImageUpload) -> Result<Option<(Vec<u8>, String)>, String> {
+    if upload.is_empty() {
+        return Ok(None);
+    }
+    let ext = resolve_image_extension(
+        upload.filename.as_deref(),
+        upload.image_path.as_deref(),
+        upload.image_base64.as_deref(),
+    );
+    match resolve_image_data(
+        upload.image_data,
+        upload.image_path.as_deref(),
+        upload.image_base64.as_deref(),
+    )? {
+        Some(data) if !data.is_empty() => Ok(Some((data, ext))),
+        _ => Err(NO_IMAGE_DATA.to_string()),
+    }
+}
+
+/// SHA-256 of an image file, hex encoded. Identical files share it.
+pub fn content_hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Points a new image at the files of `existing`, which holds the same bytes
+/// as `data`.
+pub fn reuse_image(existing: &PromptImage, data: &[u8]) -> StoredImage {
+    let metadata = metadata::read_metadata(data);
+    StoredImage {
+        image_path: existing.image_path.clone(),
+        thumbnail_path: existing
+            .thumbnail_path
+            .clone()
+            .unwrap_or_else(|| existing.image_path.clone()),
+        width: existing.width.map(|w| w as u32),
+        height: existing.height.map(|h| h as u32),
+        seed: metadata.as_ref().and_then(|m| m.seed.clone()),
+        metadata,
+        content_hash: existing
+            .content_hash
+            .clone()
+            .unwrap_or_else(|| content_hash(data)),
+        perceptual_hash: existing.perceptual_hash,
+        reused: true,
+    }
+}
+
+pub fn store_image(data_dir: &Path, data: &[u8], ext: &str) -> Result<StoredImage, String> {
+    let metadata = metadata::read_metadata(data);
+    let seed = metadata.as_ref().and_then(|m| m.seed.clone());
+    let content_hash = content_hash(data);
+    let uuid = Uuid::new_v4().to_string();
+    let now = chrono::Local::now();
+    let month_dir = now.format("%Y-%m").to_string();
+
+    // Create directories
+    let images_dir = data_dir.join("images").join(&month_dir);
+    let thumbnails_dir = data_dir.join("thumbnails");
+    fs::create_dir_all(&images_dir).map_err(|e| e.to_string())?;
+    fs::create_dir_all(&thumbnails_dir).map_err(|e| e.to_string())?;
+
+    // Save original image
+    let image_filename = format!("{}.{}", uuid, ext);
+    let image_path_full = images_dir.join(&image_filename);
+    fs::write(&image_path_full, data).map_err(|e| e.to_string())?;
+
+    // Create thumbnail
+    let image_rel_path = format!("images/{}/{}", month_dir, image_filename);
+
+    if ext.eq_ignore_ascii_case("svg") {
+        // The `image` crate doesn't decode SVG. Reuse the original for preview.
+        return Ok(StoredImage {
+            thumbnail_path: image_rel_path.clone(),
+            image_path: image_rel_path,
+            width: None,
+            height: None,
+            seed,
+            metadata,
+            content_hash,
+            perceptual_hash: None,
+            reused: false,
+        });
+    }
+
+    let thumbnail_filename = format!("{}_thumb.{}", uuid, ext);
+    let thumbnail_path_full = thumbnails_dir.join(&thumbnail_filename);
+    match image::load_from_memory(data) {
+        Ok(img) => {
+            let thumbnail = img.resize(300, 300, FilterType::Lanczos3);
+            thumbnail
+                .save(&thumbnail_path_full)
+                .map_err(|e| e.to_string())?;
+            Ok(StoredImage {
+                image_path: image_rel_path,
+                thumbnail_path: format!("thumbnails/{}", thumbnail_filename),
+                width: Some(img.width()),
+                height: Some(img.height()),
+                seed,
+                metadata,
+                content_hash,
+                perceptual_hash: Some(similar::dhash(&img)),
+                reused: false,
+            })
+        }
+        Err(_) => {
+            // Fallback: if we can't decode the image for thumbnail generation,
+            // still show something in the UI.
+            Ok(StoredImage {
+                thumbnail_path: image_rel_path.clone(),
+                image_path: image_rel_path,
+                width: None,
+                height: None,
+                seed,
+                metadata,
+                content_hash,
+                perceptual_hash: None,
+                reused: false,
+            })
+        }
+    }
+}
+
+/// Deletes the files of an image. The thumbnail may be the original itself.
+pub fn remove_image_files(data_dir: &Path, image_path: &str, thumbnail_path: Option<&str>) {
+    let _ = fs::remove_file(data_dir.join(image_path));
+    if let Some(thumbnail_path) = thumbnail_path.filter(|t| *t != image_path) {
+        let _ = fs::remove_file(data_dir.join(thumbnail_path));
+    }
+}
+
+fn resolve_image_data(
+    image_data: Option<Vec<u8>>,
+    image_path: Option<&str>,
+    image_base64: Option<&str>,
+) -> Result<Option<Vec<u8>>, String> {
+    // Prefer bytes sent over IPC (most reliable) over trying to read a client-provided path.
+    if image_data.is_some() {
+        return Ok(image_data);
+    }
+    if let Some(data_url) = image_base64 {
+        // Supports both:
+        // - data:<mime>;base64,<...>
+        // - data:<mime>,<percent-encoded bytes> (common for SVG)
+        if let Some((meta, data)) = data_url.split_once(',') {
+            if meta.contains(";base64") {
+                match BASE64.decode(data) {
+                    Ok(bytes) => return Ok(Some(bytes)),
+                    Err(_) => {
+                        if image_data.is_some() {
+                            return Ok(image_data);
+                        }
+                    }
+                }
+            } else {
+                let decoded = percent_decode(data.as_bytes());
+                if !decoded.is_empty() {
+                    return Ok(Some(decoded));
+                }
+            }
+        } else {
+            // If the string is raw base64 without a data URL prefix.
+            if let Ok(bytes) = BASE64.decode(data_url) {
+                return Ok(Some(bytes));
+            }
+        }
+    }
+    if let Some(path) = image_path {
+        let normalized = normalize_fs_path(path);
+        if let Ok(bytes) = fs::read(path) {
+            return Ok(Some(bytes));
+        }
+        if let Some(p) = normalized.as_deref() {
+            if p != path {
+                if let Ok(bytes) = fs::read(p) {
+                    return Ok(Some(bytes));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn normalize_fs_path(path: &str) -> Option<String> {
+    let p = path.trim();
+    if let Some(rest) = p.strip_prefix("file://") {
+        // Common when a frontend passes a file URL instead of a plain path.
+        // file:///C:/... or file://C:/...
+        let rest = rest.strip_prefix('/').unwrap_or(rest);
+        return Some(rest.replace('/', "\\"));
+    }
+    None
+}
+
+fn resolve_image_extension(
+    filename: Option<&str>,
+    image_path: Option<&str>,
+    image_base64: Option<&str>,
+) -> String {
+    if let Some(name) = filename {
+        if let Some(ext) = name.split('.').last() {
+            if !ext.is_empty() {
+                return ext.to_string();
+            }
+        }
+    }
+    if let Some(path) = image_path {
+        if let Some(ext) = Path::new(path).extension().and_then(|e| e.to_str()) {
+            if !ext.is_empty() {
+                return ext.to_string();
+            }
+        }
+    }
+    if let Some(data_url) = image_base64 {
+        if let Some(mime) = data_url.strip_prefix("data:").and_then(|v| v.split(';').next()) {
+            return match mime {
+                "image/jpeg" => "jpg",
+                "image/jpg" => "jpg",
+                "image/png" => "png",
+                "image/webp" => "webp",
+                "image/gif" => "gif",
+                "image/bmp" => "bmp",
+                "image/tiff" => "tiff",
+                "image/svg+xml" => "svg",
+                _ => "png",
+            }
+            .to_string();
+        }
+    }
+    "png".to_string()
+}
+
+fn percent_decode(input: &[u8]) -> Vec<u8> {
+    // Minimal percent-decoder for data URLs (ASCII/UTF-8). Also converts '+' to space.
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        match input[i] {
+            b'%' if i + 2 < input.len() => {
+                let h1 = from_hex(input[i + 1]);
+                let h2 = from_hex(input[i + 2]);
+                if let (Some(h1), Some(h2)) = (h1, h2) {
+                    out.push((h1 << 4) | h2);
+                    i += 3;
+                    continue;
+                }
+                out.push(input[i]);
+                i += 1;
+            }
+            b'+' => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+fn from_hex(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
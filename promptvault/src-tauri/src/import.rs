@@ -0,0 +1,303 @@
+use crate::db::{Database, NewPrompt, NewPromptImage};
+use crate::image_store::{self, StoredImage};
+use crate::metadata;
+use crate::AppState;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::{Emitter, Manager};
+
+pub const IMPORT_PROGRESS_EVENT: &str = "import-progress";
+pub const IMPORT_FINISHED_EVENT: &str = "import-finished";
+
+/// File types picked up from import folders.
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "webp", "gif", "bmp"];
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct ImportOptions {
+    /// Descend into subfolders; on by default.
+    pub recursive: Option<bool>,
+    /// Tags added to every imported prompt.
+    pub tags: Option<Vec<String>>,
+    pub collection_id: Option<i32>,
+    /// Model for images whose metadata doesn't name one.
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportStatus {
+    Imported,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportFileResult {
+    pub path: String,
+    pub status: ImportStatus,
+    pub prompt_id: Option<i32>,
+    /// Why a file was skipped or failed.
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportProgress {
+    pub job_id: String,
+    pub total: usize,
+    pub processed: usize,
+    pub current: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportReport {
+    pub job_id: String,
+    pub cancelled: bool,
+    pub imported: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub files: Vec<ImportFileResult>,
+}
+
+/// Starts importing `root` on a background thread and returns the job id.
+/// Progress is reported through `import-progress` events and the final
+/// report through `import-finished`.
+pub fn spawn(app_handle: tauri::AppHandle, root: PathBuf, options: ImportOptions) -> String {
+    let (job_id, cancelled) = app_handle.state::<AppState>().jobs.start();
+    let id = job_id.clone();
+    std::thread::spawn(move || {
+        let state = app_handle.state::<AppState>();
+        let report = import_folder(&state, &id, &root, &options, &cancelled, |progress| {
+            let _ = app_handle.emit(IMPORT_PROGRESS_EVENT, progress);
+        });
+        state.jobs.finish(&id);
+        let _ = app_handle.emit(IMPORT_FINISHED_EVENT, report);
+    });
+    job_id
+}
+
+/// Imports every image below `root`, one prompt per image. Stops between
+/// files once `cancelled` is set.
+pub fn import_folder(
+    state: &AppState,
+    job_id: &str,
+    root: &Path,
+    options: &ImportOptions,
+    cancelled: &AtomicBool,
+    mut on_progress: impl FnMut(ImportProgress),
+) -> ImportReport {
+    let paths = collect_images(root, options.recursive.unwrap_or(true));
+    let mut files = Vec::with_capacity(paths.len());
+
+    for (i, path) in paths.iter().enumerate() {
+        if cancelled.load(Ordering::Relaxed) {
+            break;
+        }
+        on_progress(ImportProgress {
+            job_id: job_id.to_string(),
+            total: paths.len(),
+            processed: i,
+            current: path.to_string_lossy().to_string(),
+        });
+        files.push(import_file(state, path, options));
+    }
+
+    let count = |status: ImportStatus| files.iter().filter(|f| f.status == status).count();
+    ImportReport {
+        job_id: job_id.to_string(),
+        cancelled: cancelled.load(Ordering::Relaxed),
+        imported: count(ImportStatus::Imported),
+        skipped: count(ImportStatus::Skipped),
+        failed: count(ImportStatus::Failed),
+        files,
+    }
+}
+
+/// Image files below `root`, sorted so imports run in a stable order.
+/// Hidden files and folders are left out.
+pub fn collect_images(root: &Path, recursive: bool) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            match entry.file_type() {
+                Ok(kind) if kind.is_dir() && recursive => dirs.push(path),
+                Ok(kind) if kind.is_file() && is_image(&path) => paths.push(path),
+                _ => {}
+            }
+        }
+    }
+    paths.sort();
+    paths
+}
+
+pub fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+/// Imports one file as a new prompt. Files imported before (by path) and
+/// images already in the vault (by content) are skipped.
+pub fn import_file(state: &AppState, path: &Path, options: &ImportOptions) -> ImportFileResult {
+    let source = path.to_string_lossy().to_string();
+    let result = |status, prompt_id, message: Option<String>| ImportFileResult {
+        path: source.clone(),
+        status,
+        prompt_id,
+        message,
+    };
+    match try_import_file(state, path, &source, options) {
+        Ok(Outcome::Imported(prompt_id)) => result(ImportStatus::Imported, Some(prompt_id), None),
+        Ok(Outcome::AlreadyImported) => {
+            result(ImportStatus::Skipped, None, Some("Already imported".to_string()))
+        }
+        // The prompt id links to the prompt that already has the image.
+        Ok(Outcome::Duplicate { prompt_id, title }) => result(
+            ImportStatus::Skipped,
+            Some(prompt_id),
+            Some(format!("Same image as \"{}\"", title)),
+        ),
+        Err(e) => result(ImportStatus::Failed, None, Some(e)),
+    }
+}
+
+enum Outcome {
+    Imported(i32),
+    AlreadyImported,
+    Duplicate { prompt_id: i32, title: String },
+}
+
+fn try_import_file(
+    state: &AppState,
+    path: &Path,
+    source: &str,
+    options: &ImportOptions,
+) -> Result<Outcome, String> {
+    let data_dir = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        if db.is_source_imported(source).map_err(|e| e.to_string())? {
+            return Ok(Outcome::AlreadyImported);
+        }
+        db.get_data_dir().clone()
+    };
+
+    // Reading and thumbnailing happen without holding the database lock.
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    if image::guess_format(&data).is_err() {
+        return Err("Not a readable image".to_string());
+    }
+
+    // An identical image in a live prompt is reported instead of imported
+    // again; one only left in the trash gets its file shared.
+    let existing = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        let existing = db
+            .find_image_by_hash(&image_store::content_hash(&data))
+            .map_err(|e| e.to_string())?;
+        if let Some(image) = &existing {
+            let prompt = db.get_prompt_by_id(image.prompt_id).map_err(|e| e.to_string())?;
+            if prompt.deleted_at.is_none() {
+                return Ok(Outcome::Duplicate {
+                    prompt_id: prompt.id,
+                    title: prompt.title,
+                });
+            }
+        }
+        existing.filter(|image| data_dir.join(&image.image_path).is_file())
+    };
+
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("png")
+        .to_lowercase();
+    let mut stored = match &existing {
+        Some(existing) => image_store::reuse_image(existing, &data),
+        None => image_store::store_image(&data_dir, &data, &ext)?,
+    };
+    let meta = stored
+        .metadata
+        .clone()
+        .or_else(|| metadata::read_sidecar(path));
+
+    let mut prompt = NewPrompt {
+        tags: options.tags.clone(),
+        collection_id: options.collection_id,
+        ..Default::default()
+    };
+    if let Some(meta) = &meta {
+        meta.fill_prompt(&mut prompt);
+    }
+    if prompt.prompt_text.trim().is_empty() {
+        if let Some(stem) = path.file_stem() {
+            prompt.title = stem.to_string_lossy().to_string();
+        }
+    }
+    if prompt.model.trim().is_empty() {
+        prompt.model = options.model.clone().unwrap_or_default();
+    }
+    if prompt.created_at.is_none() {
+        prompt.created_at = file_time(path);
+    }
+
+    let seed = stored.seed.clone().or_else(|| meta.and_then(|m| m.seed));
+    let insert = |db: &Database, stored: &StoredImage| {
+        let image = NewPromptImage {
+            image_path: stored.image_path.clone(),
+            thumbnail_path: Some(stored.thumbnail_path.clone()),
+            seed: seed.clone(),
+            width: stored.width,
+            height: stored.height,
+            source_path: Some(source.to_string()),
+            content_hash: Some(stored.content_hash.clone()),
+            perceptual_hash: stored.perceptual_hash,
+        };
+        db.create_prompt(&prompt, &[image]).map_err(|e| e.to_string())
+    };
+    let attempt = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        // The trashed prompt whose files are shared may have been purged
+        // since, taking them along. Checked under the lock the insert holds.
+        let shared_gone = stored.reused
+            && !(db.is_image_path_used(&stored.image_path).map_err(|e| e.to_string())?
+                && data_dir.join(&stored.image_path).is_file());
+        (!shared_gone).then(|| insert(&db, &stored))
+    };
+    let created = match attempt {
+        Some(created) => created,
+        None => {
+            stored = image_store::store_image(&data_dir, &data, &ext)?;
+            state
+                .db
+                .lock()
+                .map_err(|e| e.to_string())
+                .and_then(|db| insert(&db, &stored))
+        }
+    };
+    match created {
+        Ok(prompt) => Ok(Outcome::Imported(prompt.id)),
+        Err(e) => {
+            if !stored.reused {
+                image_store::remove_image_files(&data_dir, &stored.image_path, Some(&stored.thumbnail_path));
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Modification time of a file in the format of `created_at`, so imported
+/// images without a date in their metadata keep their place in time.
+fn file_time(path: &Path) -> Option<String> {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    Some(DateTime::<Utc>::from(modified).format("%Y-%m-%d %H:%M:%S").to_string())
+}
@@ -0,0 +1,39 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Background jobs that are still running, with the flag that cancels each.
+#[derive(Default)]
+pub struct Jobs {
+    running: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
+
+impl Jobs {
+    /// Registers a new job and returns its id and cancellation flag.
+    pub fn start(&self) -> (String, Arc<AtomicBool>) {
+        let id = Uuid::new_v4().to_string();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        if let Ok(mut running) = self.running.lock() {
+            running.insert(id.clone(), cancelled.clone());
+        }
+        (id, cancelled)
+    }
+
+    /// Asks a job to stop. Returns `false` if no such job is running.
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.running.lock().ok().and_then(|running| running.get(id).cloned()) {
+            Some(cancelled) => {
+                cancelled.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn finish(&self, id: &str) {
+        if let Ok(mut running) = self.running.lock() {
+            running.remove(id);
+        }
+    }
+}
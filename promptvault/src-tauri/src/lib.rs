@@ -1,12 +1,28 @@
+pub mod backup;
 pub mod commands;
 pub mod db;
+pub mod dedupe;
+pub mod diff;
+pub mod export;
+pub mod image_store;
+pub mod import;
+pub mod jobs;
+pub mod metadata;
+pub mod similar;
+pub mod watcher;
 
 use db::Database;
+use jobs::Jobs;
+use watcher::FolderWatcher;
 use std::sync::Mutex;
 use tauri::Manager;
 
 pub struct AppState {
     pub db: Mutex<Database>,
+    pub jobs: Jobs,
+    /// The error when the platform watcher could not be started; watched
+    /// folder commands report it.
+    pub watcher: Mutex<Result<FolderWatcher, String>>,
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -19,22 +35,85 @@ pub fn run() {
         .setup(|app| {
             let app_handle = app.handle();
             let db = Database::new(&app_handle)?;
+            let folder_watcher =
+                FolderWatcher::start(app_handle.clone()).map_err(|e| e.to_string());
             app.manage(AppState {
                 db: Mutex::new(db),
+                jobs: Jobs::default(),
+                watcher: Mutex::new(folder_watcher),
             });
+            watcher::sync_watched_folders(&app.state::<AppState>())?;
+            backup::spawn_scheduler(app_handle.clone());
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             commands::get_all_prompts,
+            commands::list_prompts,
+            commands::export_prompts,
             commands::get_prompt_by_id,
             commands::create_prompt,
+            commands::extract_image_metadata,
+            commands::find_duplicate_prompts,
+            commands::dedupe_images,
+            commands::find_similar_prompts,
+            commands::backfill_perceptual_hashes,
+            commands::get_prompt_workflow,
+            commands::export_prompt_png,
+            commands::import_folder,
+            commands::cancel_job,
+            commands::get_watched_folders,
+            commands::add_watched_folder,
+            commands::update_watched_folder,
+            commands::remove_watched_folder,
             commands::update_prompt,
             commands::delete_prompt,
+            commands::add_prompt_images,
+            commands::attach_prompt_image,
+            commands::replace_prompt_image,
+            commands::detach_prompt_image,
+            commands::remove_prompt_image,
+            commands::set_prompt_cover_image,
+            commands::reorder_prompt_images,
+            commands::get_trash,
+            commands::restore_prompt,
+            commands::purge_prompt,
+            commands::empty_trash,
+            commands::get_trash_retention_days,
+            commands::set_trash_retention_days,
+            commands::create_backup,
+            commands::get_backups,
+            commands::restore_backup,
+            commands::get_backup_schedule,
+            commands::set_backup_schedule,
+            commands::get_prompt_revisions,
+            commands::diff_prompt_revisions,
+            commands::restore_prompt_revision,
             commands::toggle_favorite,
             commands::search_prompts,
+            commands::check_search_query,
             commands::get_collections,
             commands::create_collection,
+            commands::rename_collection,
+            commands::move_collection,
+            commands::set_collection_query,
+            commands::convert_to_static_collection,
+            commands::set_collection_color,
+            commands::set_collection_icon,
+            commands::reorder_collections,
+            commands::delete_collection,
+            commands::add_prompts_to_collection,
+            commands::remove_prompts_from_collection,
+            commands::move_prompts_to_collection,
+            commands::get_tags,
+            commands::rename_tag,
+            commands::merge_tags,
+            commands::delete_tag,
             commands::get_models,
+            commands::create_model,
+            commands::rename_model,
+            commands::set_model_short_name,
+            commands::set_model_active,
+            commands::delete_model,
             commands::copy_to_clipboard,
             commands::open_image_external,
             commands::get_image_base64,
@@ -0,0 +1,222 @@
+use crate::db::NewPrompt;
+use serde::Serialize;
+use std::io::Cursor;
+use std::path::Path;
+
+mod a1111;
+mod c2pa;
+mod comfyui;
+mod exif;
+mod invokeai;
+mod novelai;
+mod sidecar;
+
+/// Generation parameters found inside an image file.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ImageMetadata {
+    /// Tool that wrote the metadata, e.g. "Automatic1111".
+    pub generator: String,
+    pub prompt_text: Option<String>,
+    pub negative_prompt: Option<String>,
+    pub model: Option<String>,
+    pub model_hash: Option<String>,
+    pub steps: Option<i32>,
+    pub sampler: Option<String>,
+    pub cfg_scale: Option<f64>,
+    pub seed: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Raw ComfyUI workflow JSON.
+    pub workflow: Option<String>,
+    /// When the image was made, as `YYYY-MM-DD HH:MM:SS` UTC.
+    pub created_at: Option<String>,
+    /// Content credentials (C2PA claim and actions) embedded in the image.
+    pub provenance: Option<serde_json::Value>,
+}
+
+/// Reads whatever generation metadata the image carries. Returns `None` for
+/// images without any, or in a format we don't understand.
+pub fn read_metadata(data: &[u8]) -> Option<ImageMetadata> {
+    if !data.starts_with(b"\x89PNG") {
+        return exif::read(data);
+    }
+    from_text_chunks(&png_text_chunks(data)).or_else(|| {
+        // Only decode the pixels when the text chunks gave nothing.
+        let hidden = novelai::read_stealth(data)?;
+        match serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&hidden) {
+            // NovelAI hides its text chunks as a JSON object.
+            Ok(object) => {
+                let chunks: Vec<(String, String)> = object
+                    .into_iter()
+                    .map(|(key, value)| match value {
+                        serde_json::Value::String(text) => (key, text),
+                        other => (key, other.to_string()),
+                    })
+                    .collect();
+                from_text_chunks(&chunks)
+            }
+            // The webui extension hides the plain `parameters` text.
+            Err(_) => a1111::parse_parameters(&hidden),
+        }
+    })
+}
+
+/// Reads the `.json` or `.txt` file some tools save next to an image on
+/// disk, for images that carry no metadata themselves.
+pub fn read_sidecar(image_path: &Path) -> Option<ImageMetadata> {
+    sidecar::read(image_path)
+}
+
+fn from_text_chunks(chunks: &[(String, String)]) -> Option<ImageMetadata> {
+    let text = |keyword: &str| {
+        chunks
+            .iter()
+            .find(|(k, _)| k == keyword)
+            .map(|(_, v)| v.as_str())
+    };
+
+    if let Some(parameters) = text("parameters") {
+        return a1111::parse_parameters(parameters);
+    }
+    if let Some(json) = text("invokeai_metadata") {
+        return invokeai::parse(json);
+    }
+    if let Some(meta) = text("Comment")
+        .and_then(|comment| novelai::parse_comment(comment, text("Description"), text("Source")))
+    {
+        return Some(meta);
+    }
+    if text("prompt").is_some() || text("workflow").is_some() {
+        return comfyui::parse(text("prompt"), text("workflow"));
+    }
+    None
+}
+
+/// Keyword/text pairs of every tEXt, zTXt and iTXt chunk of a PNG, in file
+/// order. Anything that isn't a readable PNG gives an empty list.
+pub fn png_text_chunks(data: &[u8]) -> Vec<(String, String)> {
+    let Ok(mut reader) = png::Decoder::new(Cursor::new(data)).read_info() else {
+        return Vec::new();
+    };
+
+    // Text chunks may also come after the image data, so the frame has to
+    // be read before `info()` is complete.
+    if let Some(size) = reader.output_buffer_size() {
+        let mut buf = vec![0; size];
+        if reader.next_frame(&mut buf).is_ok() {
+            let _ = reader.finish();
+        }
+    }
+
+    let info = reader.info();
+    let mut chunks = Vec::new();
+    for chunk in &info.uncompressed_latin1_text {
+        chunks.push((chunk.keyword.clone(), chunk.text.clone()));
+    }
+    for chunk in &info.compressed_latin1_text {
+        if let Ok(text) = chunk.get_text() {
+            chunks.push((chunk.keyword.clone(), text));
+        }
+    }
+    for chunk in &info.utf8_text {
+        if let Ok(text) = chunk.get_text() {
+            chunks.push((chunk.keyword.clone(), text));
+        }
+    }
+    chunks
+}
+
+impl ImageMetadata {
+    /// Fills the fields of `prompt` that are still empty. Fields the user
+    /// already typed are never overwritten.
+    pub fn fill_prompt(&self, prompt: &mut NewPrompt) {
+        fn fill<T: Clone>(field: &mut Option<T>, value: &Option<T>) {
+            if field.is_none() {
+                field.clone_from(value);
+            }
+        }
+
+        if prompt.prompt_text.trim().is_empty() {
+            if let Some(text) = &self.prompt_text {
+                prompt.prompt_text = text.clone();
+            }
+        }
+        if prompt.negative_prompt.as_deref().map_or(true, |n| n.trim().is_empty())
+            && self.negative_prompt.is_some()
+        {
+            prompt.negative_prompt.clone_from(&self.negative_prompt);
+        }
+        if prompt.model.trim().is_empty() {
+            if let Some(model) = &self.model {
+                prompt.model = model.clone();
+                prompt.model_from_metadata = true;
+            }
+        }
+        // A bare hash is no model name; it's kept apart so the model can be
+        // identified later.
+        fill(&mut prompt.model_hash, &self.model_hash);
+        if prompt.dimensions.is_none() {
+            if let (Some(width), Some(height)) = (self.width, self.height) {
+                prompt.dimensions = Some(aspect_ratio(width, height));
+            }
+        }
+        fill(&mut prompt.steps, &self.steps);
+        fill(&mut prompt.sampler, &self.sampler);
+        fill(&mut prompt.cfg_scale, &self.cfg_scale);
+        fill(&mut prompt.seed, &self.seed);
+        fill(&mut prompt.workflow_json, &self.workflow);
+        fill(&mut prompt.created_at, &self.created_at);
+        fill(&mut prompt.provenance, &self.provenance);
+        if prompt.title.trim().is_empty() {
+            prompt.title = title_from_prompt(&prompt.prompt_text);
+        }
+    }
+}
+
+/// Aspect ratios offered by the prompt form.
+const ASPECT_RATIOS: [(u32, u32); 7] = [(1, 1), (16, 9), (9, 16), (4, 3), (3, 4), (2, 3), (3, 2)];
+
+/// Turns a pixel size into the `dimensions` label used by prompts. Sizes
+/// within 5% of a common ratio (e.g. 832x1216 for 2:3) snap to it.
+pub fn aspect_ratio(width: u32, height: u32) -> String {
+    if width == 0 || height == 0 {
+        return format!("{}x{}", width, height);
+    }
+    let ratio = width as f64 / height as f64;
+    let closest = ASPECT_RATIOS.iter().min_by(|a, b| {
+        let da = (a.0 as f64 / a.1 as f64 - ratio).abs();
+        let db = (b.0 as f64 / b.1 as f64 - ratio).abs();
+        da.total_cmp(&db)
+    });
+    match closest {
+        Some((w, h)) if ((*w as f64 / *h as f64) / ratio - 1.0).abs() <= 0.05 => {
+            format!("{}:{}", w, h)
+        }
+        _ => {
+            let divisor = gcd(width, height);
+            format!("{}:{}", width / divisor, height / divisor)
+        }
+    }
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// First few words of a prompt, for imports that come without a title.
+pub fn title_from_prompt(prompt_text: &str) -> String {
+    let words: Vec<&str> = prompt_text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|w| !w.is_empty())
+        .take(6)
+        .collect();
+    if words.is_empty() {
+        "Untitled".to_string()
+    } else {
+        words.join(" ")
+    }
+}
@@ -0,0 +1,165 @@
+use super::ImageMetadata;
+
+/// Parses the `parameters` text written by Automatic1111 and Forge:
+///
+/// ```text
+/// a cat in golden hour, detailed
+/// Negative prompt: blurry, lowres
+/// Steps: 30, Sampler: DPM++ 2M, CFG scale: 7, Seed: 1234, Size: 832x1216, Model hash: 31e35c80fc, Model: sd_xl_base_1.0
+/// ```
+///
+/// The prompt and negative prompt may span several lines; the settings are
+/// on the last line that starts with `Steps:`.
+pub fn parse_parameters(text: &str) -> Option<ImageMetadata> {
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+
+    let lines: Vec<&str> = text.lines().collect();
+    let settings_at = lines
+        .iter()
+        .rposition(|line| line.trim_start().starts_with("Steps:"));
+    let (body, settings) = match settings_at {
+        Some(i) => (&lines[..i], Some(lines[i..].join(", "))),
+        None => (&lines[..], None),
+    };
+
+    let mut prompt_lines = Vec::new();
+    let mut negative_lines: Option<Vec<&str>> = None;
+    for line in body {
+        if let Some(rest) = line.strip_prefix("Negative prompt:") {
+            negative_lines = Some(vec![rest.trim_start()]);
+        } else if let Some(negative) = negative_lines.as_mut() {
+            negative.push(line);
+        } else {
+            prompt_lines.push(*line);
+        }
+    }
+
+    let mut meta = ImageMetadata {
+        generator: "Automatic1111".to_string(),
+        prompt_text: non_empty(prompt_lines.join("\n")),
+        negative_prompt: negative_lines.and_then(|lines| non_empty(lines.join("\n"))),
+        ..Default::default()
+    };
+
+    for (key, value) in settings.as_deref().map(parse_settings).unwrap_or_default() {
+        match key.as_str() {
+            "Steps" => meta.steps = value.parse().ok(),
+            "Sampler" => meta.sampler = non_empty(value),
+            "CFG scale" => meta.cfg_scale = value.parse().ok(),
+            "Seed" => meta.seed = non_empty(value),
+            "Size" => {
+                if let Some((w, h)) = value.split_once('x') {
+                    meta.width = w.trim().parse().ok();
+                    meta.height = h.trim().parse().ok();
+                }
+            }
+            "Model hash" => meta.model_hash = non_empty(value),
+            "Model" => meta.model = non_empty(value),
+            "Version" if value.starts_with('f') => meta.generator = "Forge".to_string(),
+            _ => {}
+        }
+    }
+
+    Some(meta)
+}
+
+/// Splits `Key: value, Key: "quoted, value", ...` into pairs. Quoted values
+/// may contain commas and `\"` escapes.
+fn parse_settings(line: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    loop {
+        while chars.peek().is_some_and(|c| *c == ',' || c.is_whitespace()) {
+            chars.next();
+        }
+        let key: String = chars.by_ref().take_while(|c| *c != ':').collect();
+        if key.trim().is_empty() {
+            break;
+        }
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    '"' => break,
+                    _ => value.push(c),
+                }
+            }
+        } else {
+            value = chars.by_ref().take_while(|c| *c != ',').collect();
+        }
+        pairs.push((key.trim().to_string(), value.trim().to_string()));
+    }
+
+    pairs
+}
+
+fn non_empty(value: impl Into<String>) -> Option<String> {
+    let value = value.into();
+    if value.trim().is_empty() {
+        None
+    } else {
+        Some(value.trim().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_prompt_negative_and_settings() {
+        let meta = parse_parameters(
+            "a cat in golden hour,\ndetailed\nNegative prompt: blurry,\nlowres\n\
+             Steps: 30, Sampler: DPM++ 2M, CFG scale: 7.5, Seed: 1234, Size: 832x1216, \
+             Model hash: 31e35c80fc, Model: sd_xl_base_1.0",
+        )
+        .unwrap();
+        assert_eq!(meta.generator, "Automatic1111");
+        assert_eq!(meta.prompt_text.as_deref(), Some("a cat in golden hour,\ndetailed"));
+        assert_eq!(meta.negative_prompt.as_deref(), Some("blurry,\nlowres"));
+        assert_eq!(meta.steps, Some(30));
+        assert_eq!(meta.sampler.as_deref(), Some("DPM++ 2M"));
+        assert_eq!(meta.cfg_scale, Some(7.5));
+        assert_eq!(meta.seed.as_deref(), Some("1234"));
+        assert_eq!((meta.width, meta.height), (Some(832), Some(1216)));
+        assert_eq!(meta.model.as_deref(), Some("sd_xl_base_1.0"));
+        assert_eq!(meta.model_hash.as_deref(), Some("31e35c80fc"));
+    }
+
+    #[test]
+    fn hash_alone_is_not_a_model_name() {
+        let meta = parse_parameters("a cat\nSteps: 20, Model hash: 31e35c80fc").unwrap();
+        assert_eq!(meta.model, None);
+        assert_eq!(meta.model_hash.as_deref(), Some("31e35c80fc"));
+    }
+
+    #[test]
+    fn quoted_settings_keep_commas_and_escapes() {
+        let meta = parse_parameters(
+            r#"a cat
+Steps: 20, Lora hashes: "cat: 1a2b, hat: 3c4d", Model: "my \"best\", model", Version: f2.0.1"#,
+        )
+        .unwrap();
+        assert_eq!(meta.model.as_deref(), Some(r#"my "best", model"#));
+        assert_eq!(meta.steps, Some(20));
+        assert_eq!(meta.generator, "Forge");
+    }
+
+    #[test]
+    fn text_without_settings_is_all_prompt() {
+        let meta = parse_parameters("just a prompt\nover two lines").unwrap();
+        assert_eq!(meta.prompt_text.as_deref(), Some("just a prompt\nover two lines"));
+        assert_eq!(meta.steps, None);
+        assert!(parse_parameters("  \n ").is_none());
+    }
+}
@@ -0,0 +1,279 @@
+use serde_json::{json, Map, Value};
+
+/// Nested boxes are followed at most this deep.
+const MAX_DEPTH: usize = 8;
+
+/// A JUMBF superbox: its label and the boxes it contains.
+struct Superbox<'a> {
+    label: String,
+    children: Vec<Content<'a>>,
+}
+
+enum Content<'a> {
+    Superbox(Superbox<'a>),
+    Data { kind: [u8; 4], data: &'a [u8] },
+}
+
+/// Reads the active manifest of a C2PA manifest store (a JUMBF `jumb` box
+/// labelled `c2pa`). Returns its claim and the actions it records as JSON,
+/// with byte strings (hashes, signatures) left out, plus the readable name
+/// of the `generator`.
+pub fn read_manifest_store(jumbf: &[u8]) -> Option<Value> {
+    let store = parse_boxes(jumbf, 0)
+        .into_iter()
+        .find_map(|content| match content {
+            Content::Superbox(superbox) if superbox.label == "c2pa" => Some(superbox),
+            _ => None,
+        })?;
+
+    // The active manifest is the last one in the store.
+    let manifest = store.children.iter().rev().find_map(|content| match content {
+        Content::Superbox(superbox) => Some(superbox),
+        _ => None,
+    })?;
+
+    let claim = find_superbox(&manifest.children, |label| label.starts_with("c2pa.claim"))
+        .and_then(first_cbor)?;
+    let mut provenance = Map::new();
+    provenance.insert("manifest".to_string(), Value::String(manifest.label.clone()));
+    provenance.insert("claim".to_string(), claim);
+
+    if let Some(assertions) = find_superbox(&manifest.children, |label| label == "c2pa.assertions") {
+        if let Some(actions) = find_superbox(&assertions.children, |label| label.starts_with("c2pa.actions"))
+            .and_then(first_cbor)
+        {
+            provenance.insert("actions".to_string(), actions["actions"].clone());
+        }
+    }
+
+    let mut provenance = Value::Object(provenance);
+    if let Some(name) = generator(&provenance) {
+        provenance["generator"] = Value::String(name);
+    }
+    Some(provenance)
+}
+
+/// Name of the tool that produced the content: the software agent of the
+/// `c2pa.created` action, or else the claim generator.
+pub fn generator(provenance: &Value) -> Option<String> {
+    let agent = provenance["actions"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|action| action["action"] == "c2pa.created")
+        .find_map(|action| name_of(&action["softwareAgent"]));
+
+    agent.or_else(|| {
+        let claim = &provenance["claim"];
+        claim["claim_generator_info"]
+            .as_array()
+            .and_then(|info| info.first())
+            .and_then(name_of)
+            .or_else(|| name_of(&claim["claim_generator"]))
+    })
+}
+
+/// `when` of the `c2pa.created` action, if recorded.
+pub fn created_at(provenance: &Value) -> Option<String> {
+    provenance["actions"]
+        .as_array()?
+        .iter()
+        .filter(|action| action["action"] == "c2pa.created")
+        .find_map(|action| action["when"].as_str().map(str::to_string))
+}
+
+/// Agents are either a plain string ("Adobe_Firefly/1.0 c2pa-rs/0.7") or an
+/// object with a `name`.
+fn name_of(value: &Value) -> Option<String> {
+    let name = value["name"].as_str().or(value.as_str())?;
+    let name = name.split_whitespace().next().unwrap_or(name);
+    let name = name.split('/').next().unwrap_or(name);
+    Some(name.replace('_', " "))
+}
+
+fn find_superbox<'a, 'b>(
+    children: &'b [Content<'a>],
+    matches: impl Fn(&str) -> bool,
+) -> Option<&'b Superbox<'a>> {
+    children.iter().find_map(|content| match content {
+        Content::Superbox(superbox) if matches(&superbox.label) => Some(superbox),
+        _ => None,
+    })
+}
+
+fn first_cbor(superbox: &Superbox) -> Option<Value> {
+    superbox.children.iter().find_map(|content| match content {
+        Content::Data { kind, data } if kind.as_slice() == b"cbor" => {
+            ciborium::de::from_reader::<ciborium::Value, _>(*data)
+                .ok()
+                .map(cbor_to_json)
+        }
+        _ => None,
+    })
+}
+
+/// Splits a run of ISO BMFF style boxes: a big-endian u32 length (1 means a
+/// u64 length follows, 0 means "to the end"), a four-byte type, the payload.
+fn parse_boxes(mut data: &[u8], depth: usize) -> Vec<Content<'_>> {
+    let mut boxes = Vec::new();
+    while data.len() >= 8 && depth <= MAX_DEPTH {
+        let length = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as u64;
+        let kind = [data[4], data[5], data[6], data[7]];
+        let (header, length) = match length {
+            0 => (8, data.len() as u64),
+            1 if data.len() >= 16 => {
+                let mut large = [0u8; 8];
+                large.copy_from_slice(&data[8..16]);
+                (16, u64::from_be_bytes(large))
+            }
+            _ => (8, length),
+        };
+        if length < header as u64 || length > data.len() as u64 {
+            break;
+        }
+        let payload = &data[header..length as usize];
+        data = &data[length as usize..];
+
+        if &kind == b"jumb" {
+            boxes.push(Content::Superbox(parse_superbox(payload, depth)));
+        } else {
+            boxes.push(Content::Data { kind, data: payload });
+        }
+    }
+    boxes
+}
+
+/// A superbox starts with a `jumd` description box: a 16-byte content type
+/// UUID, a toggles byte, and (if bit 1 is set) a NUL-terminated label.
+fn parse_superbox(payload: &[u8], depth: usize) -> Superbox<'_> {
+    let mut children = parse_boxes(payload, depth + 1);
+    let mut label = String::new();
+    if let Some(Content::Data { kind, data }) = children.first() {
+        if kind == b"jumd" && data.len() > 17 && data[16] & 0x02 != 0 {
+            let text = &data[17..];
+            let end = text.iter().position(|b| *b == 0).unwrap_or(text.len());
+            label = String::from_utf8_lossy(&text[..end]).into_owned();
+        }
+    }
+    if matches!(children.first(), Some(Content::Data { kind, .. }) if kind == b"jumd") {
+        children.remove(0);
+    }
+    Superbox { label, children }
+}
+
+fn cbor_to_json(value: ciborium::Value) -> Value {
+    match value {
+        ciborium::Value::Integer(i) => {
+            let i = i128::from(i);
+            i64::try_from(i)
+                .map(Value::from)
+                .unwrap_or_else(|_| Value::String(i.to_string()))
+        }
+        ciborium::Value::Float(f) => json!(f),
+        ciborium::Value::Text(s) => Value::String(s),
+        ciborium::Value::Bool(b) => Value::Bool(b),
+        ciborium::Value::Tag(_, inner) => cbor_to_json(*inner),
+        ciborium::Value::Array(items) => Value::Array(items.into_iter().map(cbor_to_json).collect()),
+        ciborium::Value::Map(entries) => Value::Object(
+            entries
+                .into_iter()
+                .filter(|(_, v)| !v.is_bytes())
+                .map(|(k, v)| {
+                    let key = match k {
+                        ciborium::Value::Text(s) => s,
+                        other => cbor_to_json(other).to_string(),
+                    };
+                    (key, cbor_to_json(v))
+                })
+                .collect(),
+        ),
+        ciborium::Value::Bytes(_) | ciborium::Value::Null => Value::Null,
+        _ => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ciborium::Value as Cbor;
+
+    fn bmff(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut out = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        out.extend(kind);
+        out.extend(payload);
+        out
+    }
+
+    fn superbox(label: &str, children: &[Vec<u8>]) -> Vec<u8> {
+        let mut description = vec![0u8; 16];
+        description.push(0x03);
+        description.extend(label.as_bytes());
+        description.push(0);
+        let mut payload = bmff(b"jumd", &description);
+        payload.extend(children.concat());
+        bmff(b"jumb", &payload)
+    }
+
+    fn cbor(entries: Vec<(&str, Cbor)>) -> Vec<u8> {
+        let map = entries.into_iter().map(|(k, v)| (Cbor::Text(k.to_string()), v)).collect();
+        let mut out = Vec::new();
+        ciborium::ser::into_writer(&Cbor::Map(map), &mut out).unwrap();
+        bmff(b"cbor", &out)
+    }
+
+    fn manifest(label: &str, claim_generator: &str, agent: Option<&str>) -> Vec<u8> {
+        let claim = cbor(vec![
+            ("claim_generator", Cbor::Text(claim_generator.to_string())),
+            ("signature", Cbor::Bytes(vec![1, 2, 3])),
+        ]);
+        let mut children = vec![superbox("c2pa.claim", &[claim])];
+        if let Some(agent) = agent {
+            let action = Cbor::Map(vec![
+                (Cbor::Text("action".into()), Cbor::Text("c2pa.created".into())),
+                (Cbor::Text("softwareAgent".into()), Cbor::Text(agent.into())),
+                (Cbor::Text("when".into()), Cbor::Text("2024-03-01T12:30:00Z".into())),
+            ]);
+            let actions = cbor(vec![("actions", Cbor::Array(vec![action]))]);
+            children.push(superbox("c2pa.assertions", &[superbox("c2pa.actions.v2", &[actions])]));
+        }
+        superbox(label, &children)
+    }
+
+    #[test]
+    fn reads_the_active_manifest() {
+        let store = superbox(
+            "c2pa",
+            &[
+                manifest("urn:uuid:old", "Photoshop/25.0", None),
+                manifest("urn:uuid:new", "c2pa-rs/0.7", Some("Adobe_Firefly/1.0 c2pa-rs/0.7")),
+            ],
+        );
+        let provenance = read_manifest_store(&store).unwrap();
+        assert_eq!(provenance["manifest"], "urn:uuid:new");
+        assert_eq!(provenance["claim"]["claim_generator"], "c2pa-rs/0.7");
+        assert!(provenance["claim"].get("signature").is_none());
+        assert_eq!(provenance["generator"], "Adobe Firefly");
+        assert_eq!(created_at(&provenance).as_deref(), Some("2024-03-01T12:30:00Z"));
+    }
+
+    #[test]
+    fn generator_falls_back_to_the_claim() {
+        let store = superbox("c2pa", &[manifest("urn:uuid:a", "ChatGPT/1.0 c2pa-rs/0.7", None)]);
+        let provenance = read_manifest_store(&store).unwrap();
+        assert_eq!(provenance["generator"], "ChatGPT");
+        assert_eq!(created_at(&provenance), None);
+
+        let info = json!({ "claim": { "claim_generator_info": [{ "name": "Imagen" }] } });
+        assert_eq!(generator(&info).as_deref(), Some("Imagen"));
+    }
+
+    #[test]
+    fn rejects_other_and_truncated_stores() {
+        let other = superbox("not-c2pa", &[manifest("urn:uuid:a", "x", None)]);
+        assert!(read_manifest_store(&other).is_none());
+
+        let store = superbox("c2pa", &[manifest("urn:uuid:a", "x", None)]);
+        assert!(read_manifest_store(&store[..store.len() - 4]).is_none());
+        assert!(read_manifest_store(&[0, 0, 0, 1, b'j', b'u', b'm', b'b']).is_none());
+    }
+}
@@ -0,0 +1,343 @@
+use super::ImageMetadata;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+
+/// Links between nodes are followed at most this deep.
+const MAX_DEPTH: usize = 16;
+
+/// Node outputs already explored by one walk. Shared nodes (reroutes, a CLIP
+/// loader feeding many encoders) are visited once, which keeps a walk linear
+/// in the size of the graph and also guards against cycles.
+type Visited = HashSet<(String, u64)>;
+
+/// Parses the graphs ComfyUI saves in its `prompt` (API format) and
+/// `workflow` (editor format) text chunks. The raw workflow is kept so it can
+/// be exported back to ComfyUI.
+pub fn parse(prompt: Option<&str>, workflow: Option<&str>) -> Option<ImageMetadata> {
+    let api_graph = prompt.and_then(|p| serde_json::from_str::<Map<String, Value>>(p).ok());
+    let editor_graph = workflow.and_then(|w| serde_json::from_str::<Value>(w).ok());
+
+    let mut meta = match (&api_graph, &editor_graph) {
+        (Some(graph), _) => parse_api_graph(graph),
+        (None, Some(graph)) => parse_editor_graph(graph),
+        (None, None) => return None,
+    };
+    meta.generator = "ComfyUI".to_string();
+    meta.workflow = match (workflow, prompt) {
+        (Some(workflow), _) if editor_graph.is_some() => Some(workflow.to_string()),
+        (_, Some(prompt)) if api_graph.is_some() => Some(prompt.to_string()),
+        _ => None,
+    };
+    Some(meta)
+}
+
+/// API format: `{ "3": { "class_type": "KSampler", "inputs": { "positive": ["6", 0], ... } } }`.
+/// Inputs are either literal values or `[node_id, output_index]` links.
+fn parse_api_graph(graph: &Map<String, Value>) -> ImageMetadata {
+    let mut meta = ImageMetadata::default();
+
+    let Some(sampler) = graph
+        .values()
+        .filter(|node| class_type(node).contains("Sampler"))
+        .max_by_key(|node| class_type(node).starts_with("KSampler"))
+    else {
+        return meta;
+    };
+    let inputs = &sampler["inputs"];
+
+    meta.prompt_text = find_text(graph, &inputs["positive"], 0, &mut Visited::new());
+    meta.negative_prompt = find_text(graph, &inputs["negative"], 0, &mut Visited::new());
+    let checkpoint_keys = ["ckpt_name", "unet_name"];
+    meta.model = find_input(graph, &inputs["model"], &checkpoint_keys, 0, &mut Visited::new())
+        .and_then(|v| v.as_str().map(model_name));
+    meta.steps = resolve(graph, &inputs["steps"], 0, &mut Visited::new())
+        .and_then(Value::as_i64)
+        .map(|v| v as i32);
+    meta.cfg_scale = resolve(graph, &inputs["cfg"], 0, &mut Visited::new()).and_then(Value::as_f64);
+    meta.sampler = resolve(graph, &inputs["sampler_name"], 0, &mut Visited::new())
+        .and_then(Value::as_str)
+        .map(|sampler| {
+            let scheduler = resolve(graph, &inputs["scheduler"], 0, &mut Visited::new());
+            match scheduler.and_then(Value::as_str) {
+                Some(scheduler) if scheduler != "normal" => format!("{} {}", sampler, scheduler),
+                _ => sampler.to_string(),
+            }
+        });
+    meta.seed = ["seed", "noise_seed"]
+        .iter()
+        .find_map(|key| resolve(graph, &inputs[*key], 0, &mut Visited::new()))
+        .map(value_to_string);
+
+    let latent = &inputs["latent_image"];
+    meta.width = find_input(graph, latent, &["width"], 0, &mut Visited::new())
+        .and_then(Value::as_u64)
+        .map(|v| v as u32);
+    meta.height = find_input(graph, latent, &["height"], 0, &mut Visited::new())
+        .and_then(Value::as_u64)
+        .map(|v| v as u32);
+
+    meta
+}
+
+/// Editor format: `{ "nodes": [{ "type": "KSampler", "widgets_values": [...] }] }`.
+/// Without the links resolved, the first text encoder is taken as the
+/// positive prompt and the second as the negative one.
+fn parse_editor_graph(graph: &Value) -> ImageMetadata {
+    let mut meta = ImageMetadata::default();
+    let nodes = graph["nodes"].as_array().map(Vec::as_slice).unwrap_or_default();
+    let of_type = |prefix: &'static str| {
+        nodes
+            .iter()
+            .filter(move |n| n["type"].as_str().is_some_and(|t| t.starts_with(prefix)))
+    };
+
+    let mut texts = of_type("CLIPTextEncode").filter_map(|n| n["widgets_values"][0].as_str());
+    meta.prompt_text = texts.next().map(str::to_string);
+    meta.negative_prompt = texts.next().map(str::to_string);
+
+    if let Some(checkpoint) = of_type("CheckpointLoader").next() {
+        meta.model = checkpoint["widgets_values"][0].as_str().map(model_name);
+    }
+    // KSampler widgets: seed, seed control, steps, cfg, sampler, scheduler,
+    // denoise. KSamplerAdvanced puts add_noise in front of them.
+    let sampler = nodes.iter().find_map(|n| match n["type"].as_str() {
+        Some("KSampler") => Some((n, 0)),
+        Some("KSamplerAdvanced") => Some((n, 1)),
+        _ => None,
+    });
+    if let Some((sampler, offset)) = sampler {
+        let widgets = &sampler["widgets_values"];
+        meta.seed = widgets.get(offset).filter(|v| !v.is_null()).map(value_to_string);
+        meta.steps = widgets[offset + 2].as_i64().map(|v| v as i32);
+        meta.cfg_scale = widgets[offset + 3].as_f64();
+        meta.sampler = widgets[offset + 4].as_str().map(str::to_string);
+    }
+    if let Some(latent) = of_type("EmptyLatentImage").next() {
+        meta.width = latent["widgets_values"][0].as_u64().map(|v| v as u32);
+        meta.height = latent["widgets_values"][1].as_u64().map(|v| v as u32);
+    }
+
+    meta
+}
+
+fn class_type(node: &Value) -> &str {
+    node["class_type"].as_str().unwrap_or_default()
+}
+
+fn linked_node<'a>(graph: &'a Map<String, Value>, value: &Value) -> Option<&'a Value> {
+    graph.get(&link_target(value)?.0)
+}
+
+/// The node id and output index a `[node_id, output_index]` link points at.
+fn link_target(value: &Value) -> Option<(String, u64)> {
+    let link = value.as_array()?;
+    let id = match link.first()? {
+        Value::String(id) => id.clone(),
+        Value::Number(id) => id.to_string(),
+        _ => return None,
+    };
+    Some((id, link.get(1).and_then(Value::as_u64).unwrap_or(0)))
+}
+
+/// Whether `link` leads to an output not explored yet, marking it as
+/// explored.
+fn first_visit(visited: &mut Visited, link: &Value) -> bool {
+    link_target(link).is_some_and(|target| visited.insert(target))
+}
+
+/// Follows links until a literal value is reached. Primitive and widget
+/// nodes hold their value in one of a few well-known inputs.
+fn resolve<'a>(
+    graph: &'a Map<String, Value>,
+    value: &'a Value,
+    depth: usize,
+    visited: &mut Visited,
+) -> Option<&'a Value> {
+    if depth > MAX_DEPTH || value.is_null() {
+        return None;
+    }
+    match linked_node(graph, value) {
+        Some(_) if !first_visit(visited, value) => None,
+        Some(node) => ["value", "seed", "noise_seed", "int", "float", "string", "text"]
+            .iter()
+            .find_map(|key| resolve(graph, &node["inputs"][*key], depth + 1, visited)),
+        None if value.is_array() => None,
+        None => Some(value),
+    }
+}
+
+/// Walks upstream from a link until a node has one of `keys` as an input.
+fn find_input<'a>(
+    graph: &'a Map<String, Value>,
+    link: &'a Value,
+    keys: &[&str],
+    depth: usize,
+    visited: &mut Visited,
+) -> Option<&'a Value> {
+    if depth > MAX_DEPTH || !first_visit(visited, link) {
+        return None;
+    }
+    let inputs = linked_node(graph, link)?["inputs"].as_object()?;
+    if let Some(value) = keys.iter().find_map(|key| inputs.get(*key)) {
+        return resolve(graph, value, depth + 1, &mut Visited::new());
+    }
+    inputs
+        .values()
+        .find_map(|value| find_input(graph, value, keys, depth + 1, visited))
+}
+
+/// Finds the prompt text feeding a conditioning input, looking through
+/// nodes such as ControlNet or conditioning combiners on the way.
+fn find_text(
+    graph: &Map<String, Value>,
+    link: &Value,
+    depth: usize,
+    visited: &mut Visited,
+) -> Option<String> {
+    if depth > MAX_DEPTH || !first_visit(visited, link) {
+        return None;
+    }
+    let inputs = linked_node(graph, link)?["inputs"].as_object()?;
+
+    // CLIPTextEncodeSDXL splits the prompt into `text_g` and `text_l`.
+    for key in ["text", "text_g", "prompt"] {
+        if let Some(text) = inputs
+            .get(key)
+            .and_then(|v| resolve(graph, v, depth + 1, &mut Visited::new()))
+            .and_then(Value::as_str)
+        {
+            return Some(text.to_string());
+        }
+    }
+    // Nodes like ControlNetApplyAdvanced pass both conditionings through:
+    // output 0 is the positive one and output 1 the negative one.
+    let slot = link.get(1).and_then(Value::as_u64).unwrap_or(0);
+    let passthrough = if slot == 1 { "negative" } else { "positive" };
+    [passthrough, "conditioning", "conditioning_1"]
+        .iter()
+        .filter_map(|key| inputs.get(*key))
+        .chain(inputs.values())
+        .find_map(|value| find_text(graph, value, depth + 1, visited))
+}
+
+/// `sd_xl_base_1.0.safetensors` or `SDXL/juggernaut.ckpt` → the file stem.
+fn model_name(file: &str) -> String {
+    let name = file.rsplit(['/', '\\']).next().unwrap_or(file);
+    match name.rsplit_once('.') {
+        Some((stem, "safetensors" | "ckpt" | "pt" | "gguf" | "sft")) => stem.to_string(),
+        _ => name.to_string(),
+    }
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const API_GRAPH: &str = r#"{
+        "3": { "class_type": "KSampler", "inputs": {
+            "model": ["4", 0], "positive": ["10", 0], "negative": ["10", 1],
+            "latent_image": ["5", 0], "seed": ["11", 0], "steps": 28, "cfg": 6.5,
+            "sampler_name": "dpmpp_2m", "scheduler": "karras" } },
+        "4": { "class_type": "CheckpointLoaderSimple",
+            "inputs": { "ckpt_name": "SDXL/juggernaut.safetensors" } },
+        "5": { "class_type": "EmptyLatentImage",
+            "inputs": { "width": 832, "height": 1216, "batch_size": 1 } },
+        "6": { "class_type": "CLIPTextEncode", "inputs": { "text": "a cat", "clip": ["4", 1] } },
+        "7": { "class_type": "CLIPTextEncode", "inputs": { "text": "blurry", "clip": ["4", 1] } },
+        "10": { "class_type": "ControlNetApplyAdvanced",
+            "inputs": { "positive": ["6", 0], "negative": ["7", 0] } },
+        "11": { "class_type": "PrimitiveNode", "inputs": { "value": 1234 } }
+    }"#;
+
+    #[test]
+    fn follows_links_in_api_graphs() {
+        let meta = parse(Some(API_GRAPH), None).unwrap();
+        assert_eq!(meta.generator, "ComfyUI");
+        assert_eq!(meta.prompt_text.as_deref(), Some("a cat"));
+        assert_eq!(meta.negative_prompt.as_deref(), Some("blurry"));
+        assert_eq!(meta.model.as_deref(), Some("juggernaut"));
+        assert_eq!(meta.sampler.as_deref(), Some("dpmpp_2m karras"));
+        assert_eq!(meta.seed.as_deref(), Some("1234"));
+        assert_eq!((meta.steps, meta.cfg_scale), (Some(28), Some(6.5)));
+        assert_eq!((meta.width, meta.height), (Some(832), Some(1216)));
+        assert_eq!(meta.workflow.as_deref(), Some(API_GRAPH));
+    }
+
+    #[test]
+    fn cycles_end_the_walk() {
+        let graph = r#"{
+            "1": { "class_type": "KSampler", "inputs": {
+                "positive": ["2", 0], "model": ["3", 0], "steps": ["4", 0] } },
+            "2": { "class_type": "ConditioningCombine",
+                "inputs": { "conditioning_1": ["3", 0], "conditioning_2": ["2", 0] } },
+            "3": { "class_type": "Reroute", "inputs": { "input": ["2", 0] } },
+            "4": { "class_type": "PrimitiveNode", "inputs": { "value": ["4", 0] } }
+        }"#;
+        let meta = parse(Some(graph), None).unwrap();
+        assert_eq!(meta.prompt_text, None);
+        assert_eq!(meta.model, None);
+        assert_eq!(meta.steps, None);
+    }
+
+    #[test]
+    fn shared_nodes_are_visited_once() {
+        // Every combiner links both of its inputs to the one below, so an
+        // unguarded walk would branch 2^depth times.
+        let sampler = r#""0": { "class_type": "KSampler", "inputs": { "positive": ["1", 0] } }"#;
+        let mut nodes = vec![sampler.to_string()];
+        for id in 1..MAX_DEPTH {
+            nodes.push(format!(
+                r#""{id}": {{ "class_type": "ConditioningCombine", "inputs": {{
+                    "conditioning_1": ["{next}", 0], "conditioning_2": ["{next}", 0] }} }}"#,
+                id = id,
+                next = id + 1,
+            ));
+        }
+        nodes.push(format!(
+            r#""{}": {{ "class_type": "CLIPTextEncode", "inputs": {{ "text": "deep" }} }}"#,
+            MAX_DEPTH
+        ));
+        let graph = format!("{{{}}}", nodes.join(","));
+        let meta = parse(Some(&graph), None).unwrap();
+        assert_eq!(meta.prompt_text.as_deref(), Some("deep"));
+    }
+
+    #[test]
+    fn reads_editor_graphs_by_widget_position() {
+        let workflow = r#"{ "nodes": [
+            { "type": "CheckpointLoaderSimple", "widgets_values": ["flux1-dev.sft"] },
+            { "type": "CLIPTextEncode", "widgets_values": ["a cat"] },
+            { "type": "CLIPTextEncode", "widgets_values": ["blurry"] },
+            { "type": "KSampler", "widgets_values": [42, "fixed", 20, 3.5, "euler", "normal", 1] },
+            { "type": "EmptyLatentImage", "widgets_values": [1024, 768, 1] }
+        ] }"#;
+        let meta = parse(Some("not json"), Some(workflow)).unwrap();
+        assert_eq!(meta.prompt_text.as_deref(), Some("a cat"));
+        assert_eq!(meta.negative_prompt.as_deref(), Some("blurry"));
+        assert_eq!(meta.model.as_deref(), Some("flux1-dev"));
+        assert_eq!(meta.seed.as_deref(), Some("42"));
+        assert_eq!(meta.sampler.as_deref(), Some("euler"));
+        assert_eq!((meta.width, meta.height), (Some(1024), Some(768)));
+        assert_eq!(meta.workflow.as_deref(), Some(workflow));
+        assert!(parse(Some("not json"), None).is_none());
+    }
+
+    #[test]
+    fn reads_advanced_sampler_widgets() {
+        let workflow = r#"{ "nodes": [
+            { "type": "KSamplerAdvanced", "widgets_values":
+                ["enable", 42, "fixed", 25, 5.5, "dpmpp_2m", "karras", 0, 10000, "disable"] }
+        ] }"#;
+        let meta = parse(None, Some(workflow)).unwrap();
+        assert_eq!(meta.seed.as_deref(), Some("42"));
+        assert_eq!((meta.steps, meta.cfg_scale), (Some(25), Some(5.5)));
+        assert_eq!(meta.sampler.as_deref(), Some("dpmpp_2m"));
+    }
+}
@@ -0,0 +1,455 @@
+use super::{a1111, c2pa, ImageMetadata};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use exif::{In, Reader, Tag, Value};
+use std::collections::HashMap;
+
+/// Metadata blocks found in a JPEG or WebP container.
+#[derive(Default)]
+struct Containers {
+    /// TIFF-structured EXIF data.
+    exif: Option<Vec<u8>>,
+    xmp: Option<String>,
+    /// C2PA manifest store as JUMBF.
+    c2pa: Option<Vec<u8>>,
+}
+
+/// Reads EXIF, XMP and C2PA metadata from JPEG and WebP files, as written by
+/// DALL-E, Firefly, Leonardo and the like. A1111 parameters stored in the EXIF
+/// `UserComment` (how it saves JPEG/WebP) are recognised too.
+pub fn read(data: &[u8]) -> Option<ImageMetadata> {
+    let containers = if data.starts_with(&[0xFF, 0xD8]) {
+        read_jpeg(data)
+    } else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        read_webp(data)
+    } else {
+        return None;
+    };
+
+    let exif = containers
+        .exif
+        .and_then(|tiff| Reader::new().read_raw(tiff).ok());
+    let exif_text = |tag: Tag| exif.as_ref().and_then(|exif| field_text(exif, tag));
+    let xmp_text = |name: &str| containers.xmp.as_deref().and_then(|xmp| xmp_value(xmp, name));
+    let provenance = containers.c2pa.as_deref().and_then(c2pa::read_manifest_store);
+
+    let comment = exif_text(Tag::UserComment).or_else(|| exif_text(XP_COMMENT));
+    let mut meta = match comment.as_deref().and_then(a1111_parameters) {
+        Some(meta) => meta,
+        None => ImageMetadata {
+            prompt_text: xmp_text("dc:description")
+                .or_else(|| exif_text(Tag::ImageDescription))
+                .or(comment)
+                .or_else(|| xmp_text("dc:title")),
+            ..Default::default()
+        },
+    };
+
+    let generator = provenance
+        .as_ref()
+        .and_then(c2pa::generator)
+        .or_else(|| xmp_text("xmp:CreatorTool"))
+        .or_else(|| exif_text(Tag::Software))
+        .or_else(|| exif_text(Tag::Make));
+    if let Some(generator) = generator {
+        // Cameras and editors ("Canon", "Adobe Photoshop 25.0") name no model.
+        if meta.model.is_none() {
+            meta.model = known_model(&generator).map(str::to_string);
+        }
+        if meta.generator.is_empty() {
+            meta.generator = generator;
+        }
+    }
+
+    meta.created_at = exif_text(Tag::DateTimeOriginal)
+        .and_then(|date| parse_date(&date))
+        .or_else(|| xmp_text("xmp:CreateDate").and_then(|date| parse_date(&date)))
+        .or_else(|| xmp_text("photoshop:DateCreated").and_then(|date| parse_date(&date)))
+        .or_else(|| provenance.as_ref().and_then(c2pa::created_at).and_then(|date| parse_date(&date)))
+        .or_else(|| exif_text(Tag::DateTime).and_then(|date| parse_date(&date)));
+    meta.provenance = provenance;
+
+    let found = meta.prompt_text.is_some() || meta.model.is_some() || meta.provenance.is_some();
+    found.then_some(meta)
+}
+
+/// Windows `XPComment`, UTF-16LE in the primary IFD.
+const XP_COMMENT: Tag = Tag(exif::Context::Tiff, 0x9C9C);
+
+fn a1111_parameters(comment: &str) -> Option<ImageMetadata> {
+    if comment.lines().any(|line| line.trim_start().starts_with("Steps:")) {
+        a1111::parse_parameters(comment)
+    } else {
+        None
+    }
+}
+
+/// Walks the JPEG marker segments up to the image data. C2PA data can span
+/// several APP11 segments that have to be joined.
+fn read_jpeg(data: &[u8]) -> Containers {
+    let mut containers = Containers::default();
+    let mut jumbf: HashMap<u16, Vec<u8>> = HashMap::new();
+    let mut pos = 2;
+
+    while pos + 4 <= data.len() && data[pos] == 0xFF {
+        let marker = data[pos + 1];
+        if marker == 0xD8 || (0xD0..=0xD7).contains(&marker) || marker == 0x01 {
+            pos += 2;
+            continue;
+        }
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        if length < 2 || pos + 2 + length > data.len() {
+            break;
+        }
+        let segment = &data[pos + 4..pos + 2 + length];
+        pos += 2 + length;
+
+        match marker {
+            0xE1 => {
+                if let Some(tiff) = segment.strip_prefix(b"Exif\0\0") {
+                    containers.exif.get_or_insert_with(|| tiff.to_vec());
+                } else if let Some(xml) = segment.strip_prefix(b"http://ns.adobe.com/xap/1.0/\0") {
+                    containers
+                        .xmp
+                        .get_or_insert_with(|| String::from_utf8_lossy(xml).into_owned());
+                }
+            }
+            // APP11: "JP", box instance (u16), sequence number (u32), then
+            // the JUMBF bytes. Continuation segments repeat the 8-byte box
+            // header, which is dropped.
+            0xEB if segment.len() > 8 && segment.starts_with(b"JP") => {
+                let instance = u16::from_be_bytes([segment[2], segment[3]]);
+                let sequence = u32::from_be_bytes([segment[4], segment[5], segment[6], segment[7]]);
+                let body = &segment[8..];
+                let buffer = jumbf.entry(instance).or_default();
+                if sequence <= 1 {
+                    buffer.extend_from_slice(body);
+                } else if body.len() > 8 {
+                    buffer.extend_from_slice(&body[8..]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    containers.c2pa = jumbf
+        .into_values()
+        .find(|bytes| bytes.windows(4).any(|w| w == b"c2pa"));
+    containers
+}
+
+/// WebP is a RIFF file: four-byte chunk ids, little-endian sizes, and
+/// payloads padded to an even length.
+fn read_webp(data: &[u8]) -> Containers {
+    let mut containers = Containers::default();
+    let mut pos = 12;
+
+    while pos + 8 <= data.len() {
+        let id = &data[pos..pos + 4];
+        let size = u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]]) as usize;
+        let start = pos + 8;
+        let Some(payload) = data.get(start..start + size) else {
+            break;
+        };
+        match id {
+            b"EXIF" => {
+                let tiff = payload.strip_prefix(b"Exif\0\0").unwrap_or(payload);
+                containers.exif = Some(tiff.to_vec());
+            }
+            b"XMP " => containers.xmp = Some(String::from_utf8_lossy(payload).into_owned()),
+            b"C2PA" => containers.c2pa = Some(payload.to_vec()),
+            _ => {}
+        }
+        pos = start + size + (size & 1);
+    }
+
+    containers
+}
+
+fn field_text(exif: &exif::Exif, tag: Tag) -> Option<String> {
+    let field = exif.get_field(tag, In::PRIMARY)?;
+    let text = match &field.value {
+        Value::Ascii(parts) => parts
+            .iter()
+            .map(|part| String::from_utf8_lossy(part).into_owned())
+            .collect::<Vec<_>>()
+            .join(" "),
+        Value::Undefined(bytes, _) if tag == Tag::UserComment => {
+            user_comment(bytes, exif.little_endian())
+        }
+        Value::Byte(bytes) if tag == XP_COMMENT => utf16(bytes, true),
+        _ => return None,
+    };
+    let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+/// `UserComment` starts with an 8-byte character code. "UNICODE" is UTF-16
+/// in the byte order of the file, though some writers always use big endian;
+/// a leading NUL byte gives that away.
+fn user_comment(bytes: &[u8], little_endian: bool) -> String {
+    let (code, text) = bytes.split_at(bytes.len().min(8));
+    if code.starts_with(b"UNICODE") {
+        let little_endian = little_endian && text.first() != Some(&0);
+        utf16(text, little_endian)
+    } else {
+        String::from_utf8_lossy(text).into_owned()
+    }
+}
+
+fn utf16(bytes: &[u8], little_endian: bool) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| {
+            if little_endian {
+                u16::from_le_bytes([pair[0], pair[1]])
+            } else {
+                u16::from_be_bytes([pair[0], pair[1]])
+            }
+        })
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Value of an XMP property, written either as an attribute
+/// (`xmp:CreatorTool="..."`) or as an element, possibly wrapping an
+/// `rdf:Alt`/`rdf:Seq` list whose first `rdf:li` is taken.
+fn xmp_value(xmp: &str, name: &str) -> Option<String> {
+    let attribute = format!("{}=\"", name);
+    if let Some(start) = xmp.find(&attribute) {
+        let rest = &xmp[start + attribute.len()..];
+        let value = &rest[..rest.find('"')?];
+        return non_empty(unescape_xml(value));
+    }
+
+    let open = format!("<{}", name);
+    let close = format!("</{}>", name);
+    let start = xmp.find(&open)?;
+    let rest = &xmp[start + open.len()..];
+    let inner = &rest[rest.find('>')? + 1..];
+    let inner = &inner[..inner.find(&close)?];
+    let value = match inner.find("<rdf:li") {
+        Some(li) => {
+            let li = &inner[li..];
+            let text = &li[li.find('>')? + 1..];
+            &text[..text.find("</rdf:li>")?]
+        }
+        None => inner,
+    };
+    non_empty(unescape_xml(value))
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#xA;", "\n")
+        .replace("&#10;", "\n")
+        .replace("&amp;", "&")
+}
+
+fn non_empty(text: String) -> Option<String> {
+    let text = text.trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+/// Normalizes the date formats found in EXIF (`2024:03:01 12:30:00`, local
+/// time), XMP and C2PA (ISO 8601) to SQLite's `CURRENT_TIMESTAMP` format in
+/// UTC, so imported dates sort with the others.
+fn parse_date(text: &str) -> Option<String> {
+    let text = text.trim();
+    let utc = if let Ok(date) = DateTime::parse_from_rfc3339(text) {
+        date.with_timezone(&Utc)
+    } else {
+        let naive = ["%Y:%m:%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+            .or_else(|| {
+                NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                    .ok()
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+            })?;
+        Local.from_local_datetime(&naive).earliest()?.with_timezone(&Utc)
+    };
+    Some(utc.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+/// Maps generator names to the default models of the registry, so
+/// "Adobe Firefly" lands on the existing "Firefly" model.
+fn known_model(generator: &str) -> Option<&'static str> {
+    let generator = generator.to_lowercase();
+    [
+        ("firefly", "Firefly"),
+        ("dall", "DALL-E 3"),
+        ("openai", "DALL-E 3"),
+        ("chatgpt", "DALL-E 3"),
+        ("leonardo", "Leonardo AI"),
+        ("midjourney", "Midjourney V6"),
+        ("gemini", "Gemini"),
+        ("imagen", "Gemini"),
+    ]
+    .iter()
+    .find(|(needle, _)| generator.contains(needle))
+    .map(|(_, model)| *model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tag, type and value of an IFD entry.
+    type Field<'a> = (u16, u16, &'a [u8]);
+
+    /// Little-endian TIFF with ASCII or UNDEFINED fields in IFD0 and, if
+    /// `exif` isn't empty, an EXIF IFD.
+    fn tiff(ifd0: &[Field], exif: &[Field]) -> Vec<u8> {
+        let ifd_size = |n: usize| 2 + 12 * n + 4;
+        let ifd0_entries = ifd0.len() + usize::from(!exif.is_empty());
+        let exif_at = 8 + ifd_size(ifd0_entries);
+        let mut data_at = exif_at + if exif.is_empty() { 0 } else { ifd_size(exif.len()) };
+
+        let mut out = b"II*\0\x08\0\0\0".to_vec();
+        let mut data = Vec::new();
+        let mut write_ifd = |out: &mut Vec<u8>, fields: &[Field], exif_ifd: Option<usize>| {
+            let count = fields.len() + usize::from(exif_ifd.is_some());
+            out.extend((count as u16).to_le_bytes());
+            for (tag, kind, value) in fields {
+                out.extend(tag.to_le_bytes());
+                out.extend(kind.to_le_bytes());
+                out.extend((value.len() as u32).to_le_bytes());
+                out.extend((data_at as u32).to_le_bytes());
+                data.extend_from_slice(value);
+                data_at += value.len();
+            }
+            if let Some(offset) = exif_ifd {
+                out.extend(0x8769u16.to_le_bytes());
+                out.extend(4u16.to_le_bytes());
+                out.extend(1u32.to_le_bytes());
+                out.extend((offset as u32).to_le_bytes());
+            }
+            out.extend(0u32.to_le_bytes());
+        };
+        write_ifd(&mut out, ifd0, (!exif.is_empty()).then_some(exif_at));
+        if !exif.is_empty() {
+            write_ifd(&mut out, exif, None);
+        }
+        out.extend(data);
+        out
+    }
+
+    fn jpeg(segments: &[(u8, Vec<u8>)]) -> Vec<u8> {
+        let mut out = vec![0xFF, 0xD8];
+        for (marker, payload) in segments {
+            out.extend([0xFF, *marker]);
+            out.extend(((payload.len() + 2) as u16).to_be_bytes());
+            out.extend(payload);
+        }
+        out.extend([0xFF, 0xD9]);
+        out
+    }
+
+    fn webp(chunks: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut body = b"WEBP".to_vec();
+        for (id, payload) in chunks {
+            body.extend(*id);
+            body.extend((payload.len() as u32).to_le_bytes());
+            body.extend(payload);
+            if payload.len() % 2 == 1 {
+                body.push(0);
+            }
+        }
+        let mut out = b"RIFF".to_vec();
+        out.extend((body.len() as u32).to_le_bytes());
+        out.extend(body);
+        out
+    }
+
+    #[test]
+    fn reads_a1111_parameters_from_the_user_comment() {
+        let mut comment = b"UNICODE\0".to_vec();
+        for unit in "a cat\nSteps: 20, Sampler: Euler a, Model: sdxl".encode_utf16() {
+            comment.extend(unit.to_le_bytes());
+        }
+        let exif = [b"Exif\0\0".as_slice(), &tiff(&[], &[(0x9286, 7, &comment)])].concat();
+        let meta = read(&jpeg(&[(0xE1, exif)])).unwrap();
+        assert_eq!(meta.generator, "Automatic1111");
+        assert_eq!(meta.prompt_text.as_deref(), Some("a cat"));
+        assert_eq!(meta.sampler.as_deref(), Some("Euler a"));
+        assert_eq!(meta.model.as_deref(), Some("sdxl"));
+    }
+
+    #[test]
+    fn reads_description_and_software_from_exif() {
+        let tiff = tiff(
+            &[(0x010E, 2, b"a red fox\0"), (0x0131, 2, b"Leonardo.Ai\0")],
+            &[(0x9003, 2, b"2024:03:01 12:30:00\0")],
+        );
+        let meta = read(&webp(&[(b"EXIF", tiff)])).unwrap();
+        assert_eq!(meta.prompt_text.as_deref(), Some("a red fox"));
+        assert_eq!(meta.generator, "Leonardo.Ai");
+        assert_eq!(meta.model.as_deref(), Some("Leonardo AI"));
+        assert_eq!(meta.created_at, parse_date("2024-03-01T12:30:00"));
+    }
+
+    #[test]
+    fn cameras_and_editors_are_no_model() {
+        let camera = tiff(&[(0x010F, 2, b"Canon\0"), (0x0131, 2, b"Adobe Photoshop 25.0\0")], &[]);
+        assert!(read(&webp(&[(b"EXIF", camera)])).is_none());
+
+        let edited = tiff(
+            &[(0x010E, 2, b"a red fox\0"), (0x0131, 2, b"Adobe Photoshop 25.0\0")],
+            &[],
+        );
+        let meta = read(&webp(&[(b"EXIF", edited)])).unwrap();
+        assert_eq!(meta.generator, "Adobe Photoshop 25.0");
+        assert_eq!(meta.model, None);
+    }
+
+    #[test]
+    fn reads_xmp_properties() {
+        let xmp = r#"<x:xmpmeta><rdf:Description xmp:CreatorTool="Adobe Firefly"
+            xmp:CreateDate="2024-03-01T12:30:00+02:00">
+            <dc:description><rdf:Alt><rdf:li xml:lang="x-default">a &quot;cozy&quot; cabin</rdf:li>
+            </rdf:Alt></dc:description></rdf:Description></x:xmpmeta>"#;
+        let segment = [b"http://ns.adobe.com/xap/1.0/\0".as_slice(), xmp.as_bytes()].concat();
+        let meta = read(&jpeg(&[(0xE1, segment)])).unwrap();
+        assert_eq!(meta.prompt_text.as_deref(), Some("a \"cozy\" cabin"));
+        assert_eq!(meta.generator, "Adobe Firefly");
+        assert_eq!(meta.model.as_deref(), Some("Firefly"));
+        assert_eq!(meta.created_at.as_deref(), Some("2024-03-01 10:30:00"));
+    }
+
+    #[test]
+    fn joins_c2pa_segments() {
+        let app11 = |sequence: u32, body: &[u8]| {
+            let mut segment = b"JP\0\x01".to_vec();
+            segment.extend(sequence.to_be_bytes());
+            segment.extend(body);
+            segment
+        };
+        // The continuation repeats the 8-byte box header, which is dropped.
+        let first = app11(1, b"\0\0\0\x10jumbc2pa");
+        let second = app11(2, b"\0\0\0\x10jumbrest");
+        let containers = read_jpeg(&jpeg(&[(0xEB, first), (0xEB, second)]));
+        assert_eq!(containers.c2pa.as_deref(), Some(b"\0\0\0\x10jumbc2parest".as_slice()));
+    }
+
+    #[test]
+    fn ignores_images_without_metadata() {
+        assert!(read(&jpeg(&[(0xE0, b"JFIF\0".to_vec())])).is_none());
+        assert!(read(&webp(&[(b"VP8 ", vec![0; 11])])).is_none());
+        assert!(read(b"GIF89a").is_none());
+        assert_eq!(parse_date("not a date"), None);
+    }
+}
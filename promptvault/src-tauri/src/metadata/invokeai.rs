@@ -0,0 +1,71 @@
+use super::ImageMetadata;
+use serde_json::Value;
+
+/// Parses the `invokeai_metadata` JSON chunk written by InvokeAI 3 and later.
+pub fn parse(json: &str) -> Option<ImageMetadata> {
+    let json: Value = serde_json::from_str(json).ok()?;
+    if !json.is_object() {
+        return None;
+    }
+
+    let text = |key: &str| {
+        json[key]
+            .as_str()
+            .filter(|t| !t.trim().is_empty())
+            .map(str::to_string)
+    };
+
+    // `model` is an object in current versions ({ "name", "hash", ... }) and
+    // used `model_name` in early 3.x releases.
+    let model = &json["model"];
+    Some(ImageMetadata {
+        generator: "InvokeAI".to_string(),
+        prompt_text: text("positive_prompt"),
+        negative_prompt: text("negative_prompt"),
+        model: model["name"]
+            .as_str()
+            .or(model["model_name"].as_str())
+            .or(model.as_str())
+            .map(str::to_string),
+        model_hash: model["hash"].as_str().map(str::to_string),
+        steps: json["steps"].as_i64().map(|v| v as i32),
+        sampler: text("scheduler"),
+        cfg_scale: json["cfg_scale"].as_f64(),
+        seed: json["seed"].as_u64().map(|v| v.to_string()),
+        width: json["width"].as_u64().map(|v| v as u32),
+        height: json["height"].as_u64().map(|v| v as u32),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_current_metadata() {
+        let json = r#"{ "positive_prompt": "a cat", "negative_prompt": " ", "steps": 30,
+            "scheduler": "dpmpp_2m", "cfg_scale": 7.5, "seed": 1234, "width": 1024, "height": 768,
+            "model": { "name": "Juggernaut XL", "hash": "blake3:abc", "base": "sdxl" } }"#;
+        let meta = parse(json).unwrap();
+        assert_eq!(meta.generator, "InvokeAI");
+        assert_eq!(meta.prompt_text.as_deref(), Some("a cat"));
+        assert_eq!(meta.negative_prompt, None);
+        assert_eq!(meta.sampler.as_deref(), Some("dpmpp_2m"));
+        assert_eq!((meta.steps, meta.cfg_scale), (Some(30), Some(7.5)));
+        assert_eq!(meta.seed.as_deref(), Some("1234"));
+        assert_eq!((meta.width, meta.height), (Some(1024), Some(768)));
+        assert_eq!(meta.model.as_deref(), Some("Juggernaut XL"));
+        assert_eq!(meta.model_hash.as_deref(), Some("blake3:abc"));
+    }
+
+    #[test]
+    fn reads_older_model_fields() {
+        let meta = parse(r#"{ "model": { "model_name": "sd-1.5" } }"#).unwrap();
+        assert_eq!(meta.model.as_deref(), Some("sd-1.5"));
+        let meta = parse(r#"{ "model": "sd-1.5" }"#).unwrap();
+        assert_eq!(meta.model.as_deref(), Some("sd-1.5"));
+        assert_eq!(meta.model_hash, None);
+        assert!(parse("\"a cat\"").is_none());
+    }
+}
@@ -0,0 +1,190 @@
+use super::ImageMetadata;
+use flate2::read::GzDecoder;
+use serde_json::Value;
+use std::io::Read;
+
+/// Parses the `Comment` JSON NovelAI writes next to its `Description`
+/// (prompt) and `Source` (model) chunks.
+pub fn parse_comment(
+    comment: &str,
+    description: Option<&str>,
+    source: Option<&str>,
+) -> Option<ImageMetadata> {
+    let json: Value = serde_json::from_str(comment).ok()?;
+    if !json.is_object() {
+        return None;
+    }
+
+    // V4 models keep the prompt in `v4_prompt.caption.base_caption`.
+    let caption = |key: &str| json[key]["caption"]["base_caption"].as_str();
+    let text = |value: Option<&str>| {
+        value
+            .filter(|t| !t.trim().is_empty())
+            .map(str::to_string)
+    };
+
+    let mut meta = ImageMetadata {
+        generator: "NovelAI".to_string(),
+        prompt_text: text(json["prompt"].as_str().or(caption("v4_prompt")).or(description)),
+        negative_prompt: text(json["uc"].as_str().or(caption("v4_negative_prompt"))),
+        steps: json["steps"].as_i64().map(|v| v as i32),
+        sampler: json["sampler"].as_str().map(str::to_string),
+        cfg_scale: json["scale"].as_f64(),
+        seed: match &json["seed"] {
+            Value::Number(n) => Some(n.to_string()),
+            Value::String(s) => Some(s.clone()),
+            _ => None,
+        },
+        width: json["width"].as_u64().map(|v| v as u32),
+        height: json["height"].as_u64().map(|v| v as u32),
+        ..Default::default()
+    };
+
+    // `Source` looks like "NovelAI Diffusion V4.5 4BDE2A90"; the last word
+    // is the model hash.
+    if let Some(source) = source.map(str::trim).filter(|s| !s.is_empty()) {
+        match source.rsplit_once(' ') {
+            Some((name, hash)) if hash.len() == 8 && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+                meta.model = Some(name.to_string());
+                meta.model_hash = Some(hash.to_string());
+            }
+            _ => meta.model = Some(source.to_string()),
+        }
+    }
+
+    Some(meta)
+}
+
+/// Reads metadata hidden in the least significant bit of the alpha channel
+/// ("stealth pnginfo"), which survives sites that strip text chunks.
+///
+/// Bits are read column by column, top to bottom. The payload starts with a
+/// 15-byte signature, then its length in bits as a big-endian u32.
+/// `stealth_pngcomp` payloads are gzipped, `stealth_pnginfo` ones are not.
+pub fn read_stealth(data: &[u8]) -> Option<String> {
+    let image = image::load_from_memory(data).ok()?;
+    if !image.color().has_alpha() {
+        return None;
+    }
+    let rgba = image.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let mut bits = (0..width)
+        .flat_map(|x| (0..height).map(move |y| (x, y)))
+        .map(|(x, y)| rgba.get_pixel(x, y)[3] & 1);
+
+    let compressed = match read_bytes(&mut bits, 15)?.as_slice() {
+        b"stealth_pnginfo" => false,
+        b"stealth_pngcomp" => true,
+        _ => return None,
+    };
+    let length_bits = u32::from_be_bytes(read_bytes(&mut bits, 4)?.try_into().ok()?) as u64;
+    let capacity = width as u64 * height as u64;
+    if length_bits == 0 || length_bits > capacity {
+        return None;
+    }
+    let payload = read_bytes(&mut bits, (length_bits / 8) as usize)?;
+
+    if compressed {
+        let mut text = String::new();
+        GzDecoder::new(payload.as_slice())
+            .read_to_string(&mut text)
+            .ok()?;
+        Some(text)
+    } else {
+        String::from_utf8(payload).ok()
+    }
+}
+
+fn read_bytes(bits: &mut impl Iterator<Item = u8>, count: usize) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mut byte = 0u8;
+        for _ in 0..8 {
+            byte = (byte << 1) | bits.next()?;
+        }
+        bytes.push(byte);
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use image::{ImageFormat, RgbaImage};
+    use std::io::{Cursor, Write};
+
+    /// A 32x32 PNG with `payload` hidden in the alpha channel, column by
+    /// column, declaring a length of `length_bits`.
+    fn stealth_png(signature: &[u8], length_bits: u32, payload: &[u8]) -> Vec<u8> {
+        let mut bytes = signature.to_vec();
+        bytes.extend(length_bits.to_be_bytes());
+        bytes.extend(payload);
+        let bits: Vec<u8> = bytes
+            .iter()
+            .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1))
+            .collect();
+
+        let (width, height) = (32, 32);
+        let mut image = RgbaImage::from_pixel(width, height, image::Rgba([10, 20, 30, 254]));
+        for (i, bit) in bits.iter().enumerate() {
+            let (x, y) = (i as u32 / height, i as u32 % height);
+            image.get_pixel_mut(x, y)[3] |= bit;
+        }
+        let mut out = Cursor::new(Vec::new());
+        image.write_to(&mut out, ImageFormat::Png).unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn parses_the_comment_json() {
+        let comment = r#"{ "prompt": "a cat", "uc": "blurry", "steps": 28, "sampler": "k_euler",
+            "scale": 5.5, "seed": 1234, "width": 832, "height": 1216 }"#;
+        let meta = parse_comment(comment, None, Some("NovelAI Diffusion V4.5 4BDE2A90")).unwrap();
+        assert_eq!(meta.generator, "NovelAI");
+        assert_eq!(meta.prompt_text.as_deref(), Some("a cat"));
+        assert_eq!(meta.negative_prompt.as_deref(), Some("blurry"));
+        assert_eq!((meta.steps, meta.cfg_scale), (Some(28), Some(5.5)));
+        assert_eq!(meta.seed.as_deref(), Some("1234"));
+        assert_eq!((meta.width, meta.height), (Some(832), Some(1216)));
+        assert_eq!(meta.model.as_deref(), Some("NovelAI Diffusion V4.5"));
+        assert_eq!(meta.model_hash.as_deref(), Some("4BDE2A90"));
+    }
+
+    #[test]
+    fn v4_captions_and_description_fill_in_the_prompt() {
+        let v4 = r#"{ "v4_prompt": { "caption": { "base_caption": "a fox" } },
+            "v4_negative_prompt": { "caption": { "base_caption": "lowres" } } }"#;
+        let meta = parse_comment(v4, Some("ignored"), Some("Stable Diffusion XL")).unwrap();
+        assert_eq!(meta.prompt_text.as_deref(), Some("a fox"));
+        assert_eq!(meta.negative_prompt.as_deref(), Some("lowres"));
+        assert_eq!(meta.model.as_deref(), Some("Stable Diffusion XL"));
+        assert_eq!(meta.model_hash, None);
+
+        let meta = parse_comment("{}", Some("a cat"), None).unwrap();
+        assert_eq!(meta.prompt_text.as_deref(), Some("a cat"));
+        assert!(parse_comment("[1, 2]", None, None).is_none());
+    }
+
+    #[test]
+    fn reads_stealth_payloads() {
+        let text = b"a cat\nSteps: 20";
+        let png = stealth_png(b"stealth_pnginfo", text.len() as u32 * 8, text);
+        assert_eq!(read_stealth(&png).as_deref(), Some("a cat\nSteps: 20"));
+
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(br#"{"Description": "a fox"}"#).unwrap();
+        let payload = gzip.finish().unwrap();
+        let png = stealth_png(b"stealth_pngcomp", payload.len() as u32 * 8, &payload);
+        assert_eq!(read_stealth(&png).as_deref(), Some(r#"{"Description": "a fox"}"#));
+    }
+
+    #[test]
+    fn ignores_images_without_a_signature() {
+        assert!(read_stealth(&stealth_png(b"something_else!", 40, b"a cat")).is_none());
+        // A declared length larger than the image is rejected.
+        assert!(read_stealth(&stealth_png(b"stealth_pnginfo", 32 * 32 + 8, b"a cat")).is_none());
+        assert!(read_stealth(&stealth_png(b"stealth_pnginfo", 0, b"")).is_none());
+    }
+}
@@ -0,0 +1,94 @@
+use super::{a1111, comfyui, invokeai, novelai, ImageMetadata};
+use serde_json::Value;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Reads generation settings from a `.json` or `.txt` file saved next to an
+/// image, named either `image.txt` or `image.png.txt`.
+pub fn read(image_path: &Path) -> Option<ImageMetadata> {
+    sidecar_paths(image_path, "json")
+        .into_iter()
+        .find_map(|path| parse_json(&fs::read_to_string(path).ok()?))
+        .or_else(|| {
+            sidecar_paths(image_path, "txt")
+                .into_iter()
+                .find_map(|path| parse_text(&fs::read_to_string(path).ok()?))
+        })
+}
+
+fn sidecar_paths(image_path: &Path, ext: &str) -> [PathBuf; 2] {
+    let mut appended = OsString::from(image_path.as_os_str());
+    appended.push(".");
+    appended.push(ext);
+    [image_path.with_extension(ext), PathBuf::from(appended)]
+}
+
+/// A1111 style parameters, or else the whole file as the prompt.
+fn parse_text(text: &str) -> Option<ImageMetadata> {
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    if text.lines().any(|line| line.trim_start().starts_with("Steps:")) {
+        if let Some(meta) = a1111::parse_parameters(text) {
+            return Some(meta);
+        }
+    }
+    Some(ImageMetadata {
+        prompt_text: Some(text.to_string()),
+        ..Default::default()
+    })
+}
+
+/// Recognises the JSON formats of the tools we read from images (InvokeAI,
+/// NovelAI, ComfyUI graphs), then falls back to common key names.
+fn parse_json(text: &str) -> Option<ImageMetadata> {
+    let json: Value = serde_json::from_str(text).ok()?;
+    let object = json.as_object()?;
+
+    if object.contains_key("positive_prompt") {
+        return invokeai::parse(text);
+    }
+    if object.contains_key("uc") || object.contains_key("v4_prompt") {
+        return novelai::parse_comment(text, None, None);
+    }
+    if let Some(parameters) = object.get("parameters").and_then(Value::as_str) {
+        return a1111::parse_parameters(parameters);
+    }
+    if object.contains_key("nodes") && object.contains_key("links") {
+        return comfyui::parse(None, Some(text));
+    }
+    if !object.is_empty() && object.values().all(|node| node.get("class_type").is_some()) {
+        return comfyui::parse(Some(text), None);
+    }
+
+    let first = |keys: &[&str]| keys.iter().find_map(|key| object.get(*key)).filter(|v| !v.is_null());
+    let text = |keys: &[&str]| {
+        first(keys)
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(str::to_string)
+    };
+    let number = |keys: &[&str]| {
+        first(keys).and_then(|v| v.as_f64().or_else(|| v.as_str()?.trim().parse().ok()))
+    };
+
+    let meta = ImageMetadata {
+        prompt_text: text(&["prompt", "positive", "prompt_text"]),
+        negative_prompt: text(&["negative_prompt", "negative", "negativePrompt"]),
+        model: text(&["model", "model_name", "sd_model_name", "checkpoint"]),
+        steps: number(&["steps", "num_inference_steps"]).map(|v| v as i32),
+        sampler: text(&["sampler", "sampler_name", "scheduler"]),
+        cfg_scale: number(&["cfg_scale", "cfg", "guidance_scale", "scale"]),
+        seed: first(&["seed"]).map(|seed| match seed {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }),
+        width: number(&["width"]).map(|v| v as u32),
+        height: number(&["height"]).map(|v| v as u32),
+        ..Default::default()
+    };
+    meta.prompt_text.is_some().then_some(meta)
+}
@@ -0,0 +1,233 @@
+use crate::db::{ImageHash, Prompt};
+use crate::AppState;
+use image::imageops::FilterType;
+use image::DynamicImage;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::{Emitter, Manager};
+
+pub const PHASH_PROGRESS_EVENT: &str = "phash-progress";
+pub const PHASH_FINISHED_EVENT: &str = "phash-finished";
+
+/// A prompt with an image that looks like the one searched for.
+#[derive(Debug, Serialize)]
+pub struct SimilarPrompt {
+    pub prompt: Prompt,
+    /// The image of the prompt closest to the query.
+    pub image_id: i32,
+    /// Hamming distance between the hashes, from 0 (same picture) to 64.
+    pub distance: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackfillProgress {
+    pub job_id: String,
+    pub total: usize,
+    pub processed: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackfillReport {
+    pub job_id: String,
+    pub cancelled: bool,
+    pub hashed: usize,
+    /// Images whose file is missing or can't be decoded.
+    pub failed: usize,
+}
+
+/// 64-bit difference hash: each bit tells whether a pixel of the 9x8
+/// grayscale version is brighter than its right neighbour. Survives
+/// resizing, recompression and small edits.
+///
+/// Since the image is shrunk to 9x8 first, a thumbnail hashes within a bit
+/// or two of its full image, so thumbnails get no hash of their own: one
+/// per stored image covers both, and a thumbnail (or any other copy) given
+/// as the query still finds it.
+pub fn dhash(img: &DynamicImage) -> i64 {
+    let small = img
+        .grayscale()
+        .resize_exact(9, 8, FilterType::Triangle)
+        .to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash as i64
+}
+
+pub fn hamming_distance(a: i64, b: i64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// The prompts closest to any of `query`, as `(prompt_id, image_id,
+/// distance)`, nearest first. Each prompt appears once, with its closest
+/// image.
+pub fn nearest(
+    hashes: &[ImageHash],
+    query: &[i64],
+    exclude_prompt: Option<i32>,
+    max_distance: Option<u32>,
+    limit: usize,
+) -> Vec<(i32, i32, u32)> {
+    let mut best: HashMap<i32, (i32, u32)> = HashMap::new();
+    for image in hashes.iter().filter(|i| Some(i.prompt_id) != exclude_prompt) {
+        let Some(distance) = query.iter().map(|q| hamming_distance(*q, image.hash)).min() else {
+            continue;
+        };
+        if max_distance.is_some_and(|max| distance > max) {
+            continue;
+        }
+        let entry = best.entry(image.prompt_id).or_insert((image.image_id, distance));
+        if distance < entry.1 {
+            *entry = (image.image_id, distance);
+        }
+    }
+    let mut ranked: Vec<(i32, i32, u32)> = best
+        .into_iter()
+        .map(|(prompt_id, (image_id, distance))| (prompt_id, image_id, distance))
+        .collect();
+    ranked.sort_by_key(|(prompt_id, _, distance)| (*distance, *prompt_id));
+    ranked.truncate(limit);
+    ranked
+}
+
+/// Starts hashing the images stored before perceptual hashes existed and
+/// returns the job id. Progress is reported through `phash-progress` events
+/// and the result through `phash-finished`.
+pub fn spawn_backfill(app_handle: tauri::AppHandle) -> String {
+    let (job_id, cancelled) = app_handle.state::<AppState>().jobs.start();
+    let id = job_id.clone();
+    std::thread::spawn(move || {
+        let state = app_handle.state::<AppState>();
+        let report = backfill(&state, &id, &cancelled, |progress| {
+            let _ = app_handle.emit(PHASH_PROGRESS_EVENT, progress);
+        });
+        state.jobs.finish(&id);
+        let _ = app_handle.emit(PHASH_FINISHED_EVENT, report);
+    });
+    job_id
+}
+
+/// Computes the missing perceptual hashes, from the thumbnail when the full
+/// image is gone or can't be decoded. Images are decoded without holding the
+/// database lock; stops between images once `cancelled` is set.
+pub fn backfill(
+    state: &AppState,
+    job_id: &str,
+    cancelled: &AtomicBool,
+    mut on_progress: impl FnMut(BackfillProgress),
+) -> BackfillReport {
+    let pending = state.db.lock().ok().and_then(|db| {
+        let images = db.get_images_without_perceptual_hash().ok()?;
+        Some((images, db.get_data_dir().clone()))
+    });
+    let (images, data_dir) = pending.unwrap_or_default();
+
+    let mut hashed = 0;
+    let mut failed = 0;
+    for (i, (id, image_path, thumbnail_path)) in images.iter().enumerate() {
+        if cancelled.load(Ordering::Relaxed) {
+            break;
+        }
+        on_progress(BackfillProgress {
+            job_id: job_id.to_string(),
+            total: images.len(),
+            processed: i,
+        });
+        let saved = image::open(data_dir.join(image_path))
+            .or_else(|e| match thumbnail_path {
+                Some(thumbnail) if thumbnail != image_path => {
+                    image::open(data_dir.join(thumbnail))
+                }
+                _ => Err(e),
+            })
+            .map_err(|e| e.to_string())
+            .and_then(|img| {
+                let db = state.db.lock().map_err(|e| e.to_string())?;
+                db.set_perceptual_hash(*id, dhash(&img))
+                    .map_err(|e| e.to_string())
+            });
+        match saved {
+            Ok(()) => hashed += 1,
+            Err(_) => failed += 1,
+        }
+    }
+
+    BackfillReport {
+        job_id: job_id.to_string(),
+        cancelled: cancelled.load(Ordering::Relaxed),
+        hashed,
+        failed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    /// A picture with enough structure for the hash to mean something:
+    /// diagonal bands whose direction depends on `flip`.
+    fn picture(width: u32, height: u32, flip: bool) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            let x = if flip { width - 1 - x } else { x };
+            let v = ((x * 7 + y * 3) / 20 % 2 * 180 + x * 60 / width) as u8;
+            Rgb([v, v / 2, 255 - v])
+        }))
+    }
+
+    fn hash(image_id: i32, prompt_id: i32, hash: i64) -> ImageHash {
+        ImageHash { image_id, prompt_id, hash }
+    }
+
+    #[test]
+    fn thumbnails_hash_like_their_image() {
+        let full = picture(1200, 800, false);
+        let thumbnail = full.resize(300, 300, FilterType::Lanczos3);
+        assert!(hamming_distance(dhash(&full), dhash(&thumbnail)) <= 2);
+        let other = picture(1200, 800, true);
+        assert!(hamming_distance(dhash(&full), dhash(&other)) > 16);
+    }
+
+    #[test]
+    fn hamming_distance_counts_differing_bits() {
+        assert_eq!(hamming_distance(0, 0), 0);
+        assert_eq!(hamming_distance(0b1011, 0b0001), 2);
+        assert_eq!(hamming_distance(0, -1), 64);
+    }
+
+    #[test]
+    fn nearest_ranks_by_distance_then_prompt() {
+        let hashes = [hash(1, 10, 0b1111), hash(2, 20, 0b0001), hash(3, 30, 0b0011)];
+        let ranked = nearest(&hashes, &[0], None, None, 10);
+        assert_eq!(ranked, vec![(20, 2, 1), (30, 3, 2), (10, 1, 4)]);
+        assert_eq!(nearest(&hashes, &[0], None, None, 2), vec![(20, 2, 1), (30, 3, 2)]);
+        // Ties go to the lower prompt id.
+        let hashes = [hash(1, 20, 0b01), hash(2, 10, 0b10)];
+        assert_eq!(nearest(&hashes, &[0], None, None, 10), vec![(10, 2, 1), (20, 1, 1)]);
+    }
+
+    #[test]
+    fn nearest_keeps_the_closest_image_of_each_prompt() {
+        let hashes = [hash(1, 10, 0b111), hash(2, 10, 0b1), hash(3, 10, 0b11)];
+        assert_eq!(nearest(&hashes, &[0], None, None, 10), vec![(10, 2, 1)]);
+        // With several query hashes the best match of any of them counts.
+        let hashes = [hash(1, 10, 0b1111_0000), hash(2, 20, 0b1000_0001)];
+        let ranked = nearest(&hashes, &[0, 0b1111_0000], None, None, 10);
+        assert_eq!(ranked, vec![(10, 1, 0), (20, 2, 2)]);
+    }
+
+    #[test]
+    fn nearest_applies_max_distance_and_exclude_prompt() {
+        let hashes = [hash(1, 10, 0), hash(2, 20, 0b1), hash(3, 30, 0b111)];
+        assert_eq!(nearest(&hashes, &[0], None, Some(1), 10), vec![(10, 1, 0), (20, 2, 1)]);
+        assert_eq!(nearest(&hashes, &[0], Some(10), Some(1), 10), vec![(20, 2, 1)]);
+        assert!(nearest(&hashes, &[], None, None, 10).is_empty());
+    }
+}
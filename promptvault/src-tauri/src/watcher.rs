@@ -0,0 +1,328 @@
+use crate::db::WatchedFolder;
+use crate::import::{self, ImportOptions, ImportStatus};
+use crate::AppState;
+use notify::event::{EventKind, ModifyKind};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Manager};
+
+/// Sent with the `ImportFileResult` of every image the watcher imports, or
+/// fails to.
+pub const WATCH_IMPORT_EVENT: &str = "watch-import";
+
+/// How long a file has to stay untouched before it is imported. Generators
+/// write images in several steps, and copying takes a while too.
+const QUIET_PERIOD: Duration = Duration::from_millis(1500);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Watches the active watched folders and imports the images that appear
+/// in them.
+pub struct FolderWatcher {
+    watcher: RecommendedWatcher,
+    watched: HashMap<PathBuf, RecursiveMode>,
+}
+
+impl FolderWatcher {
+    /// Starts the watcher and the thread that imports what it reports.
+    pub fn start(app_handle: tauri::AppHandle) -> notify::Result<Self> {
+        let (tx, rx) = mpsc::channel();
+        let watcher = notify::recommended_watcher(tx)?;
+        std::thread::spawn(move || {
+            let mut pending = PendingFiles::default();
+            loop {
+                match rx.recv_timeout(POLL_INTERVAL) {
+                    Ok(Ok(event)) => pending.record(&event, Instant::now()),
+                    Ok(Err(_)) | Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                for path in pending.take_ready(Instant::now(), file_size) {
+                    import_new_file(&app_handle, &path);
+                }
+            }
+        });
+        Ok(Self {
+            watcher,
+            watched: HashMap::new(),
+        })
+    }
+
+    /// Watches exactly the active folders of `folders`. Folders that can't
+    /// be watched (e.g. missing drives) are skipped until the next sync.
+    pub fn sync(&mut self, folders: &[WatchedFolder]) {
+        let wanted: HashMap<PathBuf, RecursiveMode> = folders
+            .iter()
+            .filter(|folder| folder.is_active)
+            .map(|folder| {
+                let mode = if folder.recursive {
+                    RecursiveMode::Recursive
+                } else {
+                    RecursiveMode::NonRecursive
+                };
+                (PathBuf::from(&folder.path), mode)
+            })
+            .collect();
+
+        let stale: Vec<PathBuf> = self
+            .watched
+            .iter()
+            .filter(|(path, mode)| wanted.get(*path) != Some(mode))
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in stale {
+            let _ = self.watcher.unwatch(&path);
+            self.watched.remove(&path);
+        }
+        for (path, mode) in wanted {
+            if !self.watched.contains_key(&path) && self.watcher.watch(&path, mode).is_ok() {
+                self.watched.insert(path, mode);
+            }
+        }
+    }
+}
+
+/// Points the watcher of `state` at the folders currently configured.
+pub fn sync_watched_folders(state: &AppState) -> Result<(), String> {
+    let folders = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.get_watched_folders().map_err(|e| e.to_string())?
+    };
+    if let Ok(watcher) = state.watcher.lock().map_err(|e| e.to_string())?.as_mut() {
+        watcher.sync(&folders);
+    }
+    Ok(())
+}
+
+/// Fails with the reason when the platform watcher could not be started, so
+/// folders aren't set up to be watched when nothing would watch them.
+pub fn check_watcher(state: &AppState) -> Result<(), String> {
+    match &*state.watcher.lock().map_err(|e| e.to_string())? {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Watched folders are disabled: {}", e)),
+    }
+}
+
+/// Files seen by the watcher that are not imported yet. A file is ready
+/// once it had no events for `QUIET_PERIOD` and its size matches the size
+/// seen on the previous check.
+#[derive(Default)]
+pub struct PendingFiles {
+    files: HashMap<PathBuf, (Instant, Option<u64>)>,
+}
+
+impl PendingFiles {
+    pub fn record(&mut self, event: &Event, now: Instant) {
+        let relevant = matches!(
+            event.kind,
+            EventKind::Create(_)
+                | EventKind::Modify(ModifyKind::Data(_))
+                | EventKind::Modify(ModifyKind::Name(_))
+                | EventKind::Modify(ModifyKind::Any)
+        );
+        if !relevant {
+            return;
+        }
+        for path in event.paths.iter().filter(|path| import::is_image(path)) {
+            self.touch(path.clone(), now);
+        }
+    }
+
+    pub fn touch(&mut self, path: PathBuf, now: Instant) {
+        let size = self.files.get(&path).and_then(|(_, size)| *size);
+        self.files.insert(path, (now, size));
+    }
+
+    pub fn take_ready(
+        &mut self,
+        now: Instant,
+        size_of: impl Fn(&Path) -> Option<u64>,
+    ) -> Vec<PathBuf> {
+        let mut ready = Vec::new();
+        self.files.retain(|path, (last_event, last_size)| {
+            if now.duration_since(*last_event) < QUIET_PERIOD {
+                return true;
+            }
+            let size = size_of(path);
+            match size {
+                // Deleted or renamed away before it settled.
+                None => false,
+                Some(_) if size == *last_size => {
+                    ready.push(path.clone());
+                    false
+                }
+                Some(_) => {
+                    *last_event = now;
+                    *last_size = size;
+                    true
+                }
+            }
+        });
+        ready.sort();
+        ready
+    }
+}
+
+fn file_size(path: &Path) -> Option<u64> {
+    fs::metadata(path).ok().filter(|m| m.is_file()).map(|m| m.len())
+}
+
+/// Imports a settled file with the settings of the folder it belongs to.
+/// Both are compared canonicalized, as notify may report a path through a
+/// symlink or alias of the folder.
+fn import_new_file(app_handle: &tauri::AppHandle, path: &Path) {
+    let state = app_handle.state::<AppState>();
+    let path = canonical_path(path).unwrap_or_else(|_| path.to_path_buf());
+    let folders = match state.db.lock() {
+        Ok(db) => db.get_watched_folders().unwrap_or_default(),
+        Err(_) => return,
+    };
+    let folders: Vec<WatchedFolder> = folders
+        .into_iter()
+        .map(|mut folder| {
+            if let Ok(root) = canonical_path(Path::new(&folder.path)) {
+                folder.path = root.to_string_lossy().to_string();
+            }
+            folder
+        })
+        .collect();
+    let Some(folder) = folder_for(&folders, &path).cloned() else {
+        return;
+    };
+    let path = path.as_path();
+
+    let options = ImportOptions {
+        collection_id: folder.collection_id,
+        model: folder.model,
+        ..Default::default()
+    };
+    let result = import::import_file(&state, path, &options);
+    if result.status != ImportStatus::Skipped {
+        let _ = app_handle.emit(WATCH_IMPORT_EVENT, result);
+    }
+}
+
+/// `fs::canonicalize`, minus the `\\?\` prefix Windows puts on drive paths
+/// so stored folders stay readable.
+pub fn canonical_path(path: &Path) -> std::io::Result<PathBuf> {
+    let path = fs::canonicalize(path)?;
+    #[cfg(windows)]
+    if let Some(plain) = path.to_str().and_then(|p| p.strip_prefix(r"\\?\")) {
+        if !plain.starts_with("UNC\\") {
+            return Ok(PathBuf::from(plain));
+        }
+    }
+    Ok(path)
+}
+
+/// The active folder `path` lies in; the innermost one if folders nest.
+pub fn folder_for<'a>(folders: &'a [WatchedFolder], path: &Path) -> Option<&'a WatchedFolder> {
+    folders
+        .iter()
+        .filter(|folder| folder.is_active)
+        .filter(|folder| {
+            let root = Path::new(&folder.path);
+            if folder.recursive {
+                path.starts_with(root)
+            } else {
+                path.parent() == Some(root)
+            }
+        })
+        .max_by_key(|folder| folder.path.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::CreateKind;
+
+    fn folder(path: &str, recursive: bool, is_active: bool) -> WatchedFolder {
+        WatchedFolder {
+            id: 0,
+            path: path.to_string(),
+            collection_id: None,
+            model: None,
+            recursive,
+            is_active,
+            created_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn files_are_ready_once_quiet_and_the_same_size() {
+        let start = Instant::now();
+        let later = |ms| start + Duration::from_millis(ms);
+        let mut pending = PendingFiles::default();
+        let event = Event::new(EventKind::Create(CreateKind::File))
+            .add_path(PathBuf::from("/out/a.png"))
+            .add_path(PathBuf::from("/out/notes.txt"));
+        pending.record(&event, start);
+
+        // Still being written.
+        assert!(pending.take_ready(later(1000), |_| Some(10)).is_empty());
+        // Quiet, but the size is new: it gets another quiet period.
+        assert!(pending.take_ready(later(1500), |_| Some(10)).is_empty());
+        assert!(pending.take_ready(later(2000), |_| Some(10)).is_empty());
+        // It grew meanwhile, so it waits again.
+        assert!(pending.take_ready(later(3000), |_| Some(20)).is_empty());
+        assert_eq!(
+            pending.take_ready(later(4500), |_| Some(20)),
+            vec![PathBuf::from("/out/a.png")]
+        );
+        // Taken files are forgotten; the text file was never tracked.
+        assert!(pending.take_ready(later(9000), |_| Some(20)).is_empty());
+    }
+
+    #[test]
+    fn new_events_restart_the_quiet_period() {
+        let start = Instant::now();
+        let later = |ms| start + Duration::from_millis(ms);
+        let mut pending = PendingFiles::default();
+        pending.touch(PathBuf::from("/out/a.png"), start);
+        assert!(pending.take_ready(later(1500), |_| Some(10)).is_empty());
+        pending.touch(PathBuf::from("/out/a.png"), later(2500));
+        assert!(pending.take_ready(later(3500), |_| Some(10)).is_empty());
+        assert_eq!(pending.take_ready(later(4000), |_| Some(10)).len(), 1);
+    }
+
+    #[test]
+    fn files_deleted_before_they_settle_are_dropped() {
+        let start = Instant::now();
+        let mut pending = PendingFiles::default();
+        pending.touch(PathBuf::from("/out/a.png"), start);
+        let later = start + QUIET_PERIOD;
+        assert!(pending.take_ready(later, |_| None).is_empty());
+        // Gone for good, even if a file of that name shows up without events.
+        assert!(pending.take_ready(later + QUIET_PERIOD * 2, |_| Some(10)).is_empty());
+    }
+
+    #[test]
+    fn files_belong_to_the_innermost_active_folder() {
+        let folders = [
+            folder("/out", true, true),
+            folder("/out/portraits", true, true),
+            folder("/out/portraits/old", true, false),
+        ];
+        let path_of = |path: &str| folder_for(&folders, Path::new(path)).map(|f| f.path.as_str());
+        assert_eq!(path_of("/out/a.png"), Some("/out"));
+        assert_eq!(path_of("/out/portraits/x/a.png"), Some("/out/portraits"));
+        // Inactive folders don't claim files; their parent does.
+        assert_eq!(path_of("/out/portraits/old/a.png"), Some("/out/portraits"));
+        // Whole path components only.
+        assert_eq!(path_of("/output/a.png"), None);
+    }
+
+    #[test]
+    fn non_recursive_folders_only_match_direct_children() {
+        let folders = [folder("/out", false, true)];
+        assert!(folder_for(&folders, Path::new("/out/a.png")).is_some());
+        assert!(folder_for(&folders, Path::new("/out/day1/a.png")).is_none());
+
+        let folders = [folder("/out", true, true), folder("/out/day1", false, true)];
+        let path_of = |path: &str| folder_for(&folders, Path::new(path)).map(|f| f.path.as_str());
+        assert_eq!(path_of("/out/day1/a.png"), Some("/out/day1"));
+        assert_eq!(path_of("/out/day1/x/a.png"), Some("/out"));
+    }
+}
@@ -24,7 +24,6 @@ function App() {
 
   const filteredPrompts = prompts.filter(prompt => {
     if (selectedSection === 'favorites' && !prompt.is_favorite) return false
-    if (selectedSection === 'collection' && selectedCollectionId && prompt.collection_id !== selectedCollectionId) return false
     if (searchQuery) {
       const query = searchQuery.toLowerCase()
       return (
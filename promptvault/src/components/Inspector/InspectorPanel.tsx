@@ -187,6 +187,23 @@ export function InspectorPanel({ prompt, onClose, onDelete }: InspectorPanelProp
           </div>
         </div>
 
+        {/* Content Credentials */}
+        {prompt.provenance && (
+          <div className="space-y-3">
+            <h4 className="text-xs font-semibold text-text-muted uppercase tracking-wider">
+              Content Credentials
+            </h4>
+            <div className="grid grid-cols-2 gap-3">
+              <InfoItem
+                label="Generator"
+                value={prompt.provenance.generator ?? prompt.model}
+              />
+              <InfoItem label="Created" value={prompt.created_at} />
+            </div>
+            <p className="text-xs text-text-muted break-all">{prompt.provenance.manifest}</p>
+          </div>
+        )}
+
         {/* Tags */}
         {prompt.tags.length > 0 && (
           <div className="space-y-2">
@@ -32,7 +32,7 @@ export function usePrompts() {
       setLoading(true);
       const result = await invoke<PromptRaw[]>('get_all_prompts', {
         filter: modelFilter || null,
-        collection_id: collectionId || null,
+        collectionId: collectionId || null,
       });
       setPrompts(result.map(normalizePrompt));
     } catch (error) {
@@ -68,11 +68,11 @@ export function usePrompts() {
           tags: data.tags || [],
           collection_id: data.collection_id,
         },
-        image_data: data.image_data || null,
+        imageData: data.image_data || null,
         filename: data.filename || null,
-        image_path: data.image_path || null,
-        image_base64: data.image_base64 || null,
-        has_image: data.has_image ?? null,
+        imagePath: data.image_path || null,
+        imageBase64: data.image_base64 || null,
+        hasImage: data.has_image ?? null,
       });
       const normalized = normalizePrompt(result);
       setPrompts(prev => [normalized, ...prev]);
@@ -4,8 +4,11 @@ export interface Prompt {
   prompt_text: string;
   negative_prompt: string;
   model: string;
+  model_id: number | null;
+  model_hash: string | null;
   image_path: string | null;
   thumbnail_path: string | null;
+  images: PromptImage[];
   dimensions: string;
   steps: number | null;
   sampler: string | null;
@@ -13,9 +16,68 @@ export interface Prompt {
   seed: string | null;
   tags: string[];
   is_favorite: boolean;
-  collection_id: number | null;
+  rating: number;
+  has_workflow: boolean;
+  provenance: Provenance | null;
+  collection_ids: number[];
   created_at: string;
   updated_at: string;
+  deleted_at: string | null;
+}
+
+export interface PromptImage {
+  id: number;
+  prompt_id: number;
+  image_path: string;
+  thumbnail_path: string | null;
+  position: number;
+  is_cover: boolean;
+  seed: string | null;
+  width: number | null;
+  height: number | null;
+  content_hash: string | null;
+  perceptual_hash: number | null;
+  created_at: string;
+}
+
+export interface DuplicateGroup {
+  content_hash: string;
+  prompt_ids: number[];
+}
+
+export interface DedupeReport {
+  hashed: number;
+  missing: number;
+  removed_files: number;
+  bytes_freed: number;
+  duplicates: DuplicateGroup[];
+}
+
+export interface SimilarPrompt {
+  prompt: Prompt;
+  image_id: number;
+  distance: number;
+}
+
+export interface BackfillProgress {
+  job_id: string;
+  total: number;
+  processed: number;
+}
+
+export interface BackfillReport {
+  job_id: string;
+  cancelled: boolean;
+  hashed: number;
+  failed: number;
+}
+
+export interface ImageUpload {
+  image_data?: number[];
+  filename?: string;
+  image_path?: string;
+  image_base64?: string;
+  seed?: string;
 }
 
 export interface NewPrompt {
@@ -23,6 +85,7 @@ export interface NewPrompt {
   prompt_text: string;
   negative_prompt?: string;
   model: string;
+  model_hash?: string;
   dimensions?: string;
   steps?: number;
   sampler?: string;
@@ -30,6 +93,19 @@ export interface NewPrompt {
   seed?: string;
   tags?: string[];
   collection_id?: number;
+  collection_ids?: number[];
+  workflow_json?: string;
+  created_at?: string;
+  provenance?: Provenance;
+}
+
+/** C2PA content credentials embedded in an imported image. */
+export interface Provenance {
+  manifest: string;
+  /** Readable name of the tool, e.g. `Adobe Firefly`. */
+  generator?: string;
+  claim: Record<string, unknown>;
+  actions?: Record<string, unknown>[];
 }
 
 export interface UpdatePrompt {
@@ -44,7 +120,73 @@ export interface UpdatePrompt {
   seed?: string;
   tags?: string[];
   is_favorite?: boolean;
-  collection_id?: number;
+  rating?: number;
+  collection_ids?: number[];
+}
+
+export type SortKey = 'created' | 'updated' | 'title' | 'rating' | 'model' | 'random';
+
+export type SortDirection = 'asc' | 'desc';
+
+export interface PageCursor {
+  key: string | number | null;
+  id: number;
+}
+
+export interface PageRequest {
+  sort?: SortKey;
+  direction?: SortDirection;
+  seed?: number;
+  cursor?: PageCursor | null;
+  limit?: number;
+}
+
+export interface PromptSummary {
+  id: number;
+  title: string;
+  model: string;
+  thumbnail_path: string | null;
+  image_count: number;
+  dimensions: string;
+  tags: string[];
+  is_favorite: boolean;
+  rating: number;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface PromptPage {
+  items: PromptSummary[];
+  next_cursor: PageCursor | null;
+  total: number;
+}
+
+export interface PromptRevision {
+  id: number;
+  prompt_id: number;
+  revision: number;
+  title: string;
+  prompt_text: string;
+  negative_prompt: string;
+  model: string;
+  dimensions: string;
+  steps: number | null;
+  sampler: string | null;
+  cfg_scale: number | null;
+  seed: string | null;
+  tags: string[];
+  created_at: string;
+}
+
+export interface DiffSpan {
+  op: 'equal' | 'insert' | 'delete';
+  text: string;
+}
+
+export interface RevisionDiff {
+  from: PromptRevision;
+  to: PromptRevision;
+  fields: { field: string; spans: DiffSpan[] }[];
 }
 
 export interface Collection {
@@ -52,7 +194,49 @@ export interface Collection {
   name: string;
   icon: string;
   color: string;
+  parent_id: number | null;
+  sort_order: number;
   prompt_count?: number;
+  total_prompt_count: number;
+  /** Search query of a smart collection; null when filled by hand. */
+  query: string | null;
+  children: Collection[];
+}
+
+export interface Tag {
+  id: number;
+  name: string;
+  prompt_count: number;
+}
+
+export type TagMatch = 'all' | 'any' | 'none';
+
+export interface PromptQuery {
+  text?: string;
+  models?: string[];
+  tags?: string[];
+  tag_match?: TagMatch;
+  collection_id?: number | null;
+  include_descendants?: boolean;
+  favorites_only?: boolean;
+  has_image?: boolean;
+  created_from?: string;
+  created_to?: string;
+  updated_from?: string;
+  updated_to?: string;
+  samplers?: string[];
+  steps_min?: number;
+  steps_max?: number;
+  cfg_min?: number;
+  cfg_max?: number;
+  dimensions?: string[];
+}
+
+/** A malformed search query; offsets are in characters. */
+export interface QueryError {
+  message: string;
+  start: number;
+  end: number;
 }
 
 export interface Model {
@@ -60,7 +244,88 @@ export interface Model {
   name: string;
   short_name: string;
   is_active: boolean;
+  prompt_count: number;
 }
 
 export type ViewMode = 'gallery' | 'detail';
 export type SidebarSection = 'all' | 'favorites' | 'history' | 'collection';
+
+export interface BackupFile {
+  path: string;
+  size: number;
+}
+
+export interface BackupManifest {
+  format: string;
+  format_version: number;
+  app_version: string;
+  schema_version: number;
+  created_at: string;
+  prompt_count: number;
+  files: BackupFile[];
+}
+
+export interface BackupInfo {
+  path: string;
+  size: number;
+  manifest: BackupManifest;
+}
+
+export interface BackupSchedule {
+  /** 0 turns automatic backups off. */
+  interval_hours: number;
+  retention: number;
+  last_backup_at: string | null;
+}
+
+export interface ImportOptions {
+  recursive?: boolean;
+  tags?: string[];
+  collection_id?: number;
+  /** Used when an image's metadata names no model. */
+  model?: string;
+}
+
+export type ImportStatus = 'imported' | 'skipped' | 'failed';
+
+export interface ImportFileResult {
+  path: string;
+  status: ImportStatus;
+  prompt_id: number | null;
+  message: string | null;
+}
+
+/** Payload of the `import-progress` event. */
+export interface ImportProgress {
+  job_id: string;
+  total: number;
+  processed: number;
+  current: string;
+}
+
+/** Payload of the `import-finished` event. */
+export interface ImportReport {
+  job_id: string;
+  cancelled: boolean;
+  imported: number;
+  skipped: number;
+  failed: number;
+  files: ImportFileResult[];
+}
+
+export interface WatchedFolder {
+  id: number;
+  path: string;
+  collection_id: number | null;
+  model: string | null;
+  recursive: boolean;
+  is_active: boolean;
+  created_at: string;
+}
+
+export interface WatchedFolderSettings {
+  collection_id?: number | null;
+  model?: string | null;
+  recursive?: boolean;
+  is_active?: boolean;
+}